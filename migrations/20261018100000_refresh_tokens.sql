@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE refresh_tokens(
+    id VARCHAR(100) PRIMARY KEY,
+    user_id VARCHAR(100) NOT NULL,
+    family_id VARCHAR(100) NOT NULL,
+    hashed_token TEXT NOT NULL,
+    expires_at DATETIME NOT NULL,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    INDEX (family_id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
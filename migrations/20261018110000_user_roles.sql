@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN role VARCHAR(30) NOT NULL DEFAULT 'doctor';
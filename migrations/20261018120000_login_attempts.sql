@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE login_attempts(
+    target_type VARCHAR(20) NOT NULL,
+    target VARCHAR(100) NOT NULL,
+    failed_count INT NOT NULL DEFAULT 0,
+    last_failed_at DATETIME,
+    locked_until DATETIME,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (target_type, target)
+);
@@ -0,0 +1,21 @@
+-- Add migration script here
+CREATE TABLE service_accounts(
+    id VARCHAR(100) PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    role VARCHAR(30) NOT NULL,
+    created_by VARCHAR(100) NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE api_keys(
+    id VARCHAR(100) PRIMARY KEY,
+    service_account_id VARCHAR(100) NOT NULL,
+    hashed_key VARCHAR(255) NOT NULL,
+    scopes TEXT NOT NULL,
+    expires_at DATETIME,
+    last_used_at DATETIME,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    INDEX (service_account_id),
+    FOREIGN KEY (service_account_id) REFERENCES service_accounts(id)
+);
@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE sessions(
+    id VARCHAR(100) PRIMARY KEY,
+    user_id VARCHAR(100) NOT NULL,
+    ip VARCHAR(100),
+    user_agent VARCHAR(255),
+    created_at DATETIME NOT NULL,
+    last_seen_at DATETIME NOT NULL,
+    revoked_at DATETIME,
+    INDEX (user_id),
+    INDEX (revoked_at),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
@@ -0,0 +1,16 @@
+-- Add migration script here
+CREATE TABLE invitations(
+    id VARCHAR(100) PRIMARY KEY,
+    role VARCHAR(30) NOT NULL,
+    hashed_code VARCHAR(255) NOT NULL,
+    expires_at DATETIME NOT NULL,
+    approval_required BOOLEAN NOT NULL DEFAULT FALSE,
+    created_by VARCHAR(100) NOT NULL,
+    used_by VARCHAR(100),
+    used_at DATETIME,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (created_by) REFERENCES users(id)
+);
+
+ALTER TABLE users ADD COLUMN approved BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,16 @@
+-- Add migration script here
+CREATE TABLE emergency_accesses(
+    id VARCHAR(100) PRIMARY KEY,
+    user_id VARCHAR(100) NOT NULL,
+    patient_code VARCHAR(100) NOT NULL,
+    reason TEXT NOT NULL,
+    granted_at DATETIME NOT NULL,
+    expires_at DATETIME NOT NULL,
+    acknowledged_by VARCHAR(100),
+    acknowledged_at DATETIME,
+    INDEX (user_id,patient_code),
+    INDEX (acknowledged_at),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (patient_code) REFERENCES patients(code),
+    FOREIGN KEY (acknowledged_by) REFERENCES users(id)
+);
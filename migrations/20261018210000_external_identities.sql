@@ -0,0 +1,17 @@
+-- Add migration script here
+CREATE TABLE external_identities(
+    id VARCHAR(100) PRIMARY KEY,
+    user_id VARCHAR(100) NOT NULL,
+    issuer VARCHAR(255) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    created_at DATETIME NOT NULL,
+    UNIQUE (issuer,subject),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE TABLE oidc_login_states(
+    id VARCHAR(100) PRIMARY KEY,
+    nonce VARCHAR(100) NOT NULL,
+    code_verifier VARCHAR(100) NOT NULL,
+    expires_at DATETIME NOT NULL
+);
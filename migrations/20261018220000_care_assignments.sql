@@ -0,0 +1,17 @@
+-- Add migration script here
+ALTER TABLE doctor_in_charges
+    ADD COLUMN id VARCHAR(100) NULL FIRST,
+    ADD COLUMN care_role VARCHAR(30) NOT NULL DEFAULT 'attending',
+    ADD COLUMN starts_at DATETIME NULL,
+    ADD COLUMN ends_at DATETIME NULL,
+    ADD COLUMN reason TEXT NULL;
+
+UPDATE doctor_in_charges SET id=UUID(),starts_at=created_at;
+
+-- the same user can be assigned to the same patient again after the assignment ended.
+ALTER TABLE doctor_in_charges ADD INDEX (user_id,patient_code);
+ALTER TABLE doctor_in_charges
+    DROP PRIMARY KEY,
+    MODIFY COLUMN id VARCHAR(100) NOT NULL,
+    MODIFY COLUMN starts_at DATETIME NOT NULL,
+    ADD PRIMARY KEY(id);
@@ -0,0 +1,23 @@
+-- Add migration script here
+CREATE TABLE delegations(
+    id VARCHAR(100) PRIMARY KEY,
+    delegator_id VARCHAR(100) NOT NULL,
+    delegate_id VARCHAR(100) NOT NULL,
+    all_patients BOOLEAN NOT NULL,
+    starts_at DATETIME NOT NULL,
+    ends_at DATETIME NOT NULL,
+    revoked_at DATETIME,
+    created_at DATETIME NOT NULL,
+    INDEX (delegate_id,ends_at),
+    INDEX (delegator_id),
+    FOREIGN KEY (delegator_id) REFERENCES users(id),
+    FOREIGN KEY (delegate_id) REFERENCES users(id)
+);
+
+CREATE TABLE delegation_patients(
+    delegation_id VARCHAR(100) NOT NULL,
+    patient_code VARCHAR(100) NOT NULL,
+    PRIMARY KEY(delegation_id,patient_code),
+    FOREIGN KEY (delegation_id) REFERENCES delegations(id),
+    FOREIGN KEY (patient_code) REFERENCES patients(code)
+);
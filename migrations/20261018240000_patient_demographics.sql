@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE patients
+    ADD COLUMN date_of_birth DATE,
+    ADD COLUMN sex VARCHAR(10),
+    ADD COLUMN phone VARCHAR(20),
+    ADD COLUMN address VARCHAR(255),
+    ADD COLUMN postal_code VARCHAR(8),
+    ADD COLUMN blood_type VARCHAR(3);
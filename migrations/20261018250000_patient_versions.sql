@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE patients ADD COLUMN version INT UNSIGNED NOT NULL DEFAULT 1;
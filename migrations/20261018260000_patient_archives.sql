@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE patients
+    ADD COLUMN archived_at DATETIME,
+    ADD COLUMN archived_by VARCHAR(100),
+    ADD COLUMN archive_reason VARCHAR(500);
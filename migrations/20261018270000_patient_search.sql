@@ -0,0 +1,10 @@
+-- Add migration script here
+-- search_name and search_kana are name and name_kana normalised for search by the application.
+ALTER TABLE patients
+    ADD COLUMN name_kana VARCHAR(60),
+    ADD COLUMN search_name VARCHAR(100) NOT NULL DEFAULT '',
+    ADD COLUMN search_kana VARCHAR(120),
+    ADD INDEX (search_name),
+    ADD INDEX (search_kana),
+    ADD INDEX (date_of_birth);
+-- existing patients are left with empty search_name, and filled by the application on start.
@@ -1,3 +1,19 @@
+drop table delegation_patients;
+drop table delegations;
+drop table oidc_login_states;
+drop table external_identities;
+drop table emergency_accesses;
+drop table invitations;
+drop table sessions;
+drop table api_keys;
+drop table service_accounts;
+drop table password_histories;
+drop table outbox_notifications;
+drop table password_reset_tokens;
+drop table user_recovery_codes;
+drop table user_two_factors;
+drop table login_attempts;
+drop table refresh_tokens;
 drop table doctor_in_charges;
 drop table medical_examinations;
 drop table patients;
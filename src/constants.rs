@@ -1,3 +1,24 @@
 pub mod env_key {
     pub const DATABASE_URL: &str = "DATABASE_URL";
+    pub const JWT_KEYRING_FILE: &str = "JWT_KEYRING_FILE";
+    pub const JWT_SECRET: &str = "JWT_SECRET";
+    pub const JWT_KEY_ID: &str = "JWT_KEY_ID";
+    pub const JWT_PREVIOUS_SECRETS: &str = "JWT_PREVIOUS_SECRETS";
+    pub const LOGIN_MAX_FAILURES: &str = "LOGIN_MAX_FAILURES";
+    pub const LOGIN_IP_MAX_FAILURES: &str = "LOGIN_IP_MAX_FAILURES";
+    pub const LOGIN_LOCKOUT_SECONDS: &str = "LOGIN_LOCKOUT_SECONDS";
+    pub const LOGIN_FAILURE_WINDOW_SECONDS: &str = "LOGIN_FAILURE_WINDOW_SECONDS";
+    pub const NOTIFIER: &str = "NOTIFIER";
+    pub const NOTIFIER_FILE: &str = "NOTIFIER_FILE";
+    pub const PASSWORD_MIN_LENGTH: &str = "PASSWORD_MIN_LENGTH";
+    pub const PASSWORD_MIN_CHARACTER_CLASSES: &str = "PASSWORD_MIN_CHARACTER_CLASSES";
+    pub const PASSWORD_COMMON_LIST_FILE: &str = "PASSWORD_COMMON_LIST_FILE";
+    pub const PASSWORD_HISTORY_SIZE: &str = "PASSWORD_HISTORY_SIZE";
+    pub const OIDC_ISSUER: &str = "OIDC_ISSUER";
+    pub const OIDC_CLIENT_ID: &str = "OIDC_CLIENT_ID";
+    pub const OIDC_CLIENT_SECRET: &str = "OIDC_CLIENT_SECRET";
+    pub const OIDC_REDIRECT_URI: &str = "OIDC_REDIRECT_URI";
+    pub const OIDC_AUTO_PROVISION: &str = "OIDC_AUTO_PROVISION";
+    pub const OIDC_PROVISION_ROLE: &str = "OIDC_PROVISION_ROLE";
+    pub const OIDC_PROVISION_APPROVAL_REQUIRED: &str = "OIDC_PROVISION_APPROVAL_REQUIRED";
 }
@@ -0,0 +1,217 @@
+use crate::domain::user::{DoctorInChargeRepository, User, UserRepository};
+use crate::utils::errors::MyError;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use strum::{Display, EnumString};
+use ulid::Ulid;
+
+const REASON_LIMIT: usize = 500;
+
+/// role of the user in care team of the patient.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum CareRole {
+    #[default]
+    Attending,
+    Consulting,
+    Nurse,
+    Resident,
+}
+
+/// the user is in charge of the patient from starts_at until ends_at.
+/// ended assignments are kept as care history of the patient.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CareAssignment {
+    pub id: String,
+    pub user_id: String,
+    pub patient_code: String,
+    pub care_role: CareRole,
+    pub starts_at: DateTime<Local>,
+    /// None while the assignment is open ended.
+    pub ends_at: Option<DateTime<Local>>,
+    pub reason: Option<String>,
+}
+
+impl CareAssignment {
+    /// starts now if starts_at is None. ends_at must be after starts_at.
+    pub fn new(
+        user_id: String,
+        patient_code: String,
+        care_role: CareRole,
+        starts_at: Option<DateTime<Local>>,
+        ends_at: Option<DateTime<Local>>,
+        reason: Option<String>,
+    ) -> Result<Self, MyError> {
+        let starts_at = starts_at.unwrap_or_else(Local::now);
+        if ends_at.map_or(false, |ends_at| ends_at <= starts_at) {
+            return Err(MyError::BadRequest(
+                json!({"error":"ends_at must be after starts_at"}),
+            ));
+        }
+        let reason = reason
+            .map(|reason| reason.trim().to_string())
+            .filter(|reason| !reason.is_empty());
+        if reason
+            .as_ref()
+            .map_or(false, |reason| reason.chars().count() > REASON_LIMIT)
+        {
+            return Err(MyError::BadRequest(
+                json!({"error":"reason must be less than 500 letters"}),
+            ));
+        }
+        Ok(Self {
+            id: Ulid::new().to_string(),
+            user_id,
+            patient_code,
+            care_role,
+            starts_at,
+            ends_at,
+            reason,
+        })
+    }
+
+    pub fn from(
+        id: String,
+        user_id: String,
+        patient_code: String,
+        care_role: CareRole,
+        starts_at: DateTime<Local>,
+        ends_at: Option<DateTime<Local>>,
+        reason: Option<String>,
+    ) -> Self {
+        Self {
+            id,
+            user_id,
+            patient_code,
+            care_role,
+            starts_at,
+            ends_at,
+            reason,
+        }
+    }
+
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        self.starts_at <= now && self.ends_at.map_or(true, |ends_at| now < ends_at)
+    }
+
+    /// whether periods of the two share any moment.
+    pub fn overlaps(&self, other: &CareAssignment) -> bool {
+        self.ends_at
+            .map_or(true, |ends_at| other.starts_at < ends_at)
+            && other
+                .ends_at
+                .map_or(true, |ends_at| self.starts_at < ends_at)
+    }
+}
+
+/// users in care team of the patient with the assignment, newest first.
+/// ended assignments are included only if include_history.
+pub async fn fetch_care_team<U: UserRepository + Sync, D: DoctorInChargeRepository + Sync>(
+    user_repository: &U,
+    doctor_in_charge_repository: &D,
+    patient_code: &String,
+    include_history: bool,
+    now: DateTime<Local>,
+) -> Result<Vec<(User, CareAssignment)>, MyError> {
+    let users = user_repository.fetch_all().await?;
+    let care_team = doctor_in_charge_repository
+        .fetch_by_patient_code(patient_code)
+        .await?
+        .into_iter()
+        .filter(|care_assignment| {
+            include_history
+                || care_assignment
+                    .ends_at
+                    .map_or(true, |ends_at| now < ends_at)
+        })
+        .filter_map(|care_assignment| {
+            users
+                .iter()
+                .find(|user| user.id == care_assignment.user_id)
+                .map(|user| (user.clone(), care_assignment))
+        })
+        .collect::<Vec<(User, CareAssignment)>>();
+    Ok(care_team)
+}
+
+/// the user has another CareAssignment to the patient during the period.
+pub fn already_in_charge(patient_code: &String) -> MyError {
+    MyError::Conflict(json!({
+        "error": format!("already in charge of patient code={}.", patient_code)
+    }))
+}
+
+#[cfg(test)]
+
+mod tests {
+
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_care_assignment_new() {
+        let now = Local::now();
+        let care_assignment = CareAssignment::new(
+            "test_id".to_string(),
+            "a".to_string(),
+            CareRole::Resident,
+            None,
+            Some(now + Duration::days(7)),
+            Some(" rotation ".to_string()),
+        )
+        .unwrap();
+        assert_eq!(care_assignment.reason, Some("rotation".to_string()));
+        assert!(care_assignment.is_active(Local::now()));
+        assert!(!care_assignment.is_active(now + Duration::days(7)));
+        assert!(!care_assignment.is_active(now - Duration::days(1)));
+
+        let err = CareAssignment::new(
+            "test_id".to_string(),
+            "a".to_string(),
+            CareRole::Attending,
+            Some(now),
+            Some(now),
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"ends_at must be after starts_at"}))
+        );
+        let err = CareAssignment::new(
+            "test_id".to_string(),
+            "a".to_string(),
+            CareRole::Attending,
+            None,
+            None,
+            Some("x".repeat(REASON_LIMIT + 1)),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"reason must be less than 500 letters"}))
+        );
+    }
+
+    #[test]
+    fn test_care_assignment_overlaps() {
+        let now = Local::now();
+        let assignment = |starts_at: i64, ends_at: Option<i64>| {
+            CareAssignment::from(
+                "test_care_assignment_id".to_string(),
+                "test_id".to_string(),
+                "a".to_string(),
+                CareRole::Attending,
+                now + Duration::days(starts_at),
+                ends_at.map(|ends_at| now + Duration::days(ends_at)),
+                None,
+            )
+        };
+        assert!(assignment(0, Some(7)).overlaps(&assignment(6, None)));
+        assert!(assignment(0, None).overlaps(&assignment(30, Some(31))));
+        assert!(!assignment(0, Some(7)).overlaps(&assignment(7, Some(14))));
+        assert!(!assignment(7, None).overlaps(&assignment(0, Some(7))));
+    }
+}
@@ -0,0 +1,222 @@
+use crate::domain::patient::{Patient, PatientRepository};
+use crate::domain::user::DoctorInChargeRepository;
+use crate::utils::errors::MyError;
+use chrono::{DateTime, Duration, Local};
+use serde_json::json;
+use ulid::Ulid;
+
+const DELEGATION_MAX_DAYS: i64 = 90;
+
+/// access to patients of the delegator lent to the delegate for a while, e.g. during leave.
+/// the delegate sees only patients the delegator is in charge of at the moment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delegation {
+    pub id: String,
+    pub delegator_id: String,
+    pub delegate_id: String,
+    /// None if every patient of the delegator is delegated.
+    pub patient_codes: Option<Vec<String>>,
+    pub starts_at: DateTime<Local>,
+    pub ends_at: DateTime<Local>,
+    pub revoked_at: Option<DateTime<Local>>,
+    pub created_at: DateTime<Local>,
+}
+
+impl Delegation {
+    /// starts now if starts_at is None. lasts 90 days at most.
+    pub fn new(
+        delegator_id: String,
+        delegate_id: String,
+        patient_codes: Option<Vec<String>>,
+        starts_at: Option<DateTime<Local>>,
+        ends_at: DateTime<Local>,
+    ) -> Result<Self, MyError> {
+        if delegator_id == delegate_id {
+            return Err(MyError::BadRequest(
+                json!({"error":"can not delegate to yourself"}),
+            ));
+        }
+        let now = Local::now();
+        let starts_at = starts_at.unwrap_or(now);
+        if ends_at <= starts_at {
+            return Err(MyError::BadRequest(
+                json!({"error":"ends_at must be after starts_at"}),
+            ));
+        }
+        if ends_at - starts_at > Duration::days(DELEGATION_MAX_DAYS) {
+            return Err(MyError::BadRequest(
+                json!({"error":"delegation must be within 90 days"}),
+            ));
+        }
+        let patient_codes = match patient_codes {
+            Some(mut patient_codes) => {
+                patient_codes.sort();
+                patient_codes.dedup();
+                if patient_codes.is_empty() {
+                    return Err(MyError::BadRequest(
+                        json!({"error":"patient_codes must not be empty"}),
+                    ));
+                }
+                Some(patient_codes)
+            }
+            None => None,
+        };
+        Ok(Self {
+            id: Ulid::new().to_string(),
+            delegator_id,
+            delegate_id,
+            patient_codes,
+            starts_at,
+            ends_at,
+            revoked_at: None,
+            created_at: now,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn from(
+        id: String,
+        delegator_id: String,
+        delegate_id: String,
+        patient_codes: Option<Vec<String>>,
+        starts_at: DateTime<Local>,
+        ends_at: DateTime<Local>,
+        revoked_at: Option<DateTime<Local>>,
+        created_at: DateTime<Local>,
+    ) -> Self {
+        Self {
+            id,
+            delegator_id,
+            delegate_id,
+            patient_codes,
+            starts_at,
+            ends_at,
+            revoked_at,
+            created_at,
+        }
+    }
+
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        !self.is_revoked() && self.starts_at <= now && now < self.ends_at
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    /// whether the patient is delegated, as long as the delegator is in charge of it.
+    pub fn covers(&self, patient_code: &String) -> bool {
+        self.patient_codes
+            .as_ref()
+            .map_or(true, |patient_codes| patient_codes.contains(patient_code))
+    }
+}
+
+/// patients the user is in charge of at now, followed by patients seen through delegations active at now.
+/// delegation is None for patients of the user's own. own assignment wins if both give access.
+/// archived patients are left out unless include_archived.
+pub async fn fetch_patients_in_charge<
+    P: PatientRepository + Sync,
+    D: DoctorInChargeRepository + Sync,
+>(
+    patient_repository: &P,
+    doctor_in_charge_repository: &D,
+    user_id: &String,
+    now: DateTime<Local>,
+    include_archived: bool,
+) -> Result<Vec<(Patient, Option<Delegation>)>, MyError> {
+    let mut patients = patient_repository
+        .fetch_by_user_id(user_id, now)
+        .await?
+        .into_iter()
+        .map(|patient| (patient, None))
+        .collect::<Vec<(Patient, Option<Delegation>)>>();
+    let delegations = doctor_in_charge_repository
+        .fetch_active_delegations(user_id, now)
+        .await?;
+    for delegation in delegations {
+        for patient in patient_repository
+            .fetch_by_user_id(&delegation.delegator_id, now)
+            .await?
+        {
+            if delegation.covers(&patient.code)
+                && !patients.iter().any(|(seen, _)| seen.code == patient.code)
+            {
+                patients.push((patient, Some(delegation.clone())));
+            }
+        }
+    }
+    if !include_archived {
+        patients.retain(|(patient, _)| !patient.is_archived());
+    }
+    Ok(patients)
+}
+
+#[cfg(test)]
+
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_delegation_new() {
+        let now = Local::now();
+        let delegation = Delegation::new(
+            "test_user_id_1".to_string(),
+            "test_id".to_string(),
+            Some(vec!["b".to_string(), "a".to_string(), "b".to_string()]),
+            None,
+            now + Duration::days(7),
+        )
+        .unwrap();
+        assert_eq!(
+            delegation.patient_codes,
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+        assert!(delegation.is_active(Local::now()));
+        assert!(!delegation.is_active(now + Duration::days(7)));
+        assert!(delegation.covers(&"a".to_string()));
+        assert!(!delegation.covers(&"c".to_string()));
+    }
+
+    #[test]
+    fn test_delegation_new_failed() {
+        let now = Local::now();
+        for (delegate_id, patient_codes, ends_at, expected) in [
+            (
+                "test_user_id_1",
+                None,
+                now + Duration::days(7),
+                "can not delegate to yourself",
+            ),
+            (
+                "test_id",
+                None,
+                now - Duration::days(1),
+                "ends_at must be after starts_at",
+            ),
+            (
+                "test_id",
+                None,
+                now + Duration::days(DELEGATION_MAX_DAYS + 1),
+                "delegation must be within 90 days",
+            ),
+            (
+                "test_id",
+                Some(vec![]),
+                now + Duration::days(7),
+                "patient_codes must not be empty",
+            ),
+        ] {
+            let err = Delegation::new(
+                "test_user_id_1".to_string(),
+                delegate_id.to_string(),
+                patient_codes,
+                Some(now),
+                ends_at,
+            )
+            .unwrap_err();
+            assert_eq!(err, MyError::BadRequest(json!({ "error": expected })));
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::utils::errors::MyError;
+use chrono::{DateTime, Duration, Local};
+use serde_json::json;
+use ulid::Ulid;
+
+const EMERGENCY_ACCESS_EXPIRED_WITHIN_MINUTES: i64 = 60;
+const REASON_LIMIT: usize = 500;
+
+/// break the glass. access to a patient the user is not in charge of, granted for a while in emergency.
+/// every access is also an audit entry which administrator reviews and acknowledges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmergencyAccess {
+    pub id: String,
+    pub user_id: String,
+    pub patient_code: String,
+    pub reason: String,
+    pub granted_at: DateTime<Local>,
+    pub expires_at: DateTime<Local>,
+    /// administrator who reviewed the access.
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<DateTime<Local>>,
+}
+
+impl EmergencyAccess {
+    /// grant access for 60 minutes. reason is mandatory.
+    pub fn new(user_id: String, patient_code: String, reason: String) -> Result<Self, MyError> {
+        let reason = reason.trim().to_string();
+        if reason.is_empty() || reason.chars().count() > REASON_LIMIT {
+            return Err(MyError::BadRequest(
+                json!({"error":"reason must be 1 to 500 letters"}),
+            ));
+        }
+        let granted_at = Local::now();
+        Ok(Self {
+            id: Ulid::new().to_string(),
+            user_id,
+            patient_code,
+            reason,
+            granted_at,
+            expires_at: granted_at + Duration::minutes(EMERGENCY_ACCESS_EXPIRED_WITHIN_MINUTES),
+            acknowledged_by: None,
+            acknowledged_at: None,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn from(
+        id: String,
+        user_id: String,
+        patient_code: String,
+        reason: String,
+        granted_at: DateTime<Local>,
+        expires_at: DateTime<Local>,
+        acknowledged_by: Option<String>,
+        acknowledged_at: Option<DateTime<Local>>,
+    ) -> Self {
+        Self {
+            id,
+            user_id,
+            patient_code,
+            reason,
+            granted_at,
+            expires_at,
+            acknowledged_by,
+            acknowledged_at,
+        }
+    }
+
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        self.granted_at <= now && now < self.expires_at
+    }
+
+    pub fn is_acknowledged(&self) -> bool {
+        self.acknowledged_at.is_some()
+    }
+}
+
+#[cfg(test)]
+
+mod tests {
+
+    use super::*;
+    #[test]
+    fn test_emergency_access_new() {
+        let emergency_access = EmergencyAccess::new(
+            "test_id".to_string(),
+            "a".to_string(),
+            " cardiac arrest in ER ".to_string(),
+        )
+        .unwrap();
+        assert_eq!(emergency_access.reason, "cardiac arrest in ER");
+        assert!(emergency_access.is_active(Local::now()));
+        assert!(!emergency_access.is_active(Local::now() + Duration::minutes(60)));
+        assert!(!emergency_access.is_acknowledged());
+    }
+
+    #[test]
+    fn test_emergency_access_new_failed() {
+        for reason in [" ".to_string(), "x".repeat(REASON_LIMIT + 1)] {
+            let err =
+                EmergencyAccess::new("test_id".to_string(), "a".to_string(), reason).unwrap_err();
+            assert_eq!(
+                err,
+                MyError::BadRequest(json!({"error":"reason must be 1 to 500 letters"}))
+            );
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::domain::user::Role;
+use crate::utils::errors::MyError;
+use crate::utils::hash::{digest_token, verify_digest};
+use crate::utils::totp::random_bytes;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Local};
+use data_encoding::BASE32_NOPAD;
+use serde_json::json;
+use ulid::Ulid;
+
+const INVITATION_EXPIRED_WITHIN_DAYS: i64 = 7;
+const INVITATION_MAX_EXPIRED_WITHIN_DAYS: i64 = 30;
+const RAW_CODE_SEPARATOR: char = '.';
+const INVITATION_SECRET_BYTES: usize = 20;
+
+/// single use code to sign up. created by administrator and bound to the role of new user.
+/// if approval is required, user signed up with it can not sign in until administrator approves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invitation {
+    pub id: String,
+    pub role: Role,
+    pub hashed_code: String,
+    pub expires_at: DateTime<Local>,
+    pub approval_required: bool,
+    /// user who created the invitation.
+    pub created_by: String,
+    /// user who signed up with the invitation.
+    pub used_by: Option<String>,
+    pub revoked: bool,
+}
+
+impl Invitation {
+    /// issue new invitation. expires in 7 days if expires_in_days is None.
+    /// return invitation and raw code for invitee. raw code is not stored, so it is shown only once.
+    pub fn new(
+        role: Role,
+        expires_in_days: Option<i64>,
+        approval_required: bool,
+        created_by: String,
+    ) -> Result<(Self, String), MyError> {
+        let expires_in_days = expires_in_days.unwrap_or(INVITATION_EXPIRED_WITHIN_DAYS);
+        if expires_in_days <= 0 || expires_in_days > INVITATION_MAX_EXPIRED_WITHIN_DAYS {
+            return Err(MyError::BadRequest(
+                json!({"error":"expires_in_days must be 1 to 30"}),
+            ));
+        }
+        let id = Ulid::new().to_string();
+        let secret = BASE32_NOPAD
+            .encode(&random_bytes(INVITATION_SECRET_BYTES))
+            .to_lowercase();
+        let raw_code = format!("{}{}{}", id, RAW_CODE_SEPARATOR, secret);
+        let invitation = Self {
+            id,
+            role,
+            hashed_code: digest_token(&secret),
+            expires_at: Local::now() + Duration::days(expires_in_days),
+            approval_required,
+            created_by,
+            used_by: None,
+            revoked: false,
+        };
+        Ok((invitation, raw_code))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn from(
+        id: String,
+        role: Role,
+        hashed_code: String,
+        expires_at: DateTime<Local>,
+        approval_required: bool,
+        created_by: String,
+        used_by: Option<String>,
+        revoked: bool,
+    ) -> Self {
+        Self {
+            id,
+            role,
+            hashed_code,
+            expires_at,
+            approval_required,
+            created_by,
+            used_by,
+            revoked,
+        }
+    }
+
+    pub fn matches(&self, secret: &str) -> bool {
+        verify_digest(secret, &self.hashed_code)
+    }
+
+    /// invitation can sign up only one user before it expires or is revoked.
+    pub fn is_usable(&self, now: DateTime<Local>) -> bool {
+        self.used_by.is_none() && !self.revoked && now < self.expires_at
+    }
+}
+
+/// split raw code into (id, secret).
+pub fn split_raw_code(raw_code: &str) -> Result<(String, String), MyError> {
+    match raw_code.trim().split_once(RAW_CODE_SEPARATOR) {
+        Some((id, secret)) if !id.is_empty() && !secret.is_empty() => {
+            Ok((id.to_string(), secret.to_string()))
+        }
+        _ => Err(MyError::BadRequest(
+            json!({"error":"invitation code is invalid"}),
+        )),
+    }
+}
+
+#[async_trait]
+pub trait InvitationRepository {
+    /// store Invitation to DB.
+    async fn save(&self, invitation: &Invitation) -> Result<(), MyError>;
+    /// find one Invitation from DB by primary key. if not exist,None.
+    async fn fetch_one(&self, id: &String) -> Result<Option<Invitation>, MyError>;
+    /// every Invitation including used, revoked and expired ones.
+    async fn fetch_all(&self) -> Result<Vec<Invitation>, MyError>;
+    async fn revoke(&self, id: &String) -> Result<(), MyError>;
+}
+
+#[cfg(test)]
+
+mod tests {
+
+    use super::*;
+    #[test]
+    fn test_invitation_new() {
+        let (invitation, raw_code) =
+            Invitation::new(Role::Nurse, None, true, "test_id".to_string()).unwrap();
+        assert_eq!(invitation.role, Role::Nurse);
+        assert!(invitation.approval_required);
+        assert!(invitation.is_usable(Local::now()));
+        assert!(!invitation.is_usable(Local::now() + Duration::days(7)));
+
+        let (id, secret) = split_raw_code(&raw_code).unwrap();
+        assert_eq!(id, invitation.id);
+        assert!(invitation.matches(&secret));
+        assert!(!invitation.matches("wrong_secret"));
+    }
+
+    #[test]
+    fn test_invitation_new_failed() {
+        for expires_in_days in [0, 31] {
+            let err = Invitation::new(
+                Role::Doctor,
+                Some(expires_in_days),
+                false,
+                "test_id".to_string(),
+            )
+            .unwrap_err();
+            assert_eq!(
+                err,
+                MyError::BadRequest(json!({"error":"expires_in_days must be 1 to 30"}))
+            );
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crate::constants::env_key;
+use crate::utils::errors::MyError;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::env;
+use strum::{Display, EnumString};
+
+const DEFAULT_MAX_FAILURES: u32 = 5;
+const DEFAULT_IP_MAX_FAILURES: u32 = 20;
+const DEFAULT_LOCKOUT_SECONDS: i64 = 15 * 60;
+const DEFAULT_FAILURE_WINDOW_SECONDS: i64 = 15 * 60;
+const BASE_DELAY_SECONDS: i64 = 1;
+const MAX_DELAY_SECONDS: i64 = 30;
+
+/// what failed sign in attempts are counted for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AttemptTarget {
+    /// target is user code.
+    Account,
+    /// target is client ip address.
+    Ip,
+}
+
+/// thresholds of brute force protection.
+/// LOGIN_MAX_FAILURES, LOGIN_IP_MAX_FAILURES, LOGIN_LOCKOUT_SECONDS and LOGIN_FAILURE_WINDOW_SECONDS env are optional.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockoutPolicy {
+    /// failures of one account until it is locked.
+    pub max_failures: u32,
+    /// failures from one ip until it is locked.
+    pub ip_max_failures: u32,
+    pub lockout_duration: Duration,
+    /// failures older than this are forgotten.
+    pub failure_window: Duration,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            max_failures: DEFAULT_MAX_FAILURES,
+            ip_max_failures: DEFAULT_IP_MAX_FAILURES,
+            lockout_duration: Duration::seconds(DEFAULT_LOCKOUT_SECONDS),
+            failure_window: Duration::seconds(DEFAULT_FAILURE_WINDOW_SECONDS),
+            base_delay: Duration::seconds(BASE_DELAY_SECONDS),
+            max_delay: Duration::seconds(MAX_DELAY_SECONDS),
+        }
+    }
+}
+
+impl LockoutPolicy {
+    /// read env. unset or invalid value falls back to default.
+    pub fn load() -> Self {
+        let default = Self::default();
+        let var = |key: &str| {
+            env::var(key)
+                .ok()
+                .and_then(|value| value.parse::<i64>().ok())
+        };
+        Self {
+            max_failures: var(env_key::LOGIN_MAX_FAILURES)
+                .map(|value| value as u32)
+                .unwrap_or(default.max_failures),
+            ip_max_failures: var(env_key::LOGIN_IP_MAX_FAILURES)
+                .map(|value| value as u32)
+                .unwrap_or(default.ip_max_failures),
+            lockout_duration: var(env_key::LOGIN_LOCKOUT_SECONDS)
+                .map(Duration::seconds)
+                .unwrap_or(default.lockout_duration),
+            failure_window: var(env_key::LOGIN_FAILURE_WINDOW_SECONDS)
+                .map(Duration::seconds)
+                .unwrap_or(default.failure_window),
+            ..default
+        }
+    }
+
+    pub fn max_failures_of(&self, target_type: AttemptTarget) -> u32 {
+        match target_type {
+            AttemptTarget::Account => self.max_failures,
+            AttemptTarget::Ip => self.ip_max_failures,
+        }
+    }
+}
+
+/// failed sign in attempts of one account or one ip.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginAttempt {
+    pub target_type: AttemptTarget,
+    pub target: String,
+    pub failed_count: u32,
+    pub last_failed_at: Option<DateTime<Local>>,
+    pub locked_until: Option<DateTime<Local>>,
+}
+
+impl LoginAttempt {
+    /// no failure yet.
+    pub fn new(target_type: AttemptTarget, target: String) -> Self {
+        Self {
+            target_type,
+            target,
+            failed_count: 0,
+            last_failed_at: None,
+            locked_until: None,
+        }
+    }
+
+    pub fn from(
+        target_type: AttemptTarget,
+        target: String,
+        failed_count: u32,
+        last_failed_at: Option<DateTime<Local>>,
+        locked_until: Option<DateTime<Local>>,
+    ) -> Self {
+        Self {
+            target_type,
+            target,
+            failed_count,
+            last_failed_at,
+            locked_until,
+        }
+    }
+
+    pub fn is_locked(&self, now: DateTime<Local>) -> bool {
+        matches!(self.locked_until, Some(locked_until) if now < locked_until)
+    }
+
+    /// wait before next attempt. doubles on every failure up to max_delay.
+    pub fn delay(&self, policy: &LockoutPolicy) -> Duration {
+        if self.failed_count == 0 {
+            return Duration::zero();
+        }
+        let exponent = (self.failed_count - 1).min(16);
+        let delay = policy.base_delay * 2_i32.pow(exponent);
+        delay.min(policy.max_delay)
+    }
+
+    /// time until next attempt is accepted. None if it is accepted now.
+    pub fn retry_after(&self, now: DateTime<Local>, policy: &LockoutPolicy) -> Option<Duration> {
+        if self.is_locked(now) {
+            return self.locked_until.map(|locked_until| locked_until - now);
+        }
+        let last_failed_at = self.last_failed_at?;
+        let next_attempt_at = last_failed_at + self.delay(policy);
+        if now < next_attempt_at {
+            Some(next_attempt_at - now)
+        } else {
+            None
+        }
+    }
+
+    /// count up failure. lock when failures reach the threshold of policy.
+    /// failures before expired lockout or out of failure_window start over.
+    /// LoginAttemptRepository::record_failure does the same in DB.
+    pub fn record_failure(&mut self, now: DateTime<Local>, policy: &LockoutPolicy) {
+        let lock_expired = self
+            .locked_until
+            .map_or(false, |locked_until| locked_until <= now);
+        let out_of_window = self.last_failed_at.map_or(false, |last_failed_at| {
+            last_failed_at + policy.failure_window <= now
+        });
+        if lock_expired || out_of_window {
+            self.failed_count = 0;
+            self.locked_until = None;
+        }
+        self.failed_count += 1;
+        self.last_failed_at = Some(now);
+        if self.failed_count >= policy.max_failures_of(self.target_type) {
+            self.locked_until = Some(now + policy.lockout_duration);
+        }
+    }
+}
+
+#[async_trait]
+pub trait LoginAttemptRepository {
+    /// find LoginAttempt of the target. if not exist,LoginAttempt without failure.
+    async fn fetch(
+        &self,
+        target_type: &AttemptTarget,
+        target: &String,
+    ) -> Result<LoginAttempt, MyError>;
+    /// count up failure of the target in DB like LoginAttempt::record_failure, and return the result.
+    /// counted by DB, so that failures at the same time are not lost.
+    async fn record_failure(
+        &self,
+        target_type: &AttemptTarget,
+        target: &String,
+        now: DateTime<Local>,
+        policy: &LockoutPolicy,
+    ) -> Result<LoginAttempt, MyError>;
+    /// forget failures of the target. used on successful sign in and unlock.
+    async fn delete(&self, target_type: &AttemptTarget, target: &String) -> Result<(), MyError>;
+}
+
+#[cfg(test)]
+
+mod tests {
+
+    use super::*;
+    #[test]
+    fn test_record_failure_locks_at_threshold() {
+        let policy = LockoutPolicy::default();
+        let now = Local::now();
+        let mut login_attempt = LoginAttempt::new(AttemptTarget::Account, "test_code".to_string());
+        for _ in 0..policy.max_failures - 1 {
+            login_attempt.record_failure(now, &policy);
+        }
+        assert!(!login_attempt.is_locked(now));
+        login_attempt.record_failure(now, &policy);
+        assert!(login_attempt.is_locked(now));
+        assert!(!login_attempt.is_locked(now + policy.lockout_duration));
+
+        // ip has its own threshold.
+        let mut login_attempt = LoginAttempt::new(AttemptTarget::Ip, "192.0.2.1".to_string());
+        for _ in 0..policy.max_failures {
+            login_attempt.record_failure(now, &policy);
+        }
+        assert!(!login_attempt.is_locked(now));
+    }
+
+    #[test]
+    fn test_delay_is_progressive() {
+        let policy = LockoutPolicy::default();
+        let now = Local::now();
+        let mut login_attempt = LoginAttempt::new(AttemptTarget::Account, "test_code".to_string());
+        assert_eq!(login_attempt.retry_after(now, &policy), None);
+
+        login_attempt.record_failure(now, &policy);
+        assert_eq!(login_attempt.delay(&policy), Duration::seconds(1));
+        login_attempt.record_failure(now, &policy);
+        assert_eq!(login_attempt.delay(&policy), Duration::seconds(2));
+        login_attempt.record_failure(now, &policy);
+        assert_eq!(login_attempt.delay(&policy), Duration::seconds(4));
+        assert_eq!(
+            login_attempt.retry_after(now, &policy),
+            Some(Duration::seconds(4))
+        );
+        assert_eq!(
+            login_attempt.retry_after(now + Duration::seconds(4), &policy),
+            None
+        );
+
+        login_attempt.failed_count = 100;
+        assert_eq!(login_attempt.delay(&policy), policy.max_delay);
+    }
+
+    #[test]
+    fn test_record_failure_starts_over() {
+        let policy = LockoutPolicy::default();
+        let now = Local::now();
+        let mut login_attempt = LoginAttempt::new(AttemptTarget::Account, "test_code".to_string());
+        for _ in 0..policy.max_failures {
+            login_attempt.record_failure(now, &policy);
+        }
+        let after_lockout = now + policy.lockout_duration;
+        login_attempt.record_failure(after_lockout, &policy);
+        assert_eq!(login_attempt.failed_count, 1);
+        assert!(!login_attempt.is_locked(after_lockout));
+
+        let out_of_window = after_lockout + policy.failure_window;
+        login_attempt.record_failure(out_of_window, &policy);
+        assert_eq!(login_attempt.failed_count, 1);
+    }
+}
@@ -1,3 +1,15 @@
+pub mod care_assignment;
+pub mod delegation;
+pub mod emergency_access;
+pub mod invitation;
+pub mod login_attempt;
 pub mod medical_examination;
+pub mod notification;
+pub mod oidc;
+pub mod password_policy;
+pub mod password_reset;
 pub mod patient;
+pub mod refresh_token;
+pub mod service_account;
+pub mod session;
 pub mod user;
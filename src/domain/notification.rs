@@ -0,0 +1,33 @@
+use crate::utils::errors::MyError;
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+/// message to user. e.g. password reset token.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Notification {
+    pub id: String,
+    pub user_id: String,
+    pub subject: String,
+    pub body: String,
+    pub created_at: DateTime<Local>,
+}
+
+impl Notification {
+    pub fn new(user_id: String, subject: String, body: String) -> Self {
+        Self {
+            id: Ulid::new().to_string(),
+            user_id,
+            subject,
+            body,
+            created_at: Local::now(),
+        }
+    }
+}
+
+/// delivers Notification to user. implementation is chosen by NOTIFIER env.
+#[async_trait]
+pub trait Notifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), MyError>;
+}
@@ -0,0 +1,226 @@
+use crate::constants::env_key;
+use crate::domain::user::Role;
+use crate::utils::errors::MyError;
+use crate::utils::totp::random_bytes;
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Local};
+use ring::digest::{digest, SHA256};
+use serde::Deserialize;
+use std::env;
+use std::str::FromStr;
+use ulid::Ulid;
+
+const LOGIN_STATE_EXPIRED_WITHIN_MINUTES: i64 = 10;
+const NONCE_BYTES: usize = 16;
+const CODE_VERIFIER_BYTES: usize = 32;
+const DEFAULT_PROVISION_ROLE: Role = Role::Receptionist;
+
+/// settings of external identity provider which supports OpenID Connect.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcConfig {
+    /// issuer url. discovery document is at {issuer}/.well-known/openid-configuration.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// our callback url registered to the identity provider.
+    pub redirect_uri: String,
+    /// create user on first login if no user is linked to the subject.
+    pub auto_provision: bool,
+    /// role of provisioned user.
+    pub provision_role: Role,
+    /// provisioned user can not sign in until administrator approves.
+    pub provision_approval_required: bool,
+}
+
+impl OidcConfig {
+    /// read env. None if OIDC_ISSUER is not set, then oidc login is disabled.
+    /// OIDC_CLIENT_ID, OIDC_CLIENT_SECRET and OIDC_REDIRECT_URI are required with OIDC_ISSUER.
+    /// OIDC_AUTO_PROVISION(default false), OIDC_PROVISION_ROLE(default receptionist)
+    /// and OIDC_PROVISION_APPROVAL_REQUIRED(default true) are optional.
+    pub fn load() -> Option<Self> {
+        let issuer = env::var(env_key::OIDC_ISSUER).ok()?;
+        let required = |key: &str| {
+            env::var(key).unwrap_or_else(|_| panic!("{} must be set with OIDC_ISSUER", key))
+        };
+        let flag = |key: &str, default: bool| {
+            env::var(key)
+                .ok()
+                .and_then(|value| value.parse::<bool>().ok())
+                .unwrap_or(default)
+        };
+        Some(Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id: required(env_key::OIDC_CLIENT_ID),
+            client_secret: required(env_key::OIDC_CLIENT_SECRET),
+            redirect_uri: required(env_key::OIDC_REDIRECT_URI),
+            auto_provision: flag(env_key::OIDC_AUTO_PROVISION, false),
+            provision_role: env::var(env_key::OIDC_PROVISION_ROLE)
+                .ok()
+                .map(|role| Role::from_str(&role).expect("OIDC_PROVISION_ROLE is invalid"))
+                .unwrap_or(DEFAULT_PROVISION_ROLE),
+            provision_approval_required: flag(env_key::OIDC_PROVISION_APPROVAL_REQUIRED, true),
+        })
+    }
+}
+
+/// account of the identity provider linked to our user. issuer and subject are unique.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalIdentity {
+    pub id: String,
+    pub user_id: String,
+    pub issuer: String,
+    /// sub claim of id token.
+    pub subject: String,
+    pub created_at: DateTime<Local>,
+}
+
+impl ExternalIdentity {
+    pub fn new(user_id: String, issuer: String, subject: String) -> Self {
+        Self {
+            id: Ulid::new().to_string(),
+            user_id,
+            issuer,
+            subject,
+            created_at: Local::now(),
+        }
+    }
+
+    pub fn from(
+        id: String,
+        user_id: String,
+        issuer: String,
+        subject: String,
+        created_at: DateTime<Local>,
+    ) -> Self {
+        Self {
+            id,
+            user_id,
+            issuer,
+            subject,
+            created_at,
+        }
+    }
+}
+
+/// one login started by browser. id is sent as state parameter and kept in cookie,
+/// so that callback is accepted only once and only in the browser which started it.
+/// nonce binds id token to the login, and code_verifier is PKCE secret.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcLoginState {
+    pub id: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub expires_at: DateTime<Local>,
+}
+
+impl OidcLoginState {
+    /// valid for 10 minutes.
+    pub fn new() -> Self {
+        Self {
+            id: Ulid::new().to_string(),
+            nonce: URL_SAFE_NO_PAD.encode(random_bytes(NONCE_BYTES)),
+            code_verifier: URL_SAFE_NO_PAD.encode(random_bytes(CODE_VERIFIER_BYTES)),
+            expires_at: Local::now() + Duration::minutes(LOGIN_STATE_EXPIRED_WITHIN_MINUTES),
+        }
+    }
+
+    pub fn from(
+        id: String,
+        nonce: String,
+        code_verifier: String,
+        expires_at: DateTime<Local>,
+    ) -> Self {
+        Self {
+            id,
+            nonce,
+            code_verifier,
+            expires_at,
+        }
+    }
+
+    pub fn is_expired(&self, now: DateTime<Local>) -> bool {
+        self.expires_at <= now
+    }
+
+    /// PKCE S256 challenge sent with authorization request.
+    pub fn code_challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(digest(&SHA256, self.code_verifier.as_bytes()))
+    }
+}
+
+impl Default for OidcLoginState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// claims of id token. signature, iss, aud and exp are verified by IdentityProvider.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub name: Option<String>,
+    pub preferred_username: Option<String>,
+}
+
+/// OpenID Connect provider. authorization code flow with PKCE.
+#[async_trait]
+pub trait IdentityProvider {
+    /// url of authorization endpoint to redirect browser to.
+    async fn authorization_url(&self, login_state: &OidcLoginState) -> Result<String, MyError>;
+    /// exchange authorization code at token endpoint and verify id token with JWKS.
+    /// if id token is invalid,Unauthorized.
+    async fn exchange_code(
+        &self,
+        code: &str,
+        login_state: &OidcLoginState,
+    ) -> Result<IdTokenClaims, MyError>;
+}
+
+#[async_trait]
+pub trait ExternalIdentityRepository {
+    /// store ExternalIdentity to DB.
+    async fn save(&self, external_identity: &ExternalIdentity) -> Result<(), MyError>;
+    /// find ExternalIdentity by issuer and subject. if not exist,None.
+    async fn fetch_by_subject(
+        &self,
+        issuer: &String,
+        subject: &String,
+    ) -> Result<Option<ExternalIdentity>, MyError>;
+    /// store OidcLoginState to DB.
+    async fn save_login_state(&self, login_state: &OidcLoginState) -> Result<(), MyError>;
+    /// find and delete OidcLoginState, so that it is used only once. if not exist,None.
+    async fn take_login_state(&self, id: &String) -> Result<Option<OidcLoginState>, MyError>;
+}
+
+#[cfg(test)]
+
+mod tests {
+
+    use super::*;
+    #[test]
+    fn test_login_state_code_challenge() {
+        // example of RFC 7636 appendix B.
+        let login_state = OidcLoginState::from(
+            "test_state".to_string(),
+            "test_nonce".to_string(),
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string(),
+            Local::now(),
+        );
+        assert_eq!(
+            login_state.code_challenge(),
+            "E9Melhoa2OwvFUmMTJjCN6Xd3aaIIOWgnoicgcvIv0M"
+        );
+    }
+
+    #[test]
+    fn test_login_state_new() {
+        let login_state = OidcLoginState::new();
+        assert!(!login_state.is_expired(Local::now()));
+        assert!(login_state.is_expired(Local::now() + Duration::minutes(10)));
+        assert_ne!(login_state.nonce, OidcLoginState::new().nonce);
+    }
+}
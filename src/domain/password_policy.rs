@@ -0,0 +1,328 @@
+use crate::constants::env_key;
+use crate::utils::errors::MyError;
+use crate::utils::hash::verify;
+use async_trait::async_trait;
+use serde_json::json;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::sync::Arc;
+
+const DEFAULT_MIN_LENGTH: usize = 8;
+const DEFAULT_MAX_LENGTH: usize = 128;
+const DEFAULT_MIN_CHARACTER_CLASSES: usize = 2;
+const DEFAULT_HISTORY_SIZE: usize = 5;
+/// name or code shorter than this is not checked. it matches too many passwords.
+const MIN_PERSONAL_INFO_LENGTH: usize = 3;
+/// always rejected even without PASSWORD_COMMON_LIST_FILE.
+const BUILT_IN_COMMON_PASSWORDS: &[&str] = &[
+    "12345678",
+    "123456789",
+    "1234567890",
+    "password",
+    "password1",
+    "password123",
+    "passw0rd",
+    "qwerty123",
+    "qwertyuiop",
+    "iloveyou",
+    "11111111",
+    "00000000",
+    "abc12345",
+    "abcd1234",
+    "1q2w3e4r",
+    "letmein1",
+    "welcome1",
+    "admin123",
+    "sunshine",
+    "football",
+];
+
+/// user information which password must not contain.
+pub struct PasswordContext<'a> {
+    pub name: &'a str,
+    pub code: &'a str,
+}
+
+/// one rule of PasswordPolicy. return violation message if password breaks the rule.
+pub trait PasswordRule: Send + Sync {
+    fn violation(&self, password: &str, context: &PasswordContext) -> Option<String>;
+}
+
+/// length in characters, not bytes.
+pub struct LengthRule {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl PasswordRule for LengthRule {
+    fn violation(&self, password: &str, _context: &PasswordContext) -> Option<String> {
+        let length = password.chars().count();
+        if length < self.min {
+            Some(format!("password must be at least {} characters", self.min))
+        } else if length > self.max {
+            Some(format!("password must be at most {} characters", self.max))
+        } else {
+            None
+        }
+    }
+}
+
+/// number of classes used in password. classes are lowercase, uppercase, digit and others.
+pub struct CharacterClassRule {
+    pub min_classes: usize,
+}
+
+impl PasswordRule for CharacterClassRule {
+    fn violation(&self, password: &str, _context: &PasswordContext) -> Option<String> {
+        let classes = [
+            password.chars().any(|c| c.is_lowercase()),
+            password.chars().any(|c| c.is_uppercase()),
+            password.chars().any(|c| c.is_numeric()),
+            password
+                .chars()
+                .any(|c| !c.is_lowercase() && !c.is_uppercase() && !c.is_numeric()),
+        ];
+        if classes.iter().filter(|used| **used).count() < self.min_classes {
+            Some(format!(
+                "password must contain at least {} of lowercase, uppercase, digit and symbol",
+                self.min_classes
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// reject passwords of breached or common password list. case is ignored.
+pub struct CommonPasswordRule {
+    passwords: HashSet<String>,
+}
+
+impl CommonPasswordRule {
+    pub fn new(passwords: Vec<String>) -> Self {
+        Self {
+            passwords: passwords
+                .into_iter()
+                .map(|password| password.trim().to_lowercase())
+                .filter(|password| !password.is_empty())
+                .collect(),
+        }
+    }
+
+    /// built in list and, if given, one password per line file.
+    pub fn load(file: Option<String>) -> Self {
+        let mut passwords = BUILT_IN_COMMON_PASSWORDS
+            .iter()
+            .map(|password| password.to_string())
+            .collect::<Vec<String>>();
+        if let Some(file) = file {
+            let list =
+                fs::read_to_string(&file).expect("PASSWORD_COMMON_LIST_FILE can not be read");
+            passwords.extend(list.lines().map(|line| line.to_string()));
+        }
+        Self::new(passwords)
+    }
+}
+
+impl PasswordRule for CommonPasswordRule {
+    fn violation(&self, password: &str, _context: &PasswordContext) -> Option<String> {
+        if self.passwords.contains(&password.to_lowercase()) {
+            Some("password is too common".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// reject passwords containing user name or code. case is ignored.
+pub struct PersonalInfoRule;
+
+impl PasswordRule for PersonalInfoRule {
+    fn violation(&self, password: &str, context: &PasswordContext) -> Option<String> {
+        let password = password.to_lowercase();
+        let contains = |info: &str| {
+            info.chars().count() >= MIN_PERSONAL_INFO_LENGTH
+                && password.contains(&info.to_lowercase())
+        };
+        if contains(context.name) || contains(context.code) {
+            Some("password must not contain user name or code".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// rules every new password must satisfy. history_size recent passwords can not be reused.
+#[derive(Clone)]
+pub struct PasswordPolicy {
+    rules: Vec<Arc<dyn PasswordRule>>,
+    pub history_size: usize,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self::new(
+            vec![
+                Arc::new(LengthRule {
+                    min: DEFAULT_MIN_LENGTH,
+                    max: DEFAULT_MAX_LENGTH,
+                }),
+                Arc::new(CharacterClassRule {
+                    min_classes: DEFAULT_MIN_CHARACTER_CLASSES,
+                }),
+                Arc::new(CommonPasswordRule::load(None)),
+                Arc::new(PersonalInfoRule),
+            ],
+            DEFAULT_HISTORY_SIZE,
+        )
+    }
+}
+
+impl PasswordPolicy {
+    pub fn new(rules: Vec<Arc<dyn PasswordRule>>, history_size: usize) -> Self {
+        Self {
+            rules,
+            history_size,
+        }
+    }
+
+    /// read env. PASSWORD_MIN_LENGTH, PASSWORD_MIN_CHARACTER_CLASSES, PASSWORD_COMMON_LIST_FILE
+    /// and PASSWORD_HISTORY_SIZE are optional.
+    pub fn load() -> Self {
+        let var = |key: &str| {
+            env::var(key)
+                .ok()
+                .and_then(|value| value.parse::<usize>().ok())
+        };
+        Self::new(
+            vec![
+                Arc::new(LengthRule {
+                    min: var(env_key::PASSWORD_MIN_LENGTH).unwrap_or(DEFAULT_MIN_LENGTH),
+                    max: DEFAULT_MAX_LENGTH,
+                }),
+                Arc::new(CharacterClassRule {
+                    min_classes: var(env_key::PASSWORD_MIN_CHARACTER_CLASSES)
+                        .unwrap_or(DEFAULT_MIN_CHARACTER_CLASSES),
+                }),
+                Arc::new(CommonPasswordRule::load(
+                    env::var(env_key::PASSWORD_COMMON_LIST_FILE).ok(),
+                )),
+                Arc::new(PersonalInfoRule),
+            ],
+            var(env_key::PASSWORD_HISTORY_SIZE).unwrap_or(DEFAULT_HISTORY_SIZE),
+        )
+    }
+
+    /// every violation of rules.
+    pub fn violations(&self, password: &str, context: &PasswordContext) -> Vec<String> {
+        self.rules
+            .iter()
+            .filter_map(|rule| rule.violation(password, context))
+            .collect()
+    }
+}
+
+#[async_trait]
+pub trait PasswordHistoryRepository {
+    /// store hashed password the user has set.
+    async fn save(&self, user_id: &String, hashed_password: &String) -> Result<(), MyError>;
+    /// hashed passwords of the user. newest first.
+    async fn fetch_recent(&self, user_id: &String, limit: usize) -> Result<Vec<String>, MyError>;
+}
+
+/// check password against policy and, if user_id is given, password history.
+/// all violations are reported together. if any,BadRequest.
+pub async fn ensure_password_policy<H: PasswordHistoryRepository + Sync>(
+    password_policy: &PasswordPolicy,
+    password_history_repository: &H,
+    raw_password: &str,
+    context: &PasswordContext<'_>,
+    user_id: Option<&String>,
+) -> Result<(), MyError> {
+    let mut violations = password_policy.violations(raw_password, context);
+    if let Some(user_id) = user_id {
+        if password_policy.history_size > 0 {
+            let reused = password_history_repository
+                .fetch_recent(user_id, password_policy.history_size)
+                .await?
+                .iter()
+                .any(|hashed_password| verify(raw_password, hashed_password).unwrap_or(false));
+            if reused {
+                violations.push(format!(
+                    "password must differ from the last {} passwords",
+                    password_policy.history_size
+                ));
+            }
+        }
+    }
+    if !violations.is_empty() {
+        return Err(MyError::BadRequest(json!({
+            "error": "password does not satisfy the policy",
+            "violations": violations,
+        })));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+
+mod tests {
+
+    use super::*;
+
+    const CONTEXT: PasswordContext = PasswordContext {
+        name: "Yamada",
+        code: "01GJT4JH83TFDT0D0SDH8ZGSQH",
+    };
+
+    #[test]
+    fn test_password_policy() {
+        let password_policy = PasswordPolicy::default();
+        assert!(password_policy
+            .violations("correct-horse", &CONTEXT)
+            .is_empty());
+        // counted in characters. 8 characters but 24 bytes.
+        assert!(password_policy
+            .violations("ぱすわーど１２３", &CONTEXT)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_password_policy_violations() {
+        let password_policy = PasswordPolicy::default();
+        assert_eq!(
+            password_policy.violations("abc", &CONTEXT),
+            vec![
+                "password must be at least 8 characters".to_string(),
+                "password must contain at least 2 of lowercase, uppercase, digit and symbol"
+                    .to_string(),
+            ]
+        );
+        assert_eq!(
+            password_policy.violations("Password1", &CONTEXT),
+            vec!["password is too common".to_string()]
+        );
+        assert_eq!(
+            password_policy.violations("yamada-2022", &CONTEXT),
+            vec!["password must not contain user name or code".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_custom_rule() {
+        struct NoSpaceRule;
+        impl PasswordRule for NoSpaceRule {
+            fn violation(&self, password: &str, _context: &PasswordContext) -> Option<String> {
+                password
+                    .contains(' ')
+                    .then(|| "password must not contain space".to_string())
+            }
+        }
+        let password_policy = PasswordPolicy::new(vec![Arc::new(NoSpaceRule)], 0);
+        assert_eq!(
+            password_policy.violations("a b", &CONTEXT),
+            vec!["password must not contain space".to_string()]
+        );
+    }
+}
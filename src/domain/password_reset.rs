@@ -0,0 +1,106 @@
+use crate::utils::errors::MyError;
+use crate::utils::hash::hash_token;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Local};
+use serde_json::json;
+use ulid::Ulid;
+use uuid::Uuid;
+
+const PASSWORD_RESET_TOKEN_EXPIRED_WITHIN_MINUTES: i64 = 30;
+const RAW_TOKEN_SEPARATOR: char = '.';
+
+/// single use token to reset forgotten password. delivered by Notifier.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordResetToken {
+    pub id: String,
+    pub user_id: String,
+    pub hashed_token: String,
+    pub expires_at: DateTime<Local>,
+    pub used: bool,
+}
+
+impl PasswordResetToken {
+    /// issue new token. return token and raw token for user. raw token is not stored.
+    pub fn new(user_id: String) -> Result<(Self, String), MyError> {
+        let id = Ulid::new().to_string();
+        let secret = Uuid::new_v4().simple().to_string();
+        let hashed_token = hash_token(&secret)?;
+        let raw_token = format!("{}{}{}", id, RAW_TOKEN_SEPARATOR, secret);
+        let password_reset_token = Self {
+            id,
+            user_id,
+            hashed_token,
+            expires_at: Local::now()
+                + Duration::minutes(PASSWORD_RESET_TOKEN_EXPIRED_WITHIN_MINUTES),
+            used: false,
+        };
+        Ok((password_reset_token, raw_token))
+    }
+
+    pub fn from(
+        id: String,
+        user_id: String,
+        hashed_token: String,
+        expires_at: DateTime<Local>,
+        used: bool,
+    ) -> Self {
+        Self {
+            id,
+            user_id,
+            hashed_token,
+            expires_at,
+            used,
+        }
+    }
+
+    /// token can reset password only once before it expires.
+    pub fn is_usable(&self, now: DateTime<Local>) -> bool {
+        !self.used && now < self.expires_at
+    }
+}
+
+/// split raw token into (id, secret).
+pub fn split_raw_token(raw_token: &str) -> Result<(String, String), MyError> {
+    match raw_token.split_once(RAW_TOKEN_SEPARATOR) {
+        Some((id, secret)) if !id.is_empty() && !secret.is_empty() => {
+            Ok((id.to_string(), secret.to_string()))
+        }
+        _ => Err(MyError::BadRequest(
+            json!({"error":"password reset token is invalid"}),
+        )),
+    }
+}
+
+#[async_trait]
+pub trait PasswordResetTokenRepository {
+    /// store PasswordResetToken to DB.
+    async fn save(&self, password_reset_token: &PasswordResetToken) -> Result<(), MyError>;
+    /// find one PasswordResetToken from DB by primary key. if not exist,BadRequest.
+    async fn fetch_one(&self, id: &String) -> Result<PasswordResetToken, MyError>;
+    /// mark PasswordResetToken as used. return false if it has been used meanwhile.
+    async fn mark_used(&self, id: &String) -> Result<bool, MyError>;
+    /// mark all PasswordResetToken of the user as used. only the newest token works.
+    async fn revoke_by_user_id(&self, user_id: &String) -> Result<(), MyError>;
+}
+
+#[cfg(test)]
+
+mod tests {
+
+    use crate::utils::hash::verify;
+
+    use super::*;
+    #[test]
+    fn test_password_reset_token_new() {
+        let test_user_id = "test_user_id".to_string();
+        let (password_reset_token, raw_token) =
+            PasswordResetToken::new(test_user_id.clone()).unwrap();
+        assert_eq!(password_reset_token.user_id, test_user_id);
+        assert!(password_reset_token.is_usable(Local::now()));
+        assert!(!password_reset_token.is_usable(Local::now() + Duration::minutes(30)));
+
+        let (id, secret) = split_raw_token(&raw_token).unwrap();
+        assert_eq!(id, password_reset_token.id);
+        assert!(verify(&secret, &password_reset_token.hashed_token).unwrap());
+    }
+}
@@ -1,45 +1,584 @@
+use crate::domain::care_assignment::CareAssignment;
+use crate::domain::medical_examination::MedicalExamination;
+use crate::domain::user::User;
+use crate::utils::datetime::DATE_FMT;
 use crate::utils::errors::MyError;
 use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use strum::{Display, EnumString};
 use ulid::Ulid;
 
-/// represent training detail and strength.
+/// patient registered at reception. code identifies the patient across the hospital.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Patient {
     pub id: String,
     pub code: String,
     pub name: String,
+    pub demographics: Demographics,
+    /// incremented on every update. optimistic lock against concurrent edit.
+    pub version: u32,
+    /// None unless archived.
+    pub archive: Option<Archive>,
 }
 const NAME_LIMIT: i32 = 30;
+const ADDRESS_LIMIT: usize = 200;
+const ARCHIVE_REASON_LIMIT: usize = 500;
+const NAME_KANA_LIMIT: usize = 60;
+/// half width katakana and the full width ones in the same order.
+const HALF_WIDTH_KATAKANA: &str = "｡｢｣､･ｦｧｨｩｪｫｬｭｮｯｰｱｲｳｴｵｶｷｸｹｺｻｼｽｾｿﾀﾁﾂﾃﾄﾅﾆﾇﾈﾉﾊﾋﾌﾍﾎﾏﾐﾑﾒﾓﾔﾕﾖﾗﾘﾙﾚﾛﾜﾝﾞﾟ";
+const FULL_WIDTH_KATAKANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン゛゜";
 
-impl Patient {
-    pub fn new(name: String, code: Option<String>) -> Result<Self, MyError> {
-        if name.chars().count() as i32 > NAME_LIMIT {
+/// patient is archived instead of deleted, so that medical examinations and care history remain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Archive {
+    pub archived_at: DateTime<Local>,
+    /// id of the user who archived.
+    pub archived_by: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Sex {
+    Male,
+    Female,
+    Other,
+    Unknown,
+}
+
+/// ABO and Rh blood type. written as "A+", "AB-" and so on.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Display, EnumString)]
+pub enum BloodType {
+    #[serde(rename = "A+")]
+    #[strum(serialize = "A+")]
+    APositive,
+    #[serde(rename = "A-")]
+    #[strum(serialize = "A-")]
+    ANegative,
+    #[serde(rename = "B+")]
+    #[strum(serialize = "B+")]
+    BPositive,
+    #[serde(rename = "B-")]
+    #[strum(serialize = "B-")]
+    BNegative,
+    #[serde(rename = "O+")]
+    #[strum(serialize = "O+")]
+    OPositive,
+    #[serde(rename = "O-")]
+    #[strum(serialize = "O-")]
+    ONegative,
+    #[serde(rename = "AB+")]
+    #[strum(serialize = "AB+")]
+    ABPositive,
+    #[serde(rename = "AB-")]
+    #[strum(serialize = "AB-")]
+    ABNegative,
+}
+
+/// who the patient is, besides the name. every item is optional as reception may not know it yet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Demographics {
+    /// reading of name in full width katakana. e.g. "ヤマダ タロウ".
+    pub name_kana: Option<String>,
+    pub date_of_birth: Option<NaiveDate>,
+    pub sex: Option<Sex>,
+    /// digits only. e.g. "0312345678".
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    /// "123-4567".
+    pub postal_code: Option<String>,
+    pub blood_type: Option<BloodType>,
+}
+
+impl Demographics {
+    /// name_kana is written in hiragana or katakana, and stored as full width katakana.
+    /// date_of_birth is "YYYY-MM-DD" and not in the future.
+    /// phone and postal_code are normalised. full width digits are accepted.
+    pub fn new(
+        name_kana: Option<String>,
+        date_of_birth: Option<String>,
+        sex: Option<Sex>,
+        phone: Option<String>,
+        address: Option<String>,
+        postal_code: Option<String>,
+        blood_type: Option<BloodType>,
+    ) -> Result<Self, MyError> {
+        let name_kana = match non_empty(name_kana) {
+            Some(name_kana) => Some(normalize_name_kana(&name_kana)?),
+            None => None,
+        };
+        let date_of_birth = match non_empty(date_of_birth) {
+            Some(date_of_birth) => Some(parse_date_of_birth(&date_of_birth)?),
+            None => None,
+        };
+        let phone = match non_empty(phone) {
+            Some(phone) => Some(normalize_phone(&phone)?),
+            None => None,
+        };
+        let address = non_empty(address);
+        if address
+            .as_ref()
+            .map_or(false, |address| address.chars().count() > ADDRESS_LIMIT)
+        {
             return Err(MyError::BadRequest(
-                json!({"error":"patient name must be less than 30 letters"}),
+                json!({"error":"address must be less than 200 letters"}),
             ));
+        }
+        let postal_code = match non_empty(postal_code) {
+            Some(postal_code) => Some(normalize_postal_code(&postal_code)?),
+            None => None,
         };
+        Ok(Self {
+            name_kana,
+            date_of_birth,
+            sex,
+            phone,
+            address,
+            postal_code,
+            blood_type,
+        })
+    }
+}
+
+/// trimmed. None if blank.
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// full width digits and hyphens to ascii.
+fn to_half_width(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '０'..='９' => char::from_u32(c as u32 - '０' as u32 + '0' as u32).unwrap_or(c),
+            '－' | 'ー' | '‐' | '−' => '-',
+            '（' => '(',
+            '）' => ')',
+            '＋' => '+',
+            '　' => ' ',
+            _ => c,
+        })
+        .collect()
+}
+
+/// hiragana and half width katakana to full width katakana. e.g. "ﾔﾏﾀﾞ たろう" to "ヤマダ タロウ".
+fn to_katakana(value: &str) -> String {
+    let mut katakana = String::new();
+    for c in value.chars() {
+        let c = match c {
+            'ぁ'..='ゖ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            '｡'..='ﾟ' => HALF_WIDTH_KATAKANA
+                .chars()
+                .position(|half_width| half_width == c)
+                .and_then(|i| FULL_WIDTH_KATAKANA.chars().nth(i))
+                .unwrap_or(c),
+            _ => c,
+        };
+        // voiced sound marks of half width katakana follow the letter.
+        let voiced = match (c, katakana.chars().last()) {
+            ('゛', Some('ウ')) => Some('ヴ'),
+            ('゛', Some(last)) if "カキクケコサシスセソタチツテトハヒフヘホ".contains(last) => {
+                char::from_u32(last as u32 + 1)
+            }
+            ('゜', Some(last)) if "ハヒフヘホ".contains(last) => {
+                char::from_u32(last as u32 + 2)
+            }
+            _ => None,
+        };
+        if let Some(voiced) = voiced {
+            katakana.pop();
+            katakana.push(voiced);
+        } else {
+            katakana.push(c);
+        }
+    }
+    katakana
+}
+
+/// normalised for search. hiragana and half width katakana to full width katakana,
+/// full width alphabets and digits to half width, lower case, and without spaces.
+pub fn normalize_for_search(value: &str) -> String {
+    to_katakana(value)
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c {
+            '！'..='～' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            _ => c,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// full width katakana with half width spaces. less than 60 letters.
+fn normalize_name_kana(name_kana: &str) -> Result<String, MyError> {
+    let name_kana = to_katakana(name_kana).replace('　', " ");
+    if !name_kana
+        .chars()
+        .all(|c| matches!(c, 'ァ'..='ヺ' | 'ー' | ' '))
+    {
+        return Err(MyError::BadRequest(
+            json!({"error":"name_kana must be written in hiragana or katakana"}),
+        ));
+    }
+    if name_kana.chars().count() > NAME_KANA_LIMIT {
+        return Err(MyError::BadRequest(
+            json!({"error":"name_kana must be less than 60 letters"}),
+        ));
+    }
+    Ok(name_kana)
+}
+
+fn parse_date_of_birth(date_of_birth: &str) -> Result<NaiveDate, MyError> {
+    let date_of_birth = NaiveDate::parse_from_str(date_of_birth, DATE_FMT).map_err(|_| {
+        MyError::BadRequest(json!({"error":"date_of_birth must be a valid date of YYYY-MM-DD"}))
+    })?;
+    if date_of_birth > Local::now().date_naive() {
+        return Err(MyError::BadRequest(
+            json!({"error":"date_of_birth must not be in the future"}),
+        ));
+    }
+    Ok(date_of_birth)
+}
+
+/// digits only. +81 is replaced with leading 0. must be 10 or 11 digits starting with 0.
+fn normalize_phone(phone: &str) -> Result<String, MyError> {
+    let phone = to_half_width(phone);
+    let phone = match phone.strip_prefix("+81") {
+        Some(national) => format!("0{}", national.trim_start()),
+        None => phone,
+    };
+    let digits = phone
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' ' | '(' | ')'))
+        .collect::<String>();
+    if !digits.chars().all(|c| c.is_ascii_digit())
+        || !digits.starts_with('0')
+        || !(10..=11).contains(&digits.len())
+    {
+        return Err(MyError::BadRequest(
+            json!({"error":"phone must be a Japanese phone number"}),
+        ));
+    }
+    Ok(digits)
+}
+
+/// "1234567" and "123-4567" to "123-4567".
+fn normalize_postal_code(postal_code: &str) -> Result<String, MyError> {
+    let postal_code = to_half_width(postal_code);
+    let digits = postal_code.replacen('-', "", 1);
+    let hyphen_at_right_place = !postal_code.contains('-') || postal_code.find('-') == Some(3);
+    if !hyphen_at_right_place || digits.len() != 7 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(MyError::BadRequest(
+            json!({"error":"postal_code must be 7 digits like 123-4567"}),
+        ));
+    }
+    Ok(format!("{}-{}", &digits[..3], &digits[3..]))
+}
+
+/// changes to patient. None leaves the item as it is. blank string clears the item,
+/// and Some(None) clears sex and blood_type. name can not be cleared.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PatientPatch {
+    pub name: Option<String>,
+    pub name_kana: Option<String>,
+    pub date_of_birth: Option<String>,
+    pub sex: Option<Option<Sex>>,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    pub postal_code: Option<String>,
+    pub blood_type: Option<Option<BloodType>>,
+}
+
+fn validate_name(name: &str) -> Result<(), MyError> {
+    if name.trim().is_empty() {
+        return Err(MyError::BadRequest(
+            json!({"error":"patient name is required"}),
+        ));
+    }
+    if name.chars().count() as i32 > NAME_LIMIT {
+        return Err(MyError::BadRequest(
+            json!({"error":"patient name must be less than 30 letters"}),
+        ));
+    };
+    Ok(())
+}
+
+impl Patient {
+    pub fn new(
+        name: String,
+        code: Option<String>,
+        demographics: Demographics,
+    ) -> Result<Self, MyError> {
+        validate_name(&name)?;
         let id = Ulid::new().to_string();
         let code = if let Some(code) = code {
             code
         } else {
             Ulid::new().to_string()
         };
-        Ok(Self { id, code, name })
+        Ok(Self {
+            id,
+            code,
+            name,
+            demographics,
+            version: 1,
+            archive: None,
+        })
+    }
+
+    pub fn from(
+        id: String,
+        code: String,
+        name: String,
+        demographics: Demographics,
+        version: u32,
+        archive: Option<Archive>,
+    ) -> Patient {
+        Self {
+            id,
+            code,
+            name,
+            demographics,
+            version,
+            archive,
+        }
+    }
+
+    /// name normalised by normalize_for_search.
+    pub fn search_name(&self) -> String {
+        normalize_for_search(&self.name)
+    }
+
+    /// name_kana normalised by normalize_for_search.
+    pub fn search_kana(&self) -> Option<String> {
+        self.demographics
+            .name_kana
+            .as_ref()
+            .map(|name_kana| normalize_for_search(name_kana))
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.archive.is_some()
+    }
+
+    /// archived patient can be read, but not changed until restored. if archived,BadRequest.
+    pub fn ensure_not_archived(&self) -> Result<(), MyError> {
+        if self.is_archived() {
+            return Err(MyError::BadRequest(json!({
+                "error": format!("patient code={} is archived.", self.code)
+            })));
+        }
+        Ok(())
+    }
+
+    /// archive with reason of 1~500 letters, and increment version.
+    pub fn archive(
+        &mut self,
+        user_id: String,
+        reason: String,
+        now: DateTime<Local>,
+    ) -> Result<(), MyError> {
+        self.ensure_not_archived()?;
+        let reason = reason.trim().to_string();
+        if reason.is_empty() || reason.chars().count() > ARCHIVE_REASON_LIMIT {
+            return Err(MyError::BadRequest(
+                json!({"error":"reason must be 1~500 letters"}),
+            ));
+        }
+        self.archive = Some(Archive {
+            archived_at: now,
+            archived_by: user_id,
+            reason,
+        });
+        self.version += 1;
+        Ok(())
+    }
+
+    /// restore archived patient, and increment version. if not archived,BadRequest.
+    pub fn restore(&mut self) -> Result<(), MyError> {
+        if !self.is_archived() {
+            return Err(MyError::BadRequest(json!({
+                "error": format!("patient code={} is not archived.", self.code)
+            })));
+        }
+        self.archive = None;
+        self.version += 1;
+        Ok(())
+    }
+
+    /// apply changes with the same validation as new, and increment version.
+    pub fn apply(&mut self, patch: PatientPatch) -> Result<(), MyError> {
+        self.ensure_not_archived()?;
+        let name = patch.name.unwrap_or_else(|| self.name.clone());
+        validate_name(&name)?;
+        let current = &self.demographics;
+        let demographics = Demographics::new(
+            patch.name_kana.or_else(|| current.name_kana.clone()),
+            patch.date_of_birth.or_else(|| {
+                current
+                    .date_of_birth
+                    .map(|date_of_birth| date_of_birth.format(DATE_FMT).to_string())
+            }),
+            patch.sex.unwrap_or(current.sex),
+            patch.phone.or_else(|| current.phone.clone()),
+            patch.address.or_else(|| current.address.clone()),
+            patch.postal_code.or_else(|| current.postal_code.clone()),
+            patch.blood_type.unwrap_or(current.blood_type),
+        )?;
+        self.name = name;
+        self.demographics = demographics;
+        self.version += 1;
+        Ok(())
+    }
+}
+/// how the name of patient matches the query. better one first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NameMatch {
+    Exact,
+    Prefix,
+    Partial,
+}
+
+/// search condition of patients. name matches name or name_kana of patients regardless of
+/// hiragana and katakana, full and half width, case and spaces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatientQuery {
+    /// normalised by normalize_for_search.
+    pub name: Option<String>,
+    pub date_of_birth: Option<NaiveDate>,
+    pub include_archived: bool,
+}
+
+impl PatientQuery {
+    /// either name or date_of_birth is required, so as not to list every patient.
+    pub fn new(
+        name: Option<String>,
+        date_of_birth: Option<String>,
+        include_archived: bool,
+    ) -> Result<Self, MyError> {
+        let name = non_empty(name)
+            .map(|name| normalize_for_search(&name))
+            .filter(|name| !name.is_empty());
+        let date_of_birth = match non_empty(date_of_birth) {
+            Some(date_of_birth) => Some(parse_date_of_birth(&date_of_birth)?),
+            None => None,
+        };
+        if name.is_none() && date_of_birth.is_none() {
+            return Err(MyError::BadRequest(
+                json!({"error":"name or date_of_birth is required"}),
+            ));
+        }
+        Ok(Self {
+            name,
+            date_of_birth,
+            include_archived,
+        })
+    }
+
+    /// how the patient matches. None if not. without name, patients of the date of birth match exactly.
+    pub fn matches(&self, patient: &Patient) -> Option<NameMatch> {
+        if !self.include_archived && patient.is_archived() {
+            return None;
+        }
+        if self.date_of_birth.map_or(false, |date_of_birth| {
+            patient.demographics.date_of_birth != Some(date_of_birth)
+        }) {
+            return None;
+        }
+        let name = match &self.name {
+            Some(name) => name,
+            None => return Some(NameMatch::Exact),
+        };
+        [Some(patient.search_name()), patient.search_kana()]
+            .into_iter()
+            .flatten()
+            .filter_map(|target| {
+                if &target == name {
+                    Some(NameMatch::Exact)
+                } else if target.starts_with(name.as_str()) {
+                    Some(NameMatch::Prefix)
+                } else if target.contains(name.as_str()) {
+                    Some(NameMatch::Partial)
+                } else {
+                    None
+                }
+            })
+            .min()
     }
 
-    pub fn from(id: String, code: String, name: String) -> Patient {
-        Self { id, code, name }
+    /// patients which match, better match first, then in order of kana reading and name.
+    pub fn rank(&self, patients: Vec<Patient>) -> Vec<Patient> {
+        let mut ranked = patients
+            .into_iter()
+            .filter_map(|patient| {
+                self.matches(&patient)
+                    .map(|name_match| (name_match, patient))
+            })
+            .collect::<Vec<(NameMatch, Patient)>>();
+        ranked.sort_by_key(|(name_match, patient)| {
+            (*name_match, patient.search_kana(), patient.search_name())
+        });
+        ranked.into_iter().map(|(_, patient)| patient).collect()
     }
 }
+
+/// patient with summary of the chart.
+/// medical examinations are None if the user is not permitted to read them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatientChart {
+    pub patient: Patient,
+    /// users in charge of the patient at the moment, newest first.
+    pub care_team: Vec<(User, CareAssignment)>,
+    /// latest first.
+    pub latest_medical_examinations: Option<Vec<MedicalExamination>>,
+    pub medical_examination_count: Option<usize>,
+}
+
 #[async_trait]
 pub trait PatientRepository {
     /// store Patient to DB.
     async fn save(&self, patient: &Patient) -> Result<(), MyError>;
+    /// store new Patient with its first CareAssignment and MedicalExamination in one transaction.
+    /// the examination is taken by the user of care_assignment.
+    async fn save_with_medical_examination(
+        &self,
+        patient: &Patient,
+        care_assignment: &CareAssignment,
+        medical_examination: &MedicalExamination,
+    ) -> Result<(), MyError>;
+    /// store patient whose version has been incremented. false if the version in DB is no longer
+    /// the one before increment, as someone else has updated it.
+    async fn update(&self, patient: &Patient) -> Result<bool, MyError>;
     /// find one Patient from DB by primary key. return Patient. if not exist,None.
     async fn fetch_one(&self, id: &String) -> Result<Patient, MyError>;
+    /// find one Patient by code. if not exist,NotFound.
     async fn fetch_by_code(&self, code: &String) -> Result<Patient, MyError>;
-    async fn fetch_all(&self) -> Result<Vec<Patient>, MyError>;
+    /// archived patients are left out unless include_archived.
+    async fn fetch_all(&self, include_archived: bool) -> Result<Vec<Patient>, MyError>;
+    /// find at most limit Patients which may match the query no worse than name_match. Prefix can
+    /// use indexes, while Partial scans patients. better matches come first, but PatientQuery::rank
+    /// decides the order.
+    async fn search(
+        &self,
+        query: &PatientQuery,
+        name_match: NameMatch,
+        limit: u32,
+    ) -> Result<Vec<Patient>, MyError>;
+    /// find Patients whose search_name has not been filled yet, as registered before search.
+    async fn fetch_without_search_name(&self) -> Result<Vec<Patient>, MyError>;
+    /// store search_name and search_kana of the patient. version is left as it is.
+    async fn update_search_columns(&self, patient: &Patient) -> Result<(), MyError>;
+    /// find Patients the user is in charge of at now. archived patients are included.
+    async fn fetch_by_user_id(
+        &self,
+        user_id: &String,
+        now: DateTime<Local>,
+    ) -> Result<Vec<Patient>, MyError>;
 }
 
 #[cfg(test)]
@@ -50,22 +589,383 @@ mod tests {
     #[test]
     fn test_patient_new() {
         let test_name = "x".to_string().repeat(30);
-        let patient = Patient::new(test_name.clone(), None).unwrap();
+        let patient = Patient::new(test_name.clone(), None, Demographics::default()).unwrap();
         assert_eq!(patient.name, test_name);
 
         let test_code = "y".to_string().repeat(30);
-        let patient = Patient::new(test_name.clone(), Some(test_code.clone())).unwrap();
+        let demographics = Demographics::new(
+            Some("ﾔﾏﾀﾞ　はなこ".to_string()),
+            Some("1990-04-01".to_string()),
+            Some(Sex::Female),
+            Some("+81 90-1234-5678".to_string()),
+            Some(" 東京都千代田区千代田1-1 ".to_string()),
+            Some("１００－０００１".to_string()),
+            Some(BloodType::ABNegative),
+        )
+        .unwrap();
+        let patient =
+            Patient::new(test_name.clone(), Some(test_code.clone()), demographics).unwrap();
         assert_eq!(patient.name, test_name);
         assert_eq!(patient.code, test_code);
+        assert_eq!(
+            patient.demographics.name_kana,
+            Some("ヤマダ ハナコ".to_string())
+        );
+        assert_eq!(
+            patient.demographics.date_of_birth,
+            NaiveDate::from_ymd_opt(1990, 4, 1)
+        );
+        assert_eq!(patient.demographics.phone, Some("09012345678".to_string()));
+        assert_eq!(
+            patient.demographics.address,
+            Some("東京都千代田区千代田1-1".to_string())
+        );
+        assert_eq!(
+            patient.demographics.postal_code,
+            Some("100-0001".to_string())
+        );
+        assert_eq!(patient.demographics.blood_type.unwrap().to_string(), "AB-");
+    }
+
+    #[test]
+    fn test_patient_apply() {
+        let demographics = Demographics::new(
+            None,
+            Some("1990-04-01".to_string()),
+            Some(Sex::Female),
+            Some("03-1234-5678".to_string()),
+            Some("東京都千代田区千代田1-1".to_string()),
+            None,
+            None,
+        )
+        .unwrap();
+        let mut patient = Patient::new("test_name".to_string(), None, demographics).unwrap();
+        patient
+            .apply(PatientPatch {
+                name: Some("test_name_fixed".to_string()),
+                phone: Some("".to_string()),
+                postal_code: Some("1000001".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(patient.name, "test_name_fixed");
+        assert_eq!(patient.version, 2);
+        assert_eq!(
+            patient.demographics.date_of_birth,
+            NaiveDate::from_ymd_opt(1990, 4, 1)
+        );
+        assert_eq!(patient.demographics.sex, Some(Sex::Female));
+        assert_eq!(patient.demographics.phone, None);
+
+        patient
+            .apply(PatientPatch {
+                sex: Some(None),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(patient.demographics.sex, None);
+        assert_eq!(patient.version, 3);
+        assert_eq!(
+            patient.demographics.postal_code,
+            Some("100-0001".to_string())
+        );
+
+        // invalid patch leaves patient as it is.
+        let before = patient.clone();
+        let err = patient
+            .apply(PatientPatch {
+                name: Some("x".repeat(31)),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"patient name must be less than 30 letters"}))
+        );
+        let err = patient
+            .apply(PatientPatch {
+                name: Some(" ".to_string()),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"patient name is required"}))
+        );
+        let err = patient
+            .apply(PatientPatch {
+                postal_code: Some("100".to_string()),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"postal_code must be 7 digits like 123-4567"}))
+        );
+        assert_eq!(patient, before);
+    }
+
+    #[test]
+    fn test_patient_archive() {
+        let mut patient =
+            Patient::new("test_name".to_string(), None, Demographics::default()).unwrap();
+        let now = Local::now();
+        patient
+            .archive("test_id".to_string(), " duplicated ".to_string(), now)
+            .unwrap();
+        assert_eq!(
+            patient.archive,
+            Some(Archive {
+                archived_at: now,
+                archived_by: "test_id".to_string(),
+                reason: "duplicated".to_string(),
+            })
+        );
+        assert_eq!(patient.version, 2);
+        let archived = MyError::BadRequest(json!({
+            "error": format!("patient code={} is archived.", patient.code)
+        }));
+        assert_eq!(
+            patient
+                .archive("test_id".to_string(), "duplicated".to_string(), now)
+                .unwrap_err(),
+            archived
+        );
+        assert_eq!(
+            patient.apply(PatientPatch::default()).unwrap_err(),
+            archived
+        );
+
+        patient.restore().unwrap();
+        assert!(!patient.is_archived());
+        assert_eq!(patient.version, 3);
+        assert_eq!(
+            patient.restore().unwrap_err(),
+            MyError::BadRequest(json!({
+                "error": format!("patient code={} is not archived.", patient.code)
+            }))
+        );
+        for reason in [" ".to_string(), "x".repeat(ARCHIVE_REASON_LIMIT + 1)] {
+            assert_eq!(
+                patient
+                    .archive("test_id".to_string(), reason, now)
+                    .unwrap_err(),
+                MyError::BadRequest(json!({"error":"reason must be 1~500 letters"}))
+            );
+        }
+    }
+
+    #[test]
+    fn test_demographics_new() {
+        for (phone, expected) in [
+            ("03-1234-5678", "0312345678"),
+            ("(03)1234-5678", "0312345678"),
+            ("０９０１２３４５６７８", "09012345678"),
+        ] {
+            let demographics =
+                Demographics::new(None, None, None, Some(phone.to_string()), None, None, None)
+                    .unwrap();
+            assert_eq!(demographics.phone, Some(expected.to_string()));
+        }
+        let demographics = Demographics::new(
+            None,
+            Some(" ".to_string()),
+            None,
+            Some("".to_string()),
+            None,
+            Some("1000001".to_string()),
+            None,
+        )
+        .unwrap();
+        assert_eq!(demographics.date_of_birth, None);
+        assert_eq!(demographics.phone, None);
+        assert_eq!(demographics.postal_code, Some("100-0001".to_string()));
+    }
+
+    #[test]
+    fn test_demographics_new_failed() {
+        let tomorrow = (Local::now().date_naive() + chrono::Duration::days(1))
+            .format(DATE_FMT)
+            .to_string();
+        for (date_of_birth, phone, address, postal_code, expected) in [
+            (
+                Some("2000-02-30".to_string()),
+                None,
+                None,
+                None,
+                "date_of_birth must be a valid date of YYYY-MM-DD",
+            ),
+            (
+                Some("2000/01/01".to_string()),
+                None,
+                None,
+                None,
+                "date_of_birth must be a valid date of YYYY-MM-DD",
+            ),
+            (
+                Some(tomorrow),
+                None,
+                None,
+                None,
+                "date_of_birth must not be in the future",
+            ),
+            (
+                None,
+                Some("1234-5678".to_string()),
+                None,
+                None,
+                "phone must be a Japanese phone number",
+            ),
+            (
+                None,
+                Some("090-1234-5678-9".to_string()),
+                None,
+                None,
+                "phone must be a Japanese phone number",
+            ),
+            (
+                None,
+                None,
+                Some("x".repeat(ADDRESS_LIMIT + 1)),
+                None,
+                "address must be less than 200 letters",
+            ),
+            (
+                None,
+                None,
+                None,
+                Some("1000-001".to_string()),
+                "postal_code must be 7 digits like 123-4567",
+            ),
+            (
+                None,
+                None,
+                None,
+                Some("100-00a1".to_string()),
+                "postal_code must be 7 digits like 123-4567",
+            ),
+        ] {
+            let err =
+                Demographics::new(None, date_of_birth, None, phone, address, postal_code, None)
+                    .unwrap_err();
+            assert_eq!(err, MyError::BadRequest(json!({ "error": expected })));
+        }
+    }
+
+    #[test]
+    fn test_normalize_for_search() {
+        for (value, expected) in [
+            ("やまだ たろう", "ヤマダタロウ"),
+            ("ﾔﾏﾀﾞ ﾀﾛｳ", "ヤマダタロウ"),
+            ("ﾊﾟﾋﾟﾌﾟ ｳﾞ", "パピプヴ"),
+            ("ＹＡＭＡＤＡ　Taro", "yamadataro"),
+            ("山田 太郎", "山田太郎"),
+        ] {
+            assert_eq!(normalize_for_search(value), expected);
+        }
+        assert_eq!(
+            Demographics::new(
+                Some("Yamada".to_string()),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None
+            )
+            .unwrap_err(),
+            MyError::BadRequest(
+                json!({"error":"name_kana must be written in hiragana or katakana"})
+            )
+        );
+    }
+
+    #[test]
+    fn test_patient_query() {
+        let patient = |name: &str, name_kana: Option<&str>, date_of_birth: Option<&str>| {
+            let demographics = Demographics::new(
+                name_kana.map(|name_kana| name_kana.to_string()),
+                date_of_birth.map(|date_of_birth| date_of_birth.to_string()),
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+            Patient::new(name.to_string(), None, demographics).unwrap()
+        };
+        let yamada_taro = patient("山田 太郎", Some("ヤマダ タロウ"), Some("1980-01-15"));
+        let yamada_hanako = patient("山田 花子", Some("ヤマダ ハナコ"), Some("1990-04-01"));
+        let nakayama = patient("中山 一郎", Some("ナカヤマ イチロウ"), None);
+        let yamada = patient("ヤマダ", None, None);
+        let patients = vec![
+            nakayama.clone(),
+            yamada_hanako.clone(),
+            yamada_taro.clone(),
+            yamada.clone(),
+        ];
+
+        // hiragana, half width and spaces are ignored.
+        let query = PatientQuery::new(Some("ﾔﾏﾀﾞ".to_string()), None, false).unwrap();
+        assert_eq!(query.matches(&yamada), Some(NameMatch::Exact));
+        assert_eq!(query.matches(&yamada_taro), Some(NameMatch::Prefix));
+        assert_eq!(query.matches(&nakayama), None);
+        assert_eq!(
+            query.rank(patients.clone()),
+            vec![yamada.clone(), yamada_taro.clone(), yamada_hanako.clone()]
+        );
+        let query = PatientQuery::new(Some("やま".to_string()), None, false).unwrap();
+        assert_eq!(query.matches(&nakayama), Some(NameMatch::Partial));
+        assert_eq!(
+            query.rank(patients.clone()),
+            vec![yamada, yamada_taro.clone(), yamada_hanako.clone(), nakayama]
+        );
+        let query = PatientQuery::new(Some("山田　太".to_string()), None, false).unwrap();
+        assert_eq!(query.rank(patients.clone()), vec![yamada_taro.clone()]);
+
+        let query = PatientQuery::new(
+            Some("やまだ".to_string()),
+            Some("1990-04-01".to_string()),
+            false,
+        )
+        .unwrap();
+        assert_eq!(query.rank(patients.clone()), vec![yamada_hanako.clone()]);
+        let query = PatientQuery::new(None, Some("1980-01-15".to_string()), false).unwrap();
+        assert_eq!(query.rank(patients), vec![yamada_taro.clone()]);
+
+        let mut archived = yamada_taro.clone();
+        archived
+            .archive(
+                "test_id".to_string(),
+                "duplicated".to_string(),
+                Local::now(),
+            )
+            .unwrap();
+        let query = PatientQuery::new(Some("タロウ".to_string()), None, false).unwrap();
+        assert_eq!(query.matches(&archived), None);
+        let query = PatientQuery::new(Some("タロウ".to_string()), None, true).unwrap();
+        assert_eq!(query.matches(&archived), Some(NameMatch::Partial));
+
+        let err = PatientQuery::new(Some(" ".to_string()), None, false).unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"name or date_of_birth is required"}))
+        );
     }
 
     #[test]
     fn test_patient_new_failed() {
         let test_name = "x".to_string().repeat((NAME_LIMIT + 1) as usize);
-        let patient = Patient::new(test_name, None).unwrap_err();
+        let patient = Patient::new(test_name, None, Demographics::default()).unwrap_err();
         assert_eq!(
             patient,
             MyError::BadRequest(json!({"error":"patient name must be less than 30 letters"}))
         );
+        let patient = Patient::new(" ".to_string(), None, Demographics::default()).unwrap_err();
+        assert_eq!(
+            patient,
+            MyError::BadRequest(json!({"error":"patient name is required"}))
+        );
     }
 }
@@ -0,0 +1,130 @@
+use crate::utils::errors::MyError;
+use crate::utils::hash::hash_token;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Local};
+use serde_json::json;
+use ulid::Ulid;
+use uuid::Uuid;
+
+const REFRESH_TOKEN_EXPIRED_WITHIN_DAYS: i64 = 14;
+const RAW_TOKEN_SEPARATOR: char = '.';
+
+/// refresh token to reissue access token without sign in.
+/// tokens rotated from the same sign in share family_id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefreshToken {
+    pub id: String,
+    pub user_id: String,
+    pub family_id: String,
+    pub hashed_token: String,
+    pub expires_at: DateTime<Local>,
+    pub revoked: bool,
+}
+
+impl RefreshToken {
+    /// issue new refresh token. start new family if family_id is None.
+    /// return refresh token and raw token for client. raw token is not stored.
+    pub fn new(user_id: String, family_id: Option<String>) -> Result<(Self, String), MyError> {
+        let id = Ulid::new().to_string();
+        let family_id = if let Some(family_id) = family_id {
+            family_id
+        } else {
+            Ulid::new().to_string()
+        };
+        let secret = Uuid::new_v4().simple().to_string();
+        let hashed_token = hash_token(&secret)?;
+        let raw_token = format!("{}{}{}", id, RAW_TOKEN_SEPARATOR, secret);
+        let refresh_token = Self {
+            id,
+            user_id,
+            family_id,
+            hashed_token,
+            expires_at: Local::now() + Duration::days(REFRESH_TOKEN_EXPIRED_WITHIN_DAYS),
+            revoked: false,
+        };
+        Ok((refresh_token, raw_token))
+    }
+
+    pub fn from(
+        id: String,
+        user_id: String,
+        family_id: String,
+        hashed_token: String,
+        expires_at: DateTime<Local>,
+        revoked: bool,
+    ) -> RefreshToken {
+        Self {
+            id,
+            user_id,
+            family_id,
+            hashed_token,
+            expires_at,
+            revoked,
+        }
+    }
+
+    pub fn is_expired(&self, now: DateTime<Local>) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// split raw token into (id, secret).
+pub fn split_raw_token(raw_token: &str) -> Result<(String, String), MyError> {
+    match raw_token.split_once(RAW_TOKEN_SEPARATOR) {
+        Some((id, secret)) if !id.is_empty() && !secret.is_empty() => {
+            Ok((id.to_string(), secret.to_string()))
+        }
+        _ => Err(MyError::Unauthorized(
+            json!({"error":"refresh token is invalid"}),
+        )),
+    }
+}
+
+#[async_trait]
+pub trait RefreshTokenRepository {
+    /// store RefreshToken to DB.
+    async fn save(&self, refresh_token: &RefreshToken) -> Result<(), MyError>;
+    /// find one RefreshToken from DB by primary key. if not exist,Unauthorized.
+    async fn fetch_one(&self, id: &String) -> Result<RefreshToken, MyError>;
+    /// mark RefreshToken as revoked. return false if it has been revoked meanwhile.
+    async fn revoke(&self, id: &String) -> Result<bool, MyError>;
+    /// mark all RefreshToken of the family as revoked.
+    async fn revoke_family(&self, family_id: &String) -> Result<(), MyError>;
+    /// mark all RefreshToken of the user as revoked. signs out every session.
+    async fn revoke_by_user_id(&self, user_id: &String) -> Result<(), MyError>;
+}
+
+#[cfg(test)]
+
+mod tests {
+
+    use crate::utils::hash::verify;
+
+    use super::*;
+    #[test]
+    fn test_refresh_token_new() {
+        let test_user_id = "test_user_id".to_string();
+        let (refresh_token, raw_token) = RefreshToken::new(test_user_id.clone(), None).unwrap();
+        assert_eq!(refresh_token.user_id, test_user_id);
+        assert!(!refresh_token.revoked);
+        assert!(!refresh_token.is_expired(Local::now()));
+
+        let (id, secret) = split_raw_token(&raw_token).unwrap();
+        assert_eq!(id, refresh_token.id);
+        assert!(verify(&secret, &refresh_token.hashed_token).unwrap());
+
+        let test_family_id = "test_family_id".to_string();
+        let (refresh_token, _) =
+            RefreshToken::new(test_user_id, Some(test_family_id.clone())).unwrap();
+        assert_eq!(refresh_token.family_id, test_family_id);
+    }
+
+    #[test]
+    fn test_split_raw_token_failed() {
+        let err = split_raw_token("no_separator").unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Unauthorized(json!({"error":"refresh token is invalid"}))
+        );
+    }
+}
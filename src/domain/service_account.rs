@@ -0,0 +1,277 @@
+use crate::domain::user::{Permission, Role};
+use crate::utils::errors::MyError;
+use crate::utils::hash::{digest_token, verify_digest};
+use crate::utils::totp::random_bytes;
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use data_encoding::BASE32_NOPAD;
+use serde_json::json;
+use ulid::Ulid;
+
+const NAME_LIMIT: usize = 30;
+/// every api key starts with this. tells api key from jwt and lets secret scanners find leaked keys.
+pub const API_KEY_PREFIX: &str = "pmk_";
+const RAW_KEY_SEPARATOR: char = '.';
+const API_KEY_SECRET_BYTES: usize = 32;
+/// scopes service account can never use. medical examinations need care assignment to the patient,
+/// and emergency access needs a person. service account has neither.
+const UNAVAILABLE_SCOPES: [Permission; 3] = [
+    Permission::ReadMedicalExamination,
+    Permission::WriteMedicalExamination,
+    Permission::BreakTheGlass,
+];
+
+/// client which is not a person. e.g. lab interface or nightly batch job.
+/// it acts with its role like user, but signs in only with ApiKey.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceAccount {
+    pub id: String,
+    pub name: String,
+    pub role: Role,
+    /// user who created the service account.
+    pub created_by: String,
+}
+
+impl ServiceAccount {
+    pub fn new(name: String, role: Role, created_by: String) -> Result<Self, MyError> {
+        if name.is_empty() || name.chars().count() > NAME_LIMIT {
+            return Err(MyError::BadRequest(
+                json!({"error":"name must be 1 to 30 letters"}),
+            ));
+        }
+        Ok(Self {
+            id: Ulid::new().to_string(),
+            name,
+            role,
+            created_by,
+        })
+    }
+
+    pub fn from(id: String, name: String, role: Role, created_by: String) -> Self {
+        Self {
+            id,
+            name,
+            role,
+            created_by,
+        }
+    }
+}
+
+/// key of ServiceAccount. scopes limit the permissions of its role. stored hashed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiKey {
+    pub id: String,
+    pub service_account_id: String,
+    pub hashed_key: String,
+    pub scopes: Vec<Permission>,
+    /// never expires if None.
+    pub expires_at: Option<DateTime<Local>>,
+    pub last_used_at: Option<DateTime<Local>>,
+    pub revoked: bool,
+}
+
+impl ApiKey {
+    /// issue new key. every scope must be permitted to the role of service account,
+    /// and not be one of UNAVAILABLE_SCOPES.
+    /// return key and raw key for client. raw key is not stored, so it is shown only once.
+    pub fn new(
+        service_account: &ServiceAccount,
+        scopes: Vec<Permission>,
+        expires_at: Option<DateTime<Local>>,
+    ) -> Result<(Self, String), MyError> {
+        if scopes.is_empty() {
+            return Err(MyError::BadRequest(
+                json!({"error":"scopes must not be empty"}),
+            ));
+        }
+        if let Some(scope) = scopes
+            .iter()
+            .find(|scope| !service_account.role.has_permission(**scope))
+        {
+            return Err(MyError::BadRequest(json!({
+                "error": format!("{} is not permitted to {}", service_account.role, scope)
+            })));
+        }
+        if let Some(scope) = scopes
+            .iter()
+            .find(|scope| UNAVAILABLE_SCOPES.contains(scope))
+        {
+            return Err(MyError::BadRequest(json!({
+                "error": format!("{} is not available to service account", scope)
+            })));
+        }
+        let id = Ulid::new().to_string();
+        let secret = BASE32_NOPAD
+            .encode(&random_bytes(API_KEY_SECRET_BYTES))
+            .to_lowercase();
+        let raw_key = format!("{}{}{}{}", API_KEY_PREFIX, id, RAW_KEY_SEPARATOR, secret);
+        let api_key = Self {
+            id,
+            service_account_id: service_account.id.clone(),
+            hashed_key: digest_token(&secret),
+            scopes,
+            expires_at,
+            last_used_at: None,
+            revoked: false,
+        };
+        Ok((api_key, raw_key))
+    }
+
+    pub fn from(
+        id: String,
+        service_account_id: String,
+        hashed_key: String,
+        scopes: Vec<Permission>,
+        expires_at: Option<DateTime<Local>>,
+        last_used_at: Option<DateTime<Local>>,
+        revoked: bool,
+    ) -> Self {
+        Self {
+            id,
+            service_account_id,
+            hashed_key,
+            scopes,
+            expires_at,
+            last_used_at,
+            revoked,
+        }
+    }
+
+    /// beginning of raw key. shown in list so that owner can tell which key it is.
+    pub fn prefix(&self) -> String {
+        format!("{}{}", API_KEY_PREFIX, self.id)
+    }
+
+    pub fn matches(&self, secret: &str) -> bool {
+        verify_digest(secret, &self.hashed_key)
+    }
+
+    pub fn is_usable(&self, now: DateTime<Local>) -> bool {
+        !self.revoked && self.expires_at.map_or(true, |expires_at| now < expires_at)
+    }
+}
+
+/// whether bearer token is api key rather than jwt.
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_PREFIX)
+}
+
+/// split raw key into (id, secret).
+pub fn split_raw_key(raw_key: &str) -> Result<(String, String), MyError> {
+    match raw_key
+        .strip_prefix(API_KEY_PREFIX)
+        .and_then(|key| key.split_once(RAW_KEY_SEPARATOR))
+    {
+        Some((id, secret)) if !id.is_empty() && !secret.is_empty() => {
+            Ok((id.to_string(), secret.to_string()))
+        }
+        _ => Err(MyError::Unauthorized(json!({"error":"api key is invalid"}))),
+    }
+}
+
+#[async_trait]
+pub trait ServiceAccountRepository {
+    /// store ServiceAccount to DB.
+    async fn save(&self, service_account: &ServiceAccount) -> Result<(), MyError>;
+    /// find one ServiceAccount from DB by primary key. if not exist,NotFound.
+    async fn fetch_one(&self, id: &String) -> Result<ServiceAccount, MyError>;
+    async fn fetch_all(&self) -> Result<Vec<ServiceAccount>, MyError>;
+}
+
+#[async_trait]
+pub trait ApiKeyRepository {
+    /// store ApiKey to DB.
+    async fn save(&self, api_key: &ApiKey) -> Result<(), MyError>;
+    /// find one ApiKey from DB by primary key. if not exist,None.
+    async fn fetch_one(&self, id: &String) -> Result<Option<ApiKey>, MyError>;
+    /// every ApiKey of the service account including revoked ones.
+    async fn fetch_by_service_account_id(
+        &self,
+        service_account_id: &String,
+    ) -> Result<Vec<ApiKey>, MyError>;
+    /// remember when the key was used last.
+    async fn update_last_used_at(&self, id: &String, now: DateTime<Local>) -> Result<(), MyError>;
+    /// mark ApiKey as revoked.
+    async fn revoke(&self, id: &String) -> Result<(), MyError>;
+}
+
+#[cfg(test)]
+
+mod tests {
+
+    use chrono::Duration;
+
+    use super::*;
+
+    fn service_account() -> ServiceAccount {
+        ServiceAccount::new(
+            "lab_interface".to_string(),
+            Role::Nurse,
+            "test_id".to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_api_key_new() {
+        let service_account = service_account();
+        let (api_key, raw_key) =
+            ApiKey::new(&service_account, vec![Permission::ReadPatient], None).unwrap();
+        assert!(is_api_key(&raw_key));
+        assert!(raw_key.starts_with(&api_key.prefix()));
+        let (id, secret) = split_raw_key(&raw_key).unwrap();
+        assert_eq!(id, api_key.id);
+        assert!(api_key.matches(&secret));
+        assert!(!api_key.matches("wrong_secret"));
+        assert_eq!(api_key.service_account_id, service_account.id);
+    }
+
+    #[test]
+    fn test_api_key_new_failed() {
+        let service_account = service_account();
+        let err =
+            ApiKey::new(&service_account, vec![Permission::RegisterPatient], None).unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"nurse is not permitted to register_patient"}))
+        );
+        // nurse is permitted, but service account can not be in charge of patients.
+        let err = ApiKey::new(
+            &service_account,
+            vec![Permission::ReadPatient, Permission::WriteMedicalExamination],
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(
+                json!({"error":"write_medical_examination is not available to service account"})
+            )
+        );
+        let err = ApiKey::new(&service_account, vec![], None).unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"scopes must not be empty"}))
+        );
+    }
+
+    #[test]
+    fn test_api_key_is_usable() {
+        let now = Local::now();
+        let (api_key, _) = ApiKey::new(
+            &service_account(),
+            vec![Permission::ReadPatient],
+            Some(now + Duration::days(1)),
+        )
+        .unwrap();
+        assert!(api_key.is_usable(now));
+        assert!(!api_key.is_usable(now + Duration::days(1)));
+        let revoked = ApiKey {
+            revoked: true,
+            ..api_key
+        };
+        assert!(!revoked.is_usable(now));
+        assert!(split_raw_key("pmk_no_separator").is_err());
+        assert!(split_raw_key("not_api_key.secret").is_err());
+    }
+}
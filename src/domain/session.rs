@@ -0,0 +1,118 @@
+use crate::utils::errors::MyError;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Local};
+use ulid::Ulid;
+
+/// session without refresh for this long can not be continued. same as refresh token lifetime.
+const SESSION_IDLE_DAYS: i64 = 14;
+const USER_AGENT_LIMIT: usize = 255;
+
+/// client of the request. recorded to session so that user can tell devices apart.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// one sign in of user. refresh tokens rotated from the sign in have its id as family_id,
+/// and access tokens have it as sid. revoked session can not be used by either token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub id: String,
+    pub user_id: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Local>,
+    /// updated on sign in and refresh.
+    pub last_seen_at: DateTime<Local>,
+    pub revoked_at: Option<DateTime<Local>>,
+}
+
+impl Session {
+    pub fn new(user_id: String, client: &ClientInfo) -> Self {
+        let now = Local::now();
+        Self {
+            id: Ulid::new().to_string(),
+            user_id,
+            ip: client.ip.clone(),
+            user_agent: truncate_user_agent(client.user_agent.clone()),
+            created_at: now,
+            last_seen_at: now,
+            revoked_at: None,
+        }
+    }
+
+    pub fn from(
+        id: String,
+        user_id: String,
+        ip: Option<String>,
+        user_agent: Option<String>,
+        created_at: DateTime<Local>,
+        last_seen_at: DateTime<Local>,
+        revoked_at: Option<DateTime<Local>>,
+    ) -> Self {
+        Self {
+            id,
+            user_id,
+            ip,
+            user_agent,
+            created_at,
+            last_seen_at,
+            revoked_at,
+        }
+    }
+
+    /// not revoked and refreshed recently enough.
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        self.revoked_at.is_none() && now < self.last_seen_at + Duration::days(SESSION_IDLE_DAYS)
+    }
+}
+
+/// user agent longer than column is cut.
+pub fn truncate_user_agent(user_agent: Option<String>) -> Option<String> {
+    user_agent.map(|user_agent| user_agent.chars().take(USER_AGENT_LIMIT).collect())
+}
+
+#[async_trait]
+pub trait SessionRepository {
+    /// store Session to DB.
+    async fn save(&self, session: &Session) -> Result<(), MyError>;
+    /// find one Session from DB by primary key. if not exist,None.
+    async fn fetch_one(&self, id: &String) -> Result<Option<Session>, MyError>;
+    /// not revoked sessions of the user. newest first.
+    async fn fetch_not_revoked_by_user_id(&self, user_id: &String)
+        -> Result<Vec<Session>, MyError>;
+    /// record client and time of refresh.
+    async fn touch(
+        &self,
+        id: &String,
+        client: &ClientInfo,
+        now: DateTime<Local>,
+    ) -> Result<(), MyError>;
+    /// mark Session as revoked.
+    async fn revoke(&self, id: &String, now: DateTime<Local>) -> Result<(), MyError>;
+}
+
+#[cfg(test)]
+
+mod tests {
+
+    use super::*;
+    #[test]
+    fn test_session_is_active() {
+        let client = ClientInfo {
+            ip: Some("192.0.2.2".to_string()),
+            user_agent: Some("x".repeat(300)),
+        };
+        let session = Session::new("test_id".to_string(), &client);
+        assert_eq!(session.user_agent.as_ref().unwrap().len(), USER_AGENT_LIMIT);
+        let now = Local::now();
+        assert!(session.is_active(now));
+        assert!(!session.is_active(now + Duration::days(SESSION_IDLE_DAYS)));
+        let revoked = Session {
+            revoked_at: Some(now),
+            ..session
+        };
+        assert!(!revoked.is_active(now));
+    }
+}
@@ -1,7 +1,17 @@
+use crate::domain::care_assignment::CareAssignment;
+use crate::domain::delegation::Delegation;
+use crate::domain::emergency_access::EmergencyAccess;
 use crate::utils::errors::MyError;
+use crate::utils::hash::{hash_password, verify};
+use crate::utils::totp::{
+    find_step, generate_secret, random_bytes, TOTP_DIGITS, TOTP_PERIOD_SECONDS,
+};
 use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use data_encoding::BASE32_NOPAD;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use strum::{Display, EnumString};
 use ulid::Ulid;
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
@@ -10,22 +20,90 @@ pub struct User {
     pub code: String,
     pub name: String,
     pub hashed_password: String,
+    pub role: Role,
+    /// deactivated user can not sign in and its tokens are rejected.
+    pub active: bool,
+    /// user signed up with invitation which requires approval can not sign in until approved.
+    pub approved: bool,
 }
 
-const NAME_LIMIT: i32 = 30;
+/// role of staff. decides what the user can do.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Role {
+    Doctor,
+    Nurse,
+    Receptionist,
+    Administrator,
+}
+
+/// operation which needs permission. also used as scope of api key.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Permission {
+    ReadPatient,
+    RegisterPatient,
+    ReadMedicalExamination,
+    WriteMedicalExamination,
+    AssignPatient,
+    ManageUser,
+    /// open a patient the user is not in charge of in emergency.
+    BreakTheGlass,
+    /// read and update registration of every patient, not only patients in charge, e.g. at reception.
+    /// medical examinations still need the user to be in charge.
+    AccessAllPatients,
+}
+
+impl Role {
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Role::Doctor => &[
+                Permission::ReadPatient,
+                Permission::RegisterPatient,
+                Permission::ReadMedicalExamination,
+                Permission::WriteMedicalExamination,
+                Permission::AssignPatient,
+                Permission::BreakTheGlass,
+            ],
+            Role::Nurse => &[
+                Permission::ReadPatient,
+                Permission::ReadMedicalExamination,
+                Permission::WriteMedicalExamination,
+                Permission::BreakTheGlass,
+            ],
+            Role::Receptionist => &[
+                Permission::ReadPatient,
+                Permission::RegisterPatient,
+                Permission::AccessAllPatients,
+            ],
+            Role::Administrator => &[
+                Permission::ReadPatient,
+                Permission::RegisterPatient,
+                Permission::AccessAllPatients,
+                Permission::AssignPatient,
+                Permission::ManageUser,
+            ],
+        }
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+pub const NAME_LIMIT: i32 = 30;
 
 impl User {
     pub fn new(
         name: String,
         code: Option<String>,
         hashed_password: String,
+        role: Role,
     ) -> Result<Self, MyError> {
         let id = Ulid::new().to_string();
-        if name.chars().count() as i32 > NAME_LIMIT {
-            return Err(MyError::BadRequest(
-                json!({"error":"train name must be less than 30 letters"}),
-            ));
-        };
+        validate_name(&name)?;
         let code = if let Some(code) = code {
             code
         } else {
@@ -36,6 +114,9 @@ impl User {
             code,
             name,
             hashed_password,
+            role,
+            active: true,
+            approved: true,
         })
     }
     pub fn from(
@@ -43,37 +124,340 @@ impl User {
         code: String,
         name: String,
         hashed_password: String,
+        role: Role,
+        active: bool,
+        approved: bool,
     ) -> Result<User, MyError> {
         let user = User {
             id,
             code,
             name,
             hashed_password,
+            role,
+            active,
+            approved,
         };
         Ok(user)
     }
+
+    pub fn change_name(&mut self, name: String) -> Result<(), MyError> {
+        validate_name(&name)?;
+        self.name = name;
+        Ok(())
+    }
+
+    /// deactivated or not yet approved user can not sign in. if so,Forbidden.
+    pub fn ensure_active(&self) -> Result<(), MyError> {
+        if !self.active {
+            return Err(MyError::Forbidden(
+                json!({"error":"account is deactivated"}),
+            ));
+        }
+        if !self.approved {
+            return Err(MyError::Forbidden(
+                json!({"error":"account is waiting for approval"}),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), MyError> {
+    if name.chars().count() as i32 > NAME_LIMIT {
+        return Err(MyError::BadRequest(
+            json!({"error":"train name must be less than 30 letters"}),
+        ));
+    };
+    Ok(())
 }
 
 #[async_trait]
 pub trait UserRepository {
     /// store user to DB.
     async fn save(&self, user: &User) -> Result<(), MyError>;
+    /// store user only if no user exists yet. return false if someone has signed up meanwhile.
+    async fn save_first(&self, user: &User) -> Result<bool, MyError>;
+    /// store user and mark the Invitation as used by it in one transaction.
+    /// return false and store nothing if the invitation has been used or revoked meanwhile.
+    async fn save_with_invitation(
+        &self,
+        user: &User,
+        invitation_id: &String,
+    ) -> Result<bool, MyError>;
     /// find one user from DB by primary key. return user. if not exist,None.
     async fn fetch_one(&self, id: &String) -> Result<User, MyError>;
+    /// find one user from DB by code. if not exist,BadRequest.
     async fn find_by_code(&self, code: &String) -> Result<User, MyError>;
+    async fn update_role(&self, code: &String, role: &Role) -> Result<(), MyError>;
+    async fn update_password(&self, id: &String, hashed_password: &String) -> Result<(), MyError>;
+    async fn update_name(&self, id: &String, name: &String) -> Result<(), MyError>;
+    async fn update_active(&self, code: &String, active: bool) -> Result<(), MyError>;
+    async fn update_approved(&self, code: &String, approved: bool) -> Result<(), MyError>;
+    /// every user including deactivated ones.
+    async fn fetch_all(&self) -> Result<Vec<User>, MyError>;
+    /// number of users including deactivated ones.
+    async fn count(&self) -> Result<i64, MyError>;
 }
 
 #[async_trait]
 pub trait DoctorInChargeRepository {
-    /// store Patient to DB.
-    async fn save(&self, user_id: &String, patient_code: &String) -> Result<(), MyError>;
+    /// store CareAssignment to DB. if the user has another CareAssignment to the patient
+    /// overlapping it,Conflict. checked in the same transaction, so two at once can not both pass.
+    async fn save(&self, care_assignment: &CareAssignment) -> Result<(), MyError>;
+    /// whether the user has CareAssignment to the patient which is active at now.
+    async fn exists(
+        &self,
+        user_id: &String,
+        patient_code: &String,
+        now: DateTime<Local>,
+    ) -> Result<bool, MyError>;
+    /// every CareAssignment to the patient including ended ones, newest first.
+    async fn fetch_by_patient_code(
+        &self,
+        patient_code: &String,
+    ) -> Result<Vec<CareAssignment>, MyError>;
+    /// end CareAssignment of the user to the patient which is active at now, keeping it as history.
+    /// return false if the user was not in charge of the patient.
+    async fn end(
+        &self,
+        user_id: &String,
+        patient_code: &String,
+        now: DateTime<Local>,
+    ) -> Result<bool, MyError>;
+    /// end active CareAssignment of from_user_id at starts_at of care_assignment and store care_assignment
+    /// in one transaction. return false if from_user_id was not in charge. overlapping is Conflict as save.
+    async fn transfer(
+        &self,
+        from_user_id: &String,
+        care_assignment: &CareAssignment,
+    ) -> Result<bool, MyError>;
+    /// store EmergencyAccess to DB.
+    async fn save_emergency_access(
+        &self,
+        emergency_access: &EmergencyAccess,
+    ) -> Result<(), MyError>;
+    /// whether the user has EmergencyAccess to the patient which is active at now.
+    async fn has_emergency_access(
+        &self,
+        user_id: &String,
+        patient_code: &String,
+        now: DateTime<Local>,
+    ) -> Result<bool, MyError>;
+    /// find one EmergencyAccess from DB by primary key. if not exist,None.
+    async fn fetch_emergency_access(&self, id: &String)
+        -> Result<Option<EmergencyAccess>, MyError>;
+    /// every EmergencyAccess, newest first. only not yet acknowledged ones if unacknowledged_only.
+    async fn fetch_emergency_accesses(
+        &self,
+        unacknowledged_only: bool,
+    ) -> Result<Vec<EmergencyAccess>, MyError>;
+    async fn acknowledge_emergency_access(
+        &self,
+        id: &String,
+        acknowledged_by: &String,
+        now: DateTime<Local>,
+    ) -> Result<(), MyError>;
+    /// store Delegation and its patients to DB.
+    async fn save_delegation(&self, delegation: &Delegation) -> Result<(), MyError>;
+    /// find one Delegation from DB by primary key. if not exist,None.
+    async fn fetch_delegation(&self, id: &String) -> Result<Option<Delegation>, MyError>;
+    /// every Delegation the user gave or received, newest first.
+    async fn fetch_delegations(&self, user_id: &String) -> Result<Vec<Delegation>, MyError>;
+    /// Delegations the user received which are active at now.
+    async fn fetch_active_delegations(
+        &self,
+        delegate_id: &String,
+        now: DateTime<Local>,
+    ) -> Result<Vec<Delegation>, MyError>;
+    async fn revoke_delegation(&self, id: &String, now: DateTime<Local>) -> Result<(), MyError>;
+}
+
+/// users can read and write only patients they are in charge of at the moment,
+/// patients delegated to them by the user in charge, or patients they broke the glass for
+/// until the access expires. if not,Forbidden.
+pub async fn ensure_in_charge<D: DoctorInChargeRepository + Sync>(
+    doctor_in_charge_repository: &D,
+    user_id: &String,
+    patient_code: &String,
+) -> Result<(), MyError> {
+    let now = Local::now();
+    if doctor_in_charge_repository
+        .exists(user_id, patient_code, now)
+        .await?
+    {
+        return Ok(());
+    }
+    for delegation in doctor_in_charge_repository
+        .fetch_active_delegations(user_id, now)
+        .await?
+    {
+        if delegation.covers(patient_code)
+            && doctor_in_charge_repository
+                .exists(&delegation.delegator_id, patient_code, now)
+                .await?
+        {
+            return Ok(());
+        }
+    }
+    if !doctor_in_charge_repository
+        .has_emergency_access(user_id, patient_code, now)
+        .await?
+    {
+        return Err(MyError::Forbidden(json!({
+            "error": format!("not in charge of patient code={}.", patient_code)
+        })));
+    }
+    Ok(())
+}
+
+/// policy of reads and writes of patient registration. users with AccessAllPatients handle
+/// every patient, and others only patients of ensure_in_charge.
+pub async fn ensure_patient_access<D: DoctorInChargeRepository + Sync>(
+    doctor_in_charge_repository: &D,
+    user_id: &String,
+    access_all_patients: bool,
+    patient_code: &String,
+) -> Result<(), MyError> {
+    if access_all_patients {
+        return Ok(());
+    }
+    ensure_in_charge(doctor_in_charge_repository, user_id, patient_code).await
+}
+
+const TOTP_ISSUER: &str = "PatientManage";
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_BYTES: usize = 5;
+const RECOVERY_CODE_SEPARATOR: char = '-';
+
+/// RFC 6238 TOTP of user. sign in needs the code after it is enabled.
+/// enabled only after the user confirms the first code, so half finished enrolment does not lock the user out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwoFactor {
+    pub user_id: String,
+    /// base32 secret shared with authenticator app.
+    pub secret: String,
+    pub enabled: bool,
+    /// step of the last accepted code. same code is not accepted twice.
+    pub last_used_step: Option<i64>,
+}
+
+impl TwoFactor {
+    /// new secret. not enabled until confirmed.
+    pub fn new(user_id: String) -> Self {
+        Self {
+            user_id,
+            secret: generate_secret(),
+            enabled: false,
+            last_used_step: None,
+        }
+    }
+
+    pub fn from(
+        user_id: String,
+        secret: String,
+        enabled: bool,
+        last_used_step: Option<i64>,
+    ) -> Self {
+        Self {
+            user_id,
+            secret,
+            enabled,
+            last_used_step,
+        }
+    }
+
+    /// uri for authenticator app. usually shown as QR code.
+    pub fn otpauth_uri(&self, account_name: &str) -> String {
+        format!(
+            "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+            issuer = TOTP_ISSUER,
+            account = account_name,
+            secret = self.secret,
+            digits = TOTP_DIGITS,
+            period = TOTP_PERIOD_SECONDS,
+        )
+    }
+
+    /// return step of the code if it is valid and newer than last used one.
+    pub fn verify(&self, code: &str, now: DateTime<Local>) -> Option<i64> {
+        let step = find_step(&self.secret, code.trim(), now.timestamp())?;
+        match self.last_used_step {
+            Some(last_used_step) if step <= last_used_step => None,
+            _ => Some(step),
+        }
+    }
+}
+
+/// one time code to sign in when authenticator app is lost. stored hashed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryCode {
+    pub id: String,
+    pub user_id: String,
+    pub hashed_code: String,
+}
+
+impl RecoveryCode {
+    /// issue recovery codes. return them and raw codes for client. raw codes are not stored.
+    pub fn generate(user_id: &String) -> Result<(Vec<Self>, Vec<String>), MyError> {
+        let mut recovery_codes = vec![];
+        let mut raw_codes = vec![];
+        for _ in 0..RECOVERY_CODE_COUNT {
+            let raw_code = BASE32_NOPAD
+                .encode(&random_bytes(RECOVERY_CODE_BYTES))
+                .to_lowercase();
+            let (head, tail) = raw_code.split_at(raw_code.len() / 2);
+            let raw_code = format!("{}{}{}", head, RECOVERY_CODE_SEPARATOR, tail);
+            recovery_codes.push(Self {
+                id: Ulid::new().to_string(),
+                user_id: user_id.clone(),
+                hashed_code: hash_password(&raw_code)?,
+            });
+            raw_codes.push(raw_code);
+        }
+        Ok((recovery_codes, raw_codes))
+    }
+
+    pub fn from(id: String, user_id: String, hashed_code: String) -> Self {
+        Self {
+            id,
+            user_id,
+            hashed_code,
+        }
+    }
+
+    /// case and surrounding spaces are ignored.
+    pub fn matches(&self, raw_code: &str) -> bool {
+        verify(raw_code.trim().to_lowercase(), &self.hashed_code).unwrap_or(false)
+    }
+}
+
+#[async_trait]
+pub trait TwoFactorRepository {
+    /// store TwoFactor to DB. overwrite if exists.
+    async fn save(&self, two_factor: &TwoFactor) -> Result<(), MyError>;
+    /// find TwoFactor of the user. if not enrolled,None.
+    async fn fetch_by_user_id(&self, user_id: &String) -> Result<Option<TwoFactor>, MyError>;
+    /// remember step of accepted code. return false if the step or newer one has been used meanwhile.
+    async fn update_last_used_step(&self, user_id: &String, step: i64) -> Result<bool, MyError>;
+    /// replace all RecoveryCode of the user.
+    async fn save_recovery_codes(
+        &self,
+        user_id: &String,
+        recovery_codes: &[RecoveryCode],
+    ) -> Result<(), MyError>;
+    /// unused RecoveryCode of the user.
+    async fn fetch_recovery_codes(&self, user_id: &String) -> Result<Vec<RecoveryCode>, MyError>;
+    /// mark RecoveryCode as used. return false if it has been used meanwhile.
+    async fn use_recovery_code(&self, id: &String) -> Result<bool, MyError>;
 }
 
 #[cfg(test)]
 
 mod tests {
 
-    use crate::utils::hash::hash_password;
+    use std::str::FromStr;
+
+    use chrono::TimeZone;
 
     use super::*;
     #[test]
@@ -85,11 +469,13 @@ mod tests {
             test_name.clone(),
             Some(test_code.clone()),
             test_hashed_password.clone(),
+            Role::Doctor,
         )
         .unwrap();
         assert_eq!(user.name, test_name);
         assert_eq!(user.code, test_code);
         assert_eq!(user.hashed_password, test_hashed_password);
+        assert_eq!(user.role, Role::Doctor);
         user.id;
     }
 
@@ -98,10 +484,101 @@ mod tests {
         let test_name = "x".to_string().repeat((NAME_LIMIT + 1) as usize);
         let test_code = "y".to_string().repeat((NAME_LIMIT) as usize);
         let test_hashed_password = hash_password("aaaaaaaaa").unwrap();
-        let user = User::new(test_name, Some(test_code), test_hashed_password).unwrap_err();
+        let user = User::new(
+            test_name,
+            Some(test_code),
+            test_hashed_password,
+            Role::Doctor,
+        )
+        .unwrap_err();
         assert_eq!(
             user,
             MyError::BadRequest(json!({"error":"train name must be less than 30 letters"}))
         );
     }
+
+    #[test]
+    fn test_user_change_name() {
+        let mut user = User::new(
+            "test_name".to_string(),
+            None,
+            hash_password("aaaaaaaaa").unwrap(),
+            Role::Doctor,
+        )
+        .unwrap();
+        assert!(user.active);
+        user.change_name("new_name".to_string()).unwrap();
+        assert_eq!(user.name, "new_name");
+        let err = user
+            .change_name("x".to_string().repeat((NAME_LIMIT + 1) as usize))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"train name must be less than 30 letters"}))
+        );
+        assert_eq!(user.name, "new_name");
+        user.ensure_active().unwrap();
+        user.approved = false;
+        assert_eq!(
+            user.ensure_active().unwrap_err(),
+            MyError::Forbidden(json!({"error":"account is waiting for approval"}))
+        );
+        user.active = false;
+        assert_eq!(
+            user.ensure_active().unwrap_err(),
+            MyError::Forbidden(json!({"error":"account is deactivated"}))
+        );
+    }
+
+    #[test]
+    fn test_role_permissions() {
+        assert!(Role::Receptionist.has_permission(Permission::RegisterPatient));
+        assert!(!Role::Receptionist.has_permission(Permission::ReadMedicalExamination));
+        assert!(Role::Nurse.has_permission(Permission::WriteMedicalExamination));
+        assert!(!Role::Nurse.has_permission(Permission::RegisterPatient));
+        assert!(Role::Administrator.has_permission(Permission::ManageUser));
+        assert!(!Role::Doctor.has_permission(Permission::ManageUser));
+        assert!(Role::Nurse.has_permission(Permission::BreakTheGlass));
+        assert!(!Role::Receptionist.has_permission(Permission::BreakTheGlass));
+        assert!(Role::Receptionist.has_permission(Permission::AccessAllPatients));
+        assert!(!Role::Doctor.has_permission(Permission::AccessAllPatients));
+    }
+
+    #[test]
+    fn test_role_from_str() {
+        assert_eq!(Role::from_str("receptionist").unwrap(), Role::Receptionist);
+        assert_eq!(Role::Administrator.to_string(), "administrator");
+        assert!(Role::from_str("janitor").is_err());
+    }
+
+    #[test]
+    fn test_two_factor_verify() {
+        // RFC 6238 test secret "12345678901234567890"
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string();
+        let two_factor = TwoFactor::from("test_id".to_string(), secret.clone(), true, None);
+        let now = Local.timestamp_opt(1111111109, 0).unwrap();
+        let step = two_factor.verify("081804", now).unwrap();
+        // previous step is accepted for clock skew.
+        assert!(two_factor
+            .verify("081804", Local.timestamp_opt(1111111109 + 30, 0).unwrap())
+            .is_some());
+        assert!(two_factor.verify("000000", now).is_none());
+
+        // used code is not accepted again.
+        let two_factor = TwoFactor::from("test_id".to_string(), secret, true, Some(step));
+        assert!(two_factor.verify("081804", now).is_none());
+    }
+
+    #[test]
+    fn test_two_factor_otpauth_uri() {
+        let two_factor = TwoFactor::new("test_id".to_string());
+        assert!(!two_factor.enabled);
+        assert_eq!(
+            two_factor.otpauth_uri("test_code"),
+            format!(
+                "otpauth://totp/PatientManage:test_code?secret={}&issuer=PatientManage&algorithm=SHA1&digits=6&period=30",
+                two_factor.secret
+            )
+        );
+    }
 }
@@ -15,8 +15,23 @@ use actix_web::{App, HttpServer};
 async fn main() -> std::io::Result<()> {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
+    middleware::keyring::init_keyring(middleware::keyring::Keyring::load());
     let pool = utils::db::establish_sqlx_connection().await;
-    let app_state = utils::state::AppState { sqlx_db: pool };
+    let filled = usecase::patient::fill_search_columns(
+        &repository::patient_repository::PatientRepositoryImpl { conn: &pool },
+    )
+    .await
+    .expect("failed to fill search columns of patients");
+    if filled > 0 {
+        log::info!("filled search columns of {} patients", filled);
+    }
+    let app_state = utils::state::AppState {
+        sqlx_db: pool,
+        lockout_policy: domain::login_attempt::LockoutPolicy::load(),
+        notifier_config: repository::notifier::NotifierConfig::load(),
+        password_policy: domain::password_policy::PasswordPolicy::load(),
+        oidc_config: domain::oidc::OidcConfig::load(),
+    };
 
     HttpServer::new(move || {
         App::new()
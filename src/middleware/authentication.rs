@@ -0,0 +1,154 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest};
+
+use serde_json::json;
+
+use crate::domain::service_account::is_api_key;
+use crate::domain::user::{Permission, Role};
+use crate::middleware::authn::{decode, get_bearer_token};
+use crate::repository::refresh_token_repository::RefreshTokenRepositoryImpl;
+use crate::repository::service_account_repository::{
+    ApiKeyRepositoryImpl, ServiceAccountRepositoryImpl,
+};
+use crate::repository::session_repository::SessionRepositoryImpl;
+use crate::usecase::service_account::ServiceAccountUsecase;
+use crate::usecase::session::SessionUsecase;
+use crate::utils::errors::MyError;
+use crate::utils::state::AppState;
+
+/// user or service account authenticated by bearer token.
+/// take this as handler argument instead of parsing authorization header in each handler.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticatedUser {
+    /// id of user, or service account if authenticated by api key.
+    pub user_id: String,
+    pub role: Role,
+    /// scopes of api key. None for user, who has every permission of the role.
+    pub scopes: Option<Vec<Permission>>,
+    /// session of the jwt. None for api key.
+    pub session_id: Option<String>,
+}
+
+impl AuthenticatedUser {
+    /// bearer token is jwt of user or api key of service account.
+    async fn authenticate(req: &HttpRequest) -> Result<Self, MyError> {
+        let token = get_bearer_token(req)?;
+        let state = match req.app_data::<web::Data<AppState>>() {
+            Some(state) => state,
+            None => return Err(MyError::InternalServerError),
+        };
+        let conn = state.get_sqls_db_conn()?;
+        if !is_api_key(token) {
+            let claims = decode(token)?.claims;
+            // revocation is looked up in DB, so that every instance rejects the token at once.
+            let session_usecase = SessionUsecase {
+                session_repository: SessionRepositoryImpl { conn: &conn },
+                refresh_token_repository: RefreshTokenRepositoryImpl { conn: &conn },
+            };
+            session_usecase.ensure_not_revoked(&claims.sid).await?;
+            return Ok(Self {
+                user_id: claims.user_id,
+                role: claims.role,
+                scopes: None,
+                session_id: Some(claims.sid),
+            });
+        }
+        let service_account_usecase = ServiceAccountUsecase {
+            service_account_repository: ServiceAccountRepositoryImpl { conn: &conn },
+            api_key_repository: ApiKeyRepositoryImpl { conn: &conn },
+        };
+        let (service_account, api_key) = service_account_usecase.authenticate(token).await?;
+        Ok(Self {
+            user_id: service_account.id,
+            role: service_account.role,
+            scopes: Some(api_key.scopes),
+            session_id: None,
+        })
+    }
+
+    /// check the role of user has permission, and scopes of api key include it. if not,Forbidden.
+    pub fn require(&self, permission: Permission) -> Result<(), MyError> {
+        if !self.role.has_permission(permission) {
+            return Err(MyError::Forbidden(json!({
+                "error": format!("{} is not permitted to {}", self.role, permission)
+            })));
+        }
+        if let Some(scopes) = &self.scopes {
+            if !scopes.contains(&permission) {
+                return Err(MyError::Forbidden(json!({
+                    "error": format!("api key is not permitted to {}", permission)
+                })));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = MyError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    /// reuse the user set by Authentication middleware. otherwise authenticate here.
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        if let Some(user) = req.extensions().get::<AuthenticatedUser>() {
+            return Box::pin(ready(Ok(user.clone())));
+        }
+        let req = req.clone();
+        Box::pin(async move { AuthenticatedUser::authenticate(&req).await })
+    }
+}
+
+/// middleware to reject unauthenticated request to every route of wrapped scope.
+/// public routes must be registered outside of the wrapped scope.
+pub struct Authentication;
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // api key is looked up in DB, so authentication is done asynchronously.
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let user = AuthenticatedUser::authenticate(req.request()).await?;
+            req.extensions_mut().insert(user);
+            service.call(req).await
+        })
+    }
+}
@@ -1,22 +1,23 @@
 use actix_web::{http::header::HeaderValue, HttpRequest};
 use chrono::Utc;
-use jsonwebtoken::{encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
-use serde::{Deserialize, Serialize};
+use jsonwebtoken::{decode_header, encode, Header, TokenData, Validation};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
 
+use crate::domain::user::Role;
+use crate::middleware::keyring::keyring;
 use crate::utils::errors::MyError;
 
-static SECRETE_KEY: [u8; 16] = *include_bytes!("../../secret.key");
 const TOKEN_EXPIRED_WITHIN: i64 = 60 * 60;
+const TWO_FACTOR_TOKEN_EXPIRED_WITHIN: i64 = 5 * 60;
+const TWO_FACTOR_PURPOSE: &str = "two_factor";
 const AUTORIZATION_HEADER: &str = "autorization";
 const BEARER: &str = "Bearer";
 
-/// extracting user_id from request header.
-pub fn get_user_id_from_header(req: &HttpRequest) -> Result<String, MyError> {
+/// extracting bearer token from request header. it is jwt or api key.
+pub fn get_bearer_token(req: &HttpRequest) -> Result<&str, MyError> {
     let authorization = req.headers().get(AUTORIZATION_HEADER);
-    let token = validate_and_extract_header(authorization)?;
-    let decoded_token = decode(token)?;
-    Ok(decoded_token.claims.user_id)
+    validate_and_extract_header(authorization)
 }
 
 fn validate_and_extract_header(authorization: Option<&HeaderValue>) -> Result<&str, MyError> {
@@ -27,7 +28,14 @@ fn validate_and_extract_header(authorization: Option<&HeaderValue>) -> Result<&s
                 json!({"error":"authorization is not found"}),
             ))
         }
-        Some(token) => token.to_str().unwrap(),
+        Some(token) => match token.to_str() {
+            Ok(token) => token,
+            Err(_) => {
+                return Err(MyError::Unauthorized(
+                    json!({"error":"authorization is invalid"}),
+                ))
+            }
+        },
     };
 
     let mut splited_token = token.split_whitespace();
@@ -64,35 +72,95 @@ fn validate_and_extract_header(authorization: Option<&HeaderValue>) -> Result<&s
 pub struct Claims {
     iat: i64,
     exp: i64,
-    user_id: String,
+    pub user_id: String,
+    pub role: Role,
+    /// id of Session the token is issued for.
+    pub sid: String,
 }
 
 impl Claims {
-    pub fn new(user_id: &String, now: i64) -> Self {
+    pub fn new(user_id: &String, role: &Role, session_id: &String, now: i64) -> Self {
         Claims {
             iat: now,
             exp: now + TOKEN_EXPIRED_WITHIN,
             user_id: user_id.clone(),
+            role: *role,
+            sid: session_id.clone(),
         }
     }
 }
 
-pub fn make_jwt(user_id: &String) -> Result<String, MyError> {
+/// sign with current key of keyring. kid header is set to find the key on decode.
+pub fn make_jwt(user_id: &String, role: &Role, session_id: &String) -> Result<String, MyError> {
     let now = Utc::now();
-    let claims = Claims::new(user_id, now.timestamp());
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(&SECRETE_KEY),
-    )?;
+    let claims = Claims::new(user_id, role, session_id, now.timestamp());
+    let signing_key = keyring().current();
+    let mut header = Header::new(signing_key.algorithm);
+    header.kid = Some(signing_key.kid.clone());
+    let token = encode(&header, &claims, signing_key.encoding_key().unwrap())?;
+    Ok(token)
+}
+
+/// claims of token between password and two factor code of sign in.
+/// it has purpose instead of role, so it can not be used as access token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorClaims {
+    iat: i64,
+    exp: i64,
+    pub user_id: String,
+    purpose: String,
+}
+
+/// short lived token which proves the password was correct.
+pub fn make_two_factor_token(user_id: &String) -> Result<String, MyError> {
+    let now = Utc::now().timestamp();
+    let claims = TwoFactorClaims {
+        iat: now,
+        exp: now + TWO_FACTOR_TOKEN_EXPIRED_WITHIN,
+        user_id: user_id.clone(),
+        purpose: TWO_FACTOR_PURPOSE.to_string(),
+    };
+    let signing_key = keyring().current();
+    let mut header = Header::new(signing_key.algorithm);
+    header.kid = Some(signing_key.kid.clone());
+    let token = encode(&header, &claims, signing_key.encoding_key().unwrap())?;
     Ok(token)
 }
 
+/// return user_id of two factor token.
+pub fn decode_two_factor_token(token: &str) -> Result<String, MyError> {
+    let claims = decode_as::<TwoFactorClaims>(token)?.claims;
+    if claims.purpose != TWO_FACTOR_PURPOSE {
+        return Err(MyError::Unauthorized(
+            json!({"error":"two factor token is invalid"}),
+        ));
+    }
+    Ok(claims.user_id)
+}
+
+/// verify with the key of kid header, current or previous. token without kid is verified with current key.
+/// whether the session is revoked is not checked here, as it needs DB.
 pub fn decode(token: &str) -> Result<TokenData<Claims>, MyError> {
-    let decoded_token = jsonwebtoken::decode::<Claims>(
+    decode_as::<Claims>(token)
+}
+
+fn decode_as<T: DeserializeOwned>(token: &str) -> Result<TokenData<T>, MyError> {
+    let header = decode_header(token)?;
+    let signing_key = match header.kid {
+        Some(kid) => match keyring().find(&kid) {
+            Some(signing_key) => signing_key,
+            None => {
+                return Err(MyError::Unauthorized(
+                    json!({"error":"Token is signed with unknown key"}),
+                ))
+            }
+        },
+        None => keyring().current(),
+    };
+    let decoded_token = jsonwebtoken::decode::<T>(
         token,
-        &DecodingKey::from_secret(&SECRETE_KEY),
-        &Validation::default(),
+        signing_key.decoding_key(),
+        &Validation::new(signing_key.algorithm),
     )?;
     Ok(decoded_token)
 }
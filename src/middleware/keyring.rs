@@ -0,0 +1,300 @@
+use crate::constants::env_key;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use dotenv::dotenv;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters,
+    OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use serde::Deserialize;
+use simple_asn1::{from_der, ASN1Block};
+use std::env;
+use std::fs;
+use std::sync::OnceLock;
+
+const DEFAULT_KEY_ID: &str = "default";
+const PREVIOUS_SECRETS_SEPARATOR: char = ',';
+const KEY_ID_SEPARATOR: char = ':';
+const RSA_PUBLIC_KEY_PEM_TAG: &str = "RSA PUBLIC KEY";
+
+static KEYRING: OnceLock<Keyring> = OnceLock::new();
+
+/// key entry of JWT_KEYRING_FILE.
+/// HS256 needs secret. RS256 and EdDSA need public_key_file, and private_key_file for current key.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct KeyConfig {
+    pub kid: String,
+    #[serde(default = "default_algorithm")]
+    pub algorithm: Algorithm,
+    pub secret: Option<String>,
+    pub private_key_file: Option<String>,
+    pub public_key_file: Option<String>,
+}
+
+fn default_algorithm() -> Algorithm {
+    Algorithm::HS256
+}
+
+/// yaml format of JWT_KEYRING_FILE.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct KeyringConfig {
+    pub current: String,
+    pub keys: Vec<KeyConfig>,
+}
+
+/// key to sign and verify jwt. kid is set to jwt header.
+#[derive(Clone)]
+pub struct SigningKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    /// None if private key is not given. such key can only verify.
+    encoding_key: Option<EncodingKey>,
+    decoding_key: DecodingKey,
+    /// public key to publish. None for HS256.
+    jwk: Option<Jwk>,
+}
+
+impl SigningKey {
+    /// HS256 key.
+    pub fn hmac(kid: String, secret: String) -> Self {
+        Self {
+            kid,
+            algorithm: Algorithm::HS256,
+            encoding_key: Some(EncodingKey::from_secret(secret.as_bytes())),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            jwk: None,
+        }
+    }
+
+    /// RS256 or EdDSA key from pem. private_pem is None if the key only verifies.
+    pub fn asymmetric(
+        kid: String,
+        algorithm: Algorithm,
+        private_pem: Option<&[u8]>,
+        public_pem: &[u8],
+    ) -> Result<Self, String> {
+        let (encoding_key, decoding_key, algorithm_parameters) = match algorithm {
+            Algorithm::RS256 => {
+                let encoding_key = match private_pem {
+                    Some(private_pem) => Some(
+                        EncodingKey::from_rsa_pem(private_pem).map_err(|err| err.to_string())?,
+                    ),
+                    None => None,
+                };
+                let decoding_key =
+                    DecodingKey::from_rsa_pem(public_pem).map_err(|err| err.to_string())?;
+                (encoding_key, decoding_key, rsa_parameters(public_pem)?)
+            }
+            Algorithm::EdDSA => {
+                let encoding_key = match private_pem {
+                    Some(private_pem) => {
+                        Some(EncodingKey::from_ed_pem(private_pem).map_err(|err| err.to_string())?)
+                    }
+                    None => None,
+                };
+                let decoding_key =
+                    DecodingKey::from_ed_pem(public_pem).map_err(|err| err.to_string())?;
+                (encoding_key, decoding_key, ed25519_parameters(public_pem)?)
+            }
+            _ => return Err(format!("{:?} is not supported", algorithm)),
+        };
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                algorithm: Some(algorithm),
+                key_id: Some(kid.clone()),
+                ..Default::default()
+            },
+            algorithm: algorithm_parameters,
+        };
+        Ok(Self {
+            kid,
+            algorithm,
+            encoding_key,
+            decoding_key,
+            jwk: Some(jwk),
+        })
+    }
+
+    fn from_config(config: KeyConfig) -> Result<Self, String> {
+        match config.algorithm {
+            Algorithm::HS256 => match config.secret {
+                Some(secret) if !secret.is_empty() => Ok(SigningKey::hmac(config.kid, secret)),
+                _ => Err(format!("secret of {} must be set", config.kid)),
+            },
+            _ => {
+                let public_key_file = config
+                    .public_key_file
+                    .ok_or(format!("public_key_file of {} must be set", config.kid))?;
+                let public_pem = fs::read(&public_key_file).map_err(|err| err.to_string())?;
+                let private_pem = match config.private_key_file {
+                    Some(private_key_file) => {
+                        Some(fs::read(&private_key_file).map_err(|err| err.to_string())?)
+                    }
+                    None => None,
+                };
+                SigningKey::asymmetric(
+                    config.kid,
+                    config.algorithm,
+                    private_pem.as_deref(),
+                    &public_pem,
+                )
+            }
+        }
+    }
+
+    pub fn encoding_key(&self) -> Option<&EncodingKey> {
+        self.encoding_key.as_ref()
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+}
+
+/// n and e of RSA public key. pem is SubjectPublicKeyInfo or PKCS#1 RSAPublicKey.
+fn rsa_parameters(public_pem: &[u8]) -> Result<AlgorithmParameters, String> {
+    let pem = pem::parse(public_pem).map_err(|err| err.to_string())?;
+    let rsa_public_key_der = if pem.tag == RSA_PUBLIC_KEY_PEM_TAG {
+        pem.contents
+    } else {
+        subject_public_key(&pem.contents)?
+    };
+    let blocks = from_der(&rsa_public_key_der).map_err(|err| err.to_string())?;
+    match blocks.first() {
+        Some(ASN1Block::Sequence(_, fields)) => match (fields.first(), fields.get(1)) {
+            (Some(ASN1Block::Integer(_, n)), Some(ASN1Block::Integer(_, e))) => {
+                Ok(AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(n.to_bytes_be().1),
+                    e: URL_SAFE_NO_PAD.encode(e.to_bytes_be().1),
+                }))
+            }
+            _ => Err("rsa public key is invalid".to_string()),
+        },
+        _ => Err("rsa public key is invalid".to_string()),
+    }
+}
+
+/// x of Ed25519 public key. pem is SubjectPublicKeyInfo.
+fn ed25519_parameters(public_pem: &[u8]) -> Result<AlgorithmParameters, String> {
+    let pem = pem::parse(public_pem).map_err(|err| err.to_string())?;
+    let x = subject_public_key(&pem.contents)?;
+    Ok(AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+        key_type: OctetKeyPairType::OctetKeyPair,
+        curve: EllipticCurve::Ed25519,
+        x: URL_SAFE_NO_PAD.encode(x),
+    }))
+}
+
+/// extract subjectPublicKey from SubjectPublicKeyInfo der.
+fn subject_public_key(der: &[u8]) -> Result<Vec<u8>, String> {
+    let blocks = from_der(der).map_err(|err| err.to_string())?;
+    match blocks.first() {
+        Some(ASN1Block::Sequence(_, fields)) => match fields.get(1) {
+            Some(ASN1Block::BitString(_, _, key)) => Ok(key.clone()),
+            _ => Err("public key is invalid".to_string()),
+        },
+        _ => Err("public key is invalid".to_string()),
+    }
+}
+
+/// current key to sign new token and previous keys to verify live token.
+#[derive(Clone)]
+pub struct Keyring {
+    current: String,
+    keys: Vec<SigningKey>,
+}
+
+impl Keyring {
+    pub fn new(current: SigningKey, previous: Vec<SigningKey>) -> Self {
+        let mut keys = vec![current.clone()];
+        keys.extend(previous);
+        Self {
+            current: current.kid,
+            keys,
+        }
+    }
+
+    /// pre: set JWT_KEYRING_FILE or JWT_SECRET env
+    /// JWT_KEYRING_FILE is preferred. JWT_KEY_ID and JWT_PREVIOUS_SECRETS(kid:secret,kid:secret) are optional.
+    pub fn load() -> Self {
+        dotenv().ok();
+        let config = if let Ok(path) = env::var(env_key::JWT_KEYRING_FILE) {
+            let file = fs::read_to_string(&path).expect("JWT_KEYRING_FILE can not be read");
+            serde_yaml::from_str::<KeyringConfig>(&file).expect("JWT_KEYRING_FILE is invalid yaml")
+        } else {
+            let secret =
+                env::var(env_key::JWT_SECRET).expect("JWT_SECRET or JWT_KEYRING_FILE must be set");
+            let kid = env::var(env_key::JWT_KEY_ID).unwrap_or_else(|_| DEFAULT_KEY_ID.to_string());
+            let mut keys = vec![KeyConfig {
+                kid: kid.clone(),
+                algorithm: Algorithm::HS256,
+                secret: Some(secret),
+                private_key_file: None,
+                public_key_file: None,
+            }];
+            keys.extend(
+                env::var(env_key::JWT_PREVIOUS_SECRETS)
+                    .unwrap_or_default()
+                    .split(PREVIOUS_SECRETS_SEPARATOR)
+                    .filter(|key| !key.is_empty())
+                    .map(|key| {
+                        let (kid, secret) = key
+                            .split_once(KEY_ID_SEPARATOR)
+                            .expect("JWT_PREVIOUS_SECRETS must be kid:secret,kid:secret");
+                        KeyConfig {
+                            kid: kid.to_string(),
+                            algorithm: Algorithm::HS256,
+                            secret: Some(secret.to_string()),
+                            private_key_file: None,
+                            public_key_file: None,
+                        }
+                    }),
+            );
+            KeyringConfig { current: kid, keys }
+        };
+        let keyring = Keyring {
+            current: config.current,
+            keys: config
+                .keys
+                .into_iter()
+                .map(|key| SigningKey::from_config(key).expect("jwt key is invalid"))
+                .collect::<Vec<SigningKey>>(),
+        };
+        match keyring.find(&keyring.current) {
+            Some(key) if key.encoding_key().is_some() => keyring,
+            Some(_) => panic!("current jwt key must have private key"),
+            None => panic!("current jwt key is not found in keys"),
+        }
+    }
+
+    /// key to sign new token.
+    pub fn current(&self) -> &SigningKey {
+        self.find(&self.current).unwrap()
+    }
+
+    /// key to verify token signed with kid.
+    pub fn find(&self, kid: &str) -> Option<&SigningKey> {
+        self.keys.iter().find(|key| key.kid == kid)
+    }
+
+    /// public keys of RS256 and EdDSA to publish at jwks endpoint.
+    pub fn jwk_set(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().filter_map(|key| key.jwk.clone()).collect(),
+        }
+    }
+}
+
+/// set keyring used by authn. call once at startup. later calls are ignored.
+pub fn init_keyring(keyring: Keyring) {
+    let _ = KEYRING.set(keyring);
+}
+
+pub fn keyring() -> &'static Keyring {
+    KEYRING
+        .get()
+        .expect("keyring must be initialized at startup")
+}
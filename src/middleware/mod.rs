@@ -1 +1,3 @@
+pub mod authentication;
 pub mod authn;
+pub mod keyring;
@@ -1,12 +1,16 @@
-use actix_web::{web, HttpRequest};
+use actix_web::web;
 use chrono::{DateTime, Local};
 
 use crate::repository::medical_examination_repository::MedicalExaminationRepositoryImpl;
 use crate::repository::patient_repository::PatientRepositoryImpl;
+use crate::repository::user_repository::DoctorInChargeRepositoryImpl;
 use crate::usecase::medical_examination::MedicalExaminationUsecase;
 use crate::utils::errors::MyError;
 use crate::utils::state::AppState;
-use crate::{domain::medical_examination::MedicalExamination, middleware::authn};
+use crate::{
+    domain::{medical_examination::MedicalExamination, user::Permission},
+    middleware::authentication::AuthenticatedUser,
+};
 use actix_web::HttpResponse;
 use serde::{Deserialize, Serialize};
 
@@ -45,7 +49,7 @@ pub struct FetchMedicalExamination {
 }
 
 impl FetchMedicalExamination {
-    fn from(medical_examination: MedicalExamination) -> Self {
+    pub fn from(medical_examination: MedicalExamination) -> Self {
         Self {
             interviewed_at: medical_examination.interviewed_at,
             symptom: medical_examination.symptom,
@@ -67,23 +71,25 @@ impl FetchMedicalExaminationsResponse {
 
 pub async fn create_medical_examination(
     state: web::Data<AppState>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     form: web::Json<CreateMedicalExaminationRequest>,
 ) -> ApiResponse {
+    user.require(Permission::WriteMedicalExamination)?;
     // object setting.
     let conn = state.get_sqls_db_conn()?;
-    let user_id = authn::get_user_id_from_header(&req)?;
     let medical_examination_repository = MedicalExaminationRepositoryImpl { conn: &conn };
     let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
     let medical_examination_usecase = MedicalExaminationUsecase {
         medical_examination_repository,
         patient_repository,
+        doctor_in_charge_repository,
     };
 
     medical_examination_usecase
         .create_medical_examination(
             form.interviewed_at.clone(),
-            user_id.clone(),
+            user.user_id.clone(),
             form.patient_code.clone(),
             form.symptom.clone(),
         )
@@ -94,21 +100,23 @@ pub async fn create_medical_examination(
 
 pub async fn fetch_medical_examinations(
     state: web::Data<AppState>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     params: web::Query<FetchMedicalExaminationsParameter>,
 ) -> ApiResponse {
+    user.require(Permission::ReadMedicalExamination)?;
     // object setting.
     let conn = state.get_sqls_db_conn()?;
-    let _ = authn::get_user_id_from_header(&req)?;
     let medical_examination_repository = MedicalExaminationRepositoryImpl { conn: &conn };
     let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
     let medical_examination_usecase = MedicalExaminationUsecase {
         medical_examination_repository,
         patient_repository,
+        doctor_in_charge_repository,
     };
 
     let medical_examinations = medical_examination_usecase
-        .fetch_by_patient_code(params.patient_code.clone())
+        .fetch_by_patient_code(user.user_id.clone(), params.patient_code.clone())
         .await?;
     let fetch_medical_examination_response =
         FetchMedicalExaminationsResponse::from(medical_examinations);
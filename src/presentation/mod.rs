@@ -1,4 +1,10 @@
 pub mod healthcheck;
 pub mod medical_examination;
+pub mod oidc;
+pub mod password_reset;
 pub mod patient;
+pub mod registration;
+pub mod service_account;
+pub mod session;
 pub mod user;
+pub mod well_known;
@@ -0,0 +1,188 @@
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::http::header::LOCATION;
+use actix_web::{web, HttpRequest};
+
+use crate::domain::oidc::OidcConfig;
+use crate::domain::user::{Permission, Role, User};
+use crate::middleware::authentication::AuthenticatedUser;
+use crate::presentation::user::client_info;
+use crate::repository::external_identity_repository::ExternalIdentityRepositoryImpl;
+use crate::repository::identity_provider::IdentityProviderImpl;
+use crate::repository::refresh_token_repository::RefreshTokenRepositoryImpl;
+use crate::repository::session_repository::SessionRepositoryImpl;
+use crate::repository::user_repository::UserRepositoryImpl;
+use crate::usecase::oidc::OidcUsecase;
+use crate::utils::errors::MyError;
+use crate::utils::state::AppState;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// keeps id of login state between login and callback. same lifetime as login state.
+const LOGIN_STATE_COOKIE: &str = "oidc_state";
+const LOGIN_STATE_COOKIE_PATH: &str = "/api/oidc";
+const LOGIN_STATE_COOKIE_MINUTES: i64 = 10;
+
+pub type ApiResponse = Result<HttpResponse, MyError>;
+
+/// query of redirect from the identity provider. error is set instead of code if login failed.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CallbackParameter {
+    state: String,
+    code: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CallbackResponse {
+    code: String,
+    role: Role,
+    token: String,
+    refresh_token: String,
+}
+
+impl CallbackResponse {
+    fn from(user: User, token: String, refresh_token: String) -> Self {
+        Self {
+            code: user.code,
+            role: user.role,
+            token,
+            refresh_token,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct LinkRequest {
+    code: String,
+    subject: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct LinkResponse {}
+
+impl LinkResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+/// if OIDC_ISSUER is not set,NotFound.
+fn oidc_config(state: &AppState) -> Result<OidcConfig, MyError> {
+    state
+        .oidc_config
+        .clone()
+        .ok_or_else(|| MyError::NotFound(json!({"error":"oidc login is not configured"})))
+}
+
+/// redirect browser to the identity provider.
+pub async fn login(state: web::Data<AppState>) -> ApiResponse {
+    let config = oidc_config(&state)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let external_identity_repository = ExternalIdentityRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let identity_provider = IdentityProviderImpl {
+        config: config.clone(),
+    };
+    let oidc_usecase = OidcUsecase {
+        user_repository,
+        external_identity_repository,
+        refresh_token_repository,
+        session_repository,
+        identity_provider,
+        config: config.clone(),
+    };
+
+    let (login_state_id, url) = oidc_usecase.start_login().await?;
+    let cookie = Cookie::build(LOGIN_STATE_COOKIE, login_state_id)
+        .path(LOGIN_STATE_COOKIE_PATH)
+        .http_only(true)
+        .secure(config.redirect_uri.starts_with("https://"))
+        .same_site(SameSite::Lax)
+        .max_age(CookieDuration::minutes(LOGIN_STATE_COOKIE_MINUTES))
+        .finish();
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .cookie(cookie)
+        .finish())
+}
+
+/// redirect uri registered to the identity provider. return our tokens like sign in.
+pub async fn callback(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    params: web::Query<CallbackParameter>,
+) -> ApiResponse {
+    let config = oidc_config(&state)?;
+    if let Some(error) = &params.error {
+        return Err(MyError::Unauthorized(json!({
+            "error": format!("identity provider returned {}", error)
+        })));
+    }
+    let code = params
+        .code
+        .clone()
+        .ok_or_else(|| MyError::BadRequest(json!({"error":"code is required"})))?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let external_identity_repository = ExternalIdentityRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let identity_provider = IdentityProviderImpl {
+        config: config.clone(),
+    };
+    let oidc_usecase = OidcUsecase {
+        user_repository,
+        external_identity_repository,
+        refresh_token_repository,
+        session_repository,
+        identity_provider,
+        config,
+    };
+
+    let cookie_state = req
+        .cookie(LOGIN_STATE_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+    let (user, token, refresh_token) = oidc_usecase
+        .callback(params.state.clone(), cookie_state, code, client_info(&req))
+        .await?;
+    let mut removal = Cookie::build(LOGIN_STATE_COOKIE, "")
+        .path(LOGIN_STATE_COOKIE_PATH)
+        .finish();
+    removal.make_removal();
+    let callback_response = CallbackResponse::from(user, token, refresh_token);
+    Ok(HttpResponse::Ok().cookie(removal).json(callback_response))
+}
+
+pub async fn link(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<LinkRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let config = oidc_config(&state)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let external_identity_repository = ExternalIdentityRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let identity_provider = IdentityProviderImpl {
+        config: config.clone(),
+    };
+    let oidc_usecase = OidcUsecase {
+        user_repository,
+        external_identity_repository,
+        refresh_token_repository,
+        session_repository,
+        identity_provider,
+        config,
+    };
+
+    oidc_usecase
+        .link(form.code.clone(), form.subject.clone())
+        .await?;
+    let link_response = LinkResponse::from();
+    Ok(HttpResponse::Ok().json(link_response))
+}
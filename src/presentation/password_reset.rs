@@ -0,0 +1,106 @@
+use actix_web::web;
+
+use crate::repository::notifier::NotifierImpl;
+use crate::repository::password_history_repository::PasswordHistoryRepositoryImpl;
+use crate::repository::password_reset_repository::PasswordResetTokenRepositoryImpl;
+use crate::repository::refresh_token_repository::RefreshTokenRepositoryImpl;
+use crate::repository::session_repository::SessionRepositoryImpl;
+use crate::repository::user_repository::UserRepositoryImpl;
+use crate::usecase::password_reset::PasswordResetUsecase;
+use crate::utils::errors::MyError;
+use crate::utils::state::AppState;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+pub type ApiResponse = Result<HttpResponse, MyError>;
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RequestPasswordResetRequest {
+    code: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RequestPasswordResetResponse {}
+
+impl RequestPasswordResetResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ConfirmPasswordResetRequest {
+    token: String,
+    password: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ConfirmPasswordResetResponse {}
+
+impl ConfirmPasswordResetResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+pub async fn request_password_reset(
+    state: web::Data<AppState>,
+    form: web::Json<RequestPasswordResetRequest>,
+) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let password_reset_token_repository = PasswordResetTokenRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let notifier = NotifierImpl {
+        conn: &conn,
+        config: state.notifier_config.clone(),
+    };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let password_reset_usecase = PasswordResetUsecase {
+        user_repository,
+        password_reset_token_repository,
+        refresh_token_repository,
+        notifier,
+        password_history_repository,
+        session_repository,
+        password_policy: state.password_policy.clone(),
+    };
+
+    password_reset_usecase
+        .request_password_reset(form.code.clone())
+        .await?;
+    let request_password_reset_response = RequestPasswordResetResponse::from();
+    Ok(HttpResponse::Ok().json(request_password_reset_response))
+}
+
+pub async fn confirm_password_reset(
+    state: web::Data<AppState>,
+    form: web::Json<ConfirmPasswordResetRequest>,
+) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let password_reset_token_repository = PasswordResetTokenRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let notifier = NotifierImpl {
+        conn: &conn,
+        config: state.notifier_config.clone(),
+    };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let password_reset_usecase = PasswordResetUsecase {
+        user_repository,
+        password_reset_token_repository,
+        refresh_token_repository,
+        notifier,
+        password_history_repository,
+        session_repository,
+        password_policy: state.password_policy.clone(),
+    };
+
+    password_reset_usecase
+        .confirm_password_reset(form.token.clone(), form.password.clone())
+        .await?;
+    let confirm_password_reset_response = ConfirmPasswordResetResponse::from();
+    Ok(HttpResponse::Ok().json(confirm_password_reset_response))
+}
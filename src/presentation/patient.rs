@@ -1,21 +1,53 @@
+use actix_web::http::header::{ETag, EntityTag, Header, IfMatch, IF_MATCH};
 use actix_web::{web, HttpRequest};
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate};
 
-use crate::domain::patient::Patient;
+use crate::domain::delegation::Delegation;
+use crate::domain::emergency_access::EmergencyAccess;
+use crate::domain::patient::{BloodType, Demographics, Patient, PatientChart, PatientPatch, Sex};
+use crate::domain::user::Permission;
+use crate::presentation::medical_examination::FetchMedicalExamination;
+use crate::presentation::user::FetchDoctorInChargeResponse;
 use crate::repository::medical_examination_repository::MedicalExaminationRepositoryImpl;
 use crate::repository::patient_repository::PatientRepositoryImpl;
+use crate::repository::user_repository::{DoctorInChargeRepositoryImpl, UserRepositoryImpl};
 use crate::usecase::patient::PatientUsecase;
 use crate::utils::errors::MyError;
 use crate::utils::state::AppState;
-use crate::{domain::medical_examination::MedicalExamination, middleware};
+use crate::{
+    domain::medical_examination::MedicalExamination, middleware::authentication::AuthenticatedUser,
+};
 use actix_web::HttpResponse;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::convert::From;
+use std::str::FromStr;
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct CreatePatientRequest {
     name: String,
     code: Option<String>,
+    name_kana: Option<String>,
+    date_of_birth: Option<String>,
+    sex: Option<Sex>,
+    phone: Option<String>,
+    address: Option<String>,
+    postal_code: Option<String>,
+    blood_type: Option<BloodType>,
+}
+
+impl CreatePatientRequest {
+    fn demographics(&self) -> Result<Demographics, MyError> {
+        Demographics::new(
+            self.name_kana.clone(),
+            self.date_of_birth.clone(),
+            self.sex,
+            self.phone.clone(),
+            self.address.clone(),
+            self.postal_code.clone(),
+            self.blood_type,
+        )
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -35,6 +67,27 @@ pub struct CreatePatientWithMedicalExaminationRequest {
     code: Option<String>,
     interviewed_at: Option<DateTime<Local>>,
     symptom: String,
+    name_kana: Option<String>,
+    date_of_birth: Option<String>,
+    sex: Option<Sex>,
+    phone: Option<String>,
+    address: Option<String>,
+    postal_code: Option<String>,
+    blood_type: Option<BloodType>,
+}
+
+impl CreatePatientWithMedicalExaminationRequest {
+    fn demographics(&self) -> Result<Demographics, MyError> {
+        Demographics::new(
+            self.name_kana.clone(),
+            self.date_of_birth.clone(),
+            self.sex,
+            self.phone.clone(),
+            self.address.clone(),
+            self.postal_code.clone(),
+            self.blood_type,
+        )
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -48,63 +101,313 @@ impl From<Patient> for CreatePatientWithMedicalExaminationResponse {
     }
 }
 
+/// omitted items are left as they are. blank string clears the item except name.
 #[derive(Deserialize, Serialize, Debug)]
-pub struct FetchPatientParameter {
-    id: String,
+pub struct UpdatePatientRequest {
+    name: Option<String>,
+    name_kana: Option<String>,
+    date_of_birth: Option<String>,
+    sex: Option<String>,
+    phone: Option<String>,
+    address: Option<String>,
+    postal_code: Option<String>,
+    blood_type: Option<String>,
+}
+
+impl UpdatePatientRequest {
+    fn patch(&self) -> Result<PatientPatch, MyError> {
+        Ok(PatientPatch {
+            name: self.name.clone(),
+            name_kana: self.name_kana.clone(),
+            date_of_birth: self.date_of_birth.clone(),
+            sex: parse_clearable::<Sex>("sex", &self.sex)?,
+            phone: self.phone.clone(),
+            address: self.address.clone(),
+            postal_code: self.postal_code.clone(),
+            blood_type: parse_clearable::<BloodType>("blood_type", &self.blood_type)?,
+        })
+    }
+}
+
+/// None if omitted, Some(None) if blank.
+fn parse_clearable<T: FromStr>(
+    key: &str,
+    value: &Option<String>,
+) -> Result<Option<Option<T>>, MyError> {
+    match value.as_deref().map(str::trim) {
+        None => Ok(None),
+        Some("") => Ok(Some(None)),
+        Some(value) => T::from_str(value)
+            .map(|value| Some(Some(value)))
+            .map_err(|_| MyError::BadRequest(json!({ "error": format!("{} is invalid", key) }))),
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct UpdatePatientResponse {
+    code: String,
+}
+
+impl UpdatePatientResponse {
+    fn from(patient: Patient) -> Self {
+        Self { code: patient.code }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ArchivePatientRequest {
+    reason: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ArchivePatientResponse {
+    code: String,
+    archived_at: Option<DateTime<Local>>,
+}
+
+impl ArchivePatientResponse {
+    fn from(patient: Patient) -> Self {
+        Self {
+            code: patient.code,
+            archived_at: patient.archive.map(|archive| archive.archived_at),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RestorePatientResponse {
+    code: String,
+}
+
+impl RestorePatientResponse {
+    fn from(patient: Patient) -> Self {
+        Self { code: patient.code }
+    }
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct FetchPatientResponse {
     name: String,
     code: String,
+    name_kana: Option<String>,
+    date_of_birth: Option<NaiveDate>,
+    sex: Option<Sex>,
+    phone: Option<String>,
+    address: Option<String>,
+    postal_code: Option<String>,
+    blood_type: Option<BloodType>,
+    archived_at: Option<DateTime<Local>>,
+    archive_reason: Option<String>,
+    doctors: Vec<FetchDoctorInChargeResponse>,
+    doctor_count: usize,
+    /// null if the user is not permitted to read medical examinations.
+    latest_medical_examinations: Option<Vec<FetchMedicalExamination>>,
+    medical_examination_count: Option<usize>,
 }
 
 impl FetchPatientResponse {
-    fn from(patient: Patient) -> Self {
+    fn from(patient_chart: PatientChart) -> Self {
+        let patient = patient_chart.patient;
+        let demographics = patient.demographics;
+        let doctors = patient_chart
+            .care_team
+            .into_iter()
+            .map(FetchDoctorInChargeResponse::from)
+            .collect::<Vec<FetchDoctorInChargeResponse>>();
+        let latest_medical_examinations =
+            patient_chart
+                .latest_medical_examinations
+                .map(|medical_examinations| {
+                    medical_examinations
+                        .into_iter()
+                        .map(FetchMedicalExamination::from)
+                        .collect::<Vec<FetchMedicalExamination>>()
+                });
         Self {
             code: patient.code,
             name: patient.name,
+            name_kana: demographics.name_kana,
+            date_of_birth: demographics.date_of_birth,
+            sex: demographics.sex,
+            phone: demographics.phone,
+            address: demographics.address,
+            postal_code: demographics.postal_code,
+            blood_type: demographics.blood_type,
+            archived_at: patient.archive.as_ref().map(|archive| archive.archived_at),
+            archive_reason: patient.archive.map(|archive| archive.reason),
+            doctor_count: doctors.len(),
+            doctors,
+            latest_medical_examinations,
+            medical_examination_count: patient_chart.medical_examination_count,
         }
     }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
-pub struct FetchPatientsParameter {}
+pub struct FetchPatientsParameter {
+    include_archived: Option<bool>,
+}
+
+/// delegated_by is id of the user who delegated the patient. null for patients of the user's own.
+#[derive(Deserialize, Serialize)]
+pub struct FetchPatientInChargeResponse {
+    name: String,
+    code: String,
+    delegated_by: Option<String>,
+    delegated_until: Option<DateTime<Local>>,
+    archived_at: Option<DateTime<Local>>,
+}
+
+impl FetchPatientInChargeResponse {
+    fn from((patient, delegation): (Patient, Option<Delegation>)) -> Self {
+        Self {
+            code: patient.code,
+            name: patient.name,
+            delegated_by: delegation
+                .as_ref()
+                .map(|delegation| delegation.delegator_id.clone()),
+            delegated_until: delegation.map(|delegation| delegation.ends_at),
+            archived_at: patient.archive.map(|archive| archive.archived_at),
+        }
+    }
+}
 
 #[derive(Deserialize, Serialize)]
 pub struct FetchPatientsResponse {
-    patients: Vec<FetchPatientResponse>,
+    patients: Vec<FetchPatientInChargeResponse>,
 }
 
 impl FetchPatientsResponse {
+    fn from(patients: Vec<(Patient, Option<Delegation>)>) -> Self {
+        let patients = patients
+            .into_iter()
+            .map(FetchPatientInChargeResponse::from)
+            .collect::<Vec<FetchPatientInChargeResponse>>();
+        Self { patients }
+    }
+}
+
+/// name or date_of_birth is required.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SearchPatientsParameter {
+    name: Option<String>,
+    date_of_birth: Option<String>,
+    include_archived: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SearchPatientResponse {
+    code: String,
+    name: String,
+    name_kana: Option<String>,
+    date_of_birth: Option<NaiveDate>,
+    sex: Option<Sex>,
+    archived_at: Option<DateTime<Local>>,
+}
+
+impl SearchPatientResponse {
+    fn from(patient: Patient) -> Self {
+        Self {
+            code: patient.code,
+            name: patient.name,
+            name_kana: patient.demographics.name_kana,
+            date_of_birth: patient.demographics.date_of_birth,
+            sex: patient.demographics.sex,
+            archived_at: patient.archive.map(|archive| archive.archived_at),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SearchPatientsResponse {
+    patients: Vec<SearchPatientResponse>,
+}
+
+impl SearchPatientsResponse {
     fn from(patients: Vec<Patient>) -> Self {
         let patients = patients
             .into_iter()
-            .map(|patient| FetchPatientResponse::from(patient))
-            .collect::<Vec<FetchPatientResponse>>();
+            .map(SearchPatientResponse::from)
+            .collect::<Vec<SearchPatientResponse>>();
         Self { patients }
     }
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct BreakTheGlassRequest {
+    patient_code: String,
+    reason: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BreakTheGlassResponse {
+    id: String,
+    patient_code: String,
+    expires_at: DateTime<Local>,
+}
+
+impl BreakTheGlassResponse {
+    fn from(emergency_access: EmergencyAccess) -> Self {
+        Self {
+            id: emergency_access.id,
+            patient_code: emergency_access.patient_code,
+            expires_at: emergency_access.expires_at,
+        }
+    }
+}
+
 pub type ApiResponse = Result<HttpResponse, MyError>;
 
+/// ETag of patient. version of the patient as strong tag, e.g. "3".
+fn patient_etag(patient: &Patient) -> ETag {
+    ETag(EntityTag::new_strong(patient.version.to_string()))
+}
+
+/// version in If-Match. If-Match is required so that nobody overwrites changes of others unawares.
+/// if missing or "*",PreconditionRequired.
+fn expected_version(req: &HttpRequest) -> Result<u32, MyError> {
+    let required = || {
+        MyError::PreconditionRequired(
+            json!({"error":"If-Match header with ETag of the patient is required"}),
+        )
+    };
+    if !req.headers().contains_key(IF_MATCH) {
+        return Err(required());
+    }
+    let unmatched =
+        || MyError::PreconditionFailed(json!({"error":"If-Match does not match the patient"}));
+    match IfMatch::parse(req).map_err(|_| unmatched())? {
+        IfMatch::Any => Err(required()),
+        // weak tag never matches If-Match.
+        IfMatch::Items(tags) => tags
+            .iter()
+            .filter(|tag| !tag.weak)
+            .find_map(|tag| tag.tag().parse::<u32>().ok())
+            .ok_or_else(unmatched),
+    }
+}
+
 pub async fn create_patient(
     state: web::Data<AppState>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     form: web::Json<CreatePatientRequest>,
 ) -> ApiResponse {
+    user.require(Permission::RegisterPatient)?;
     let conn = state.get_sqls_db_conn()?;
-    let _ = middleware::authn::get_user_id_from_header(&req)?;
 
     let patient_repository = PatientRepositoryImpl { conn: &conn };
     let medical_examination_repository = MedicalExaminationRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let user_repository = UserRepositoryImpl { conn: &conn };
     let patient_usecase = PatientUsecase {
         patient_repository,
         medical_examination_repository,
+        doctor_in_charge_repository,
+        user_repository,
     };
 
     let patient = patient_usecase
-        .create_patient(form.name.clone(), form.code.clone())
+        .create_patient(form.name.clone(), form.code.clone(), form.demographics()?)
         .await?;
     let create_patient_response = CreatePatientResponse::from(patient);
     Ok(HttpResponse::Ok().json(create_patient_response))
@@ -112,24 +415,30 @@ pub async fn create_patient(
 
 pub async fn create_patient_with_medical_examination(
     state: web::Data<AppState>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     form: web::Json<CreatePatientWithMedicalExaminationRequest>,
 ) -> ApiResponse {
+    user.require(Permission::RegisterPatient)?;
+    user.require(Permission::WriteMedicalExamination)?;
     let conn = state.get_sqls_db_conn()?;
-    let user_id = middleware::authn::get_user_id_from_header(&req)?;
     let patient_repository = PatientRepositoryImpl { conn: &conn };
     let medical_examination_repository = MedicalExaminationRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let user_repository = UserRepositoryImpl { conn: &conn };
     let patient_usecase = PatientUsecase {
         patient_repository,
         medical_examination_repository,
+        doctor_in_charge_repository,
+        user_repository,
     };
 
     let patient = patient_usecase
         .create_patient_with_medical_examination(
             form.name.clone(),
             form.code.clone(),
+            form.demographics()?,
             form.interviewed_at,
-            user_id,
+            user.user_id,
             form.symptom.clone(),
         )
         .await?;
@@ -137,35 +446,223 @@ pub async fn create_patient_with_medical_examination(
     Ok(HttpResponse::Ok().json(create_patient_response))
 }
 
+/// chart of the patient. medical examinations are left out if the user can not read them.
 pub async fn fetch_patient(
     state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    code: web::Path<String>,
+) -> ApiResponse {
+    user.require(Permission::ReadPatient)?;
+    let access_all_patients = user.require(Permission::AccessAllPatients).is_ok();
+    let include_medical_examinations = user.require(Permission::ReadMedicalExamination).is_ok();
+    let conn = state.get_sqls_db_conn()?;
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let medical_examination_repository = MedicalExaminationRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_usecase = PatientUsecase::new(
+        patient_repository,
+        medical_examination_repository,
+        doctor_in_charge_repository,
+        user_repository,
+    );
+    let patient_chart = patient_usecase
+        .fetch_chart(
+            &user.user_id,
+            access_all_patients,
+            &code.into_inner(),
+            include_medical_examinations,
+        )
+        .await?;
+    let etag = patient_etag(&patient_chart.patient);
+    let res = FetchPatientResponse::from(patient_chart);
+    Ok(HttpResponse::Ok().insert_header(etag).json(res))
+}
+
+/// update patient seen in GET /api/patient/{code}. If-Match is the ETag of it.
+pub async fn update_patient(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
     req: HttpRequest,
-    params: web::Query<FetchPatientParameter>,
+    code: web::Path<String>,
+    form: web::Json<UpdatePatientRequest>,
 ) -> ApiResponse {
-    let mut conn = state.get_sqls_db_conn()?;
-    let _ = middleware::authn::get_user_id_from_header(&req)?;
+    user.require(Permission::RegisterPatient)?;
+    let access_all_patients = user.require(Permission::AccessAllPatients).is_ok();
+    let expected_version = expected_version(&req)?;
+    let conn = state.get_sqls_db_conn()?;
     let patient_repository = PatientRepositoryImpl { conn: &conn };
     let medical_examination_repository = MedicalExaminationRepositoryImpl { conn: &conn };
-    let patient_usecase = PatientUsecase::new(patient_repository, medical_examination_repository);
-    let patient = patient_usecase.fetch_one(&params.id).await?;
-    let res = FetchPatientResponse::from(patient);
-    Ok(HttpResponse::Ok().json(res))
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_usecase = PatientUsecase {
+        patient_repository,
+        medical_examination_repository,
+        doctor_in_charge_repository,
+        user_repository,
+    };
+
+    let patient = patient_usecase
+        .update_patient(
+            &user.user_id,
+            access_all_patients,
+            code.into_inner(),
+            expected_version,
+            form.patch()?,
+        )
+        .await?;
+    let etag = patient_etag(&patient);
+    let update_patient_response = UpdatePatientResponse::from(patient);
+    Ok(HttpResponse::Ok()
+        .insert_header(etag)
+        .json(update_patient_response))
+}
+
+pub async fn archive_patient(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    code: web::Path<String>,
+    form: web::Json<ArchivePatientRequest>,
+) -> ApiResponse {
+    user.require(Permission::RegisterPatient)?;
+    let access_all_patients = user.require(Permission::AccessAllPatients).is_ok();
+    let conn = state.get_sqls_db_conn()?;
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let medical_examination_repository = MedicalExaminationRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_usecase = PatientUsecase {
+        patient_repository,
+        medical_examination_repository,
+        doctor_in_charge_repository,
+        user_repository,
+    };
+
+    let patient = patient_usecase
+        .archive_patient(
+            user.user_id,
+            access_all_patients,
+            code.into_inner(),
+            form.reason.clone(),
+        )
+        .await?;
+    let etag = patient_etag(&patient);
+    let archive_patient_response = ArchivePatientResponse::from(patient);
+    Ok(HttpResponse::Ok()
+        .insert_header(etag)
+        .json(archive_patient_response))
+}
+
+pub async fn restore_patient(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    code: web::Path<String>,
+) -> ApiResponse {
+    user.require(Permission::RegisterPatient)?;
+    let access_all_patients = user.require(Permission::AccessAllPatients).is_ok();
+    let conn = state.get_sqls_db_conn()?;
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let medical_examination_repository = MedicalExaminationRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_usecase = PatientUsecase {
+        patient_repository,
+        medical_examination_repository,
+        doctor_in_charge_repository,
+        user_repository,
+    };
+
+    let patient = patient_usecase
+        .restore_patient(&user.user_id, access_all_patients, code.into_inner())
+        .await?;
+    let etag = patient_etag(&patient);
+    let restore_patient_response = RestorePatientResponse::from(patient);
+    Ok(HttpResponse::Ok()
+        .insert_header(etag)
+        .json(restore_patient_response))
 }
 
 pub async fn fetch_patients(
     state: web::Data<AppState>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     params: web::Query<FetchPatientsParameter>,
 ) -> ApiResponse {
+    user.require(Permission::ReadPatient)?;
     let conn = state.get_sqls_db_conn()?;
-    let _ = middleware::authn::get_user_id_from_header(&req)?;
     let patient_repository = PatientRepositoryImpl { conn: &conn };
     let medical_examination_repository = MedicalExaminationRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let user_repository = UserRepositoryImpl { conn: &conn };
     let train_usecase = PatientUsecase {
         patient_repository,
         medical_examination_repository,
+        doctor_in_charge_repository,
+        user_repository,
     };
-    let patients = train_usecase.fetch_patients().await?;
+    let patients = train_usecase
+        .fetch_patients(&user.user_id, params.include_archived.unwrap_or(false))
+        .await?;
     let res = FetchPatientsResponse::from(patients);
     Ok(HttpResponse::Ok().json(res))
 }
+
+/// search every patient, not only patients in charge, so that reception finds the patient.
+pub async fn search_patients(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    params: web::Query<SearchPatientsParameter>,
+) -> ApiResponse {
+    user.require(Permission::AccessAllPatients)?;
+    let conn = state.get_sqls_db_conn()?;
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let medical_examination_repository = MedicalExaminationRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_usecase = PatientUsecase {
+        patient_repository,
+        medical_examination_repository,
+        doctor_in_charge_repository,
+        user_repository,
+    };
+
+    let patients = patient_usecase
+        .search_patients(
+            params.name.clone(),
+            params.date_of_birth.clone(),
+            params.include_archived.unwrap_or(false),
+        )
+        .await?;
+    let res = SearchPatientsResponse::from(patients);
+    Ok(HttpResponse::Ok().json(res))
+}
+
+pub async fn break_the_glass(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<BreakTheGlassRequest>,
+) -> ApiResponse {
+    user.require(Permission::BreakTheGlass)?;
+    // emergency access is taken by a person. service account can not break the glass.
+    if user.scopes.is_some() {
+        return Err(MyError::Forbidden(json!({
+            "error": format!("api key is not permitted to {}", Permission::BreakTheGlass)
+        })));
+    }
+    let conn = state.get_sqls_db_conn()?;
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let medical_examination_repository = MedicalExaminationRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_usecase = PatientUsecase {
+        patient_repository,
+        medical_examination_repository,
+        doctor_in_charge_repository,
+        user_repository,
+    };
+
+    let emergency_access = patient_usecase
+        .break_the_glass(user.user_id, form.patient_code.clone(), form.reason.clone())
+        .await?;
+    let break_the_glass_response = BreakTheGlassResponse::from(emergency_access);
+    Ok(HttpResponse::Ok().json(break_the_glass_response))
+}
@@ -0,0 +1,349 @@
+use actix_web::{web, HttpRequest};
+use chrono::{DateTime, Local};
+
+use crate::domain::invitation::Invitation;
+use crate::domain::user::{Permission, Role, User};
+use crate::middleware::authentication::AuthenticatedUser;
+use crate::presentation::user::client_info;
+use crate::repository::invitation_repository::InvitationRepositoryImpl;
+use crate::repository::password_history_repository::PasswordHistoryRepositoryImpl;
+use crate::repository::refresh_token_repository::RefreshTokenRepositoryImpl;
+use crate::repository::session_repository::SessionRepositoryImpl;
+use crate::repository::user_repository::UserRepositoryImpl;
+use crate::usecase::registration::RegistrationUsecase;
+use crate::utils::errors::MyError;
+use crate::utils::state::AppState;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+pub type ApiResponse = Result<HttpResponse, MyError>;
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SignUpRequest {
+    name: String,
+    password: String,
+    code: Option<String>,
+    /// required except for the first user.
+    invitation_code: Option<String>,
+}
+
+/// tokens are returned only if the account can sign in now. otherwise it waits for approval.
+#[derive(Deserialize, Serialize)]
+pub struct SignUpResponse {
+    code: String,
+    role: Role,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    approval_required: bool,
+}
+
+impl SignUpResponse {
+    fn from(user: User, tokens: Option<(String, String)>) -> Self {
+        let (token, refresh_token) = tokens.unzip();
+        Self {
+            code: user.code,
+            role: user.role,
+            token,
+            refresh_token,
+            approval_required: !user.approved,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CreateInvitationRequest {
+    role: Role,
+    /// 7 days if omitted.
+    expires_in_days: Option<i64>,
+    #[serde(default)]
+    approval_required: bool,
+}
+
+/// invitation_code is shown only here.
+#[derive(Deserialize, Serialize)]
+pub struct CreateInvitationResponse {
+    id: String,
+    invitation_code: String,
+    role: Role,
+    expires_at: DateTime<Local>,
+    approval_required: bool,
+}
+
+impl CreateInvitationResponse {
+    fn from(invitation: Invitation, raw_code: String) -> Self {
+        Self {
+            id: invitation.id,
+            invitation_code: raw_code,
+            role: invitation.role,
+            expires_at: invitation.expires_at,
+            approval_required: invitation.approval_required,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchInvitationResponse {
+    id: String,
+    role: Role,
+    expires_at: DateTime<Local>,
+    approval_required: bool,
+    used: bool,
+    revoked: bool,
+}
+
+impl FetchInvitationResponse {
+    fn from(invitation: Invitation) -> Self {
+        Self {
+            id: invitation.id,
+            role: invitation.role,
+            expires_at: invitation.expires_at,
+            approval_required: invitation.approval_required,
+            used: invitation.used_by.is_some(),
+            revoked: invitation.revoked,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchInvitationsResponse {
+    invitations: Vec<FetchInvitationResponse>,
+}
+
+impl FetchInvitationsResponse {
+    fn from(invitations: Vec<Invitation>) -> Self {
+        let invitations = invitations
+            .into_iter()
+            .map(FetchInvitationResponse::from)
+            .collect::<Vec<FetchInvitationResponse>>();
+        Self { invitations }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RevokeInvitationRequest {
+    id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RevokeInvitationResponse {}
+
+impl RevokeInvitationResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchPendingUserResponse {
+    code: String,
+    name: String,
+    role: Role,
+}
+
+impl FetchPendingUserResponse {
+    fn from(user: User) -> Self {
+        Self {
+            code: user.code,
+            name: user.name,
+            role: user.role,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchPendingUsersResponse {
+    users: Vec<FetchPendingUserResponse>,
+}
+
+impl FetchPendingUsersResponse {
+    fn from(users: Vec<User>) -> Self {
+        let users = users
+            .into_iter()
+            .map(FetchPendingUserResponse::from)
+            .collect::<Vec<FetchPendingUserResponse>>();
+        Self { users }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ApproveRequest {
+    code: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ApproveResponse {}
+
+impl ApproveResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+pub async fn sign_up(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    form: web::Json<SignUpRequest>,
+) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let invitation_repository = InvitationRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let registration_usecase = RegistrationUsecase {
+        user_repository,
+        invitation_repository,
+        password_history_repository,
+        session_repository,
+        refresh_token_repository,
+        password_policy: state.password_policy.clone(),
+    };
+
+    let (user, tokens) = registration_usecase
+        .sign_up(
+            form.name.clone(),
+            form.code.clone(),
+            form.password.clone(),
+            form.invitation_code.clone(),
+            client_info(&req),
+        )
+        .await?;
+    let sign_up_response = SignUpResponse::from(user, tokens);
+    Ok(HttpResponse::Ok().json(sign_up_response))
+}
+
+pub async fn create_invitation(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<CreateInvitationRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let invitation_repository = InvitationRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let registration_usecase = RegistrationUsecase {
+        user_repository,
+        invitation_repository,
+        password_history_repository,
+        session_repository,
+        refresh_token_repository,
+        password_policy: state.password_policy.clone(),
+    };
+
+    let (invitation, raw_code) = registration_usecase
+        .create_invitation(
+            form.role,
+            form.expires_in_days,
+            form.approval_required,
+            user.user_id,
+        )
+        .await?;
+    let create_invitation_response = CreateInvitationResponse::from(invitation, raw_code);
+    Ok(HttpResponse::Ok().json(create_invitation_response))
+}
+
+pub async fn fetch_invitations(state: web::Data<AppState>, user: AuthenticatedUser) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let invitation_repository = InvitationRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let registration_usecase = RegistrationUsecase {
+        user_repository,
+        invitation_repository,
+        password_history_repository,
+        session_repository,
+        refresh_token_repository,
+        password_policy: state.password_policy.clone(),
+    };
+
+    let invitations = registration_usecase.fetch_invitations().await?;
+    let res = FetchInvitationsResponse::from(invitations);
+    Ok(HttpResponse::Ok().json(res))
+}
+
+pub async fn revoke_invitation(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<RevokeInvitationRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let invitation_repository = InvitationRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let registration_usecase = RegistrationUsecase {
+        user_repository,
+        invitation_repository,
+        password_history_repository,
+        session_repository,
+        refresh_token_repository,
+        password_policy: state.password_policy.clone(),
+    };
+
+    registration_usecase
+        .revoke_invitation(form.id.clone())
+        .await?;
+    let revoke_invitation_response = RevokeInvitationResponse::from();
+    Ok(HttpResponse::Ok().json(revoke_invitation_response))
+}
+
+pub async fn fetch_pending_users(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let invitation_repository = InvitationRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let registration_usecase = RegistrationUsecase {
+        user_repository,
+        invitation_repository,
+        password_history_repository,
+        session_repository,
+        refresh_token_repository,
+        password_policy: state.password_policy.clone(),
+    };
+
+    let users = registration_usecase.fetch_pending_users().await?;
+    let res = FetchPendingUsersResponse::from(users);
+    Ok(HttpResponse::Ok().json(res))
+}
+
+pub async fn approve(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<ApproveRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let invitation_repository = InvitationRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let registration_usecase = RegistrationUsecase {
+        user_repository,
+        invitation_repository,
+        password_history_repository,
+        session_repository,
+        refresh_token_repository,
+        password_policy: state.password_policy.clone(),
+    };
+
+    registration_usecase.approve(form.code.clone()).await?;
+    let approve_response = ApproveResponse::from();
+    Ok(HttpResponse::Ok().json(approve_response))
+}
@@ -0,0 +1,245 @@
+use actix_web::web;
+use chrono::{DateTime, Local};
+
+use crate::domain::service_account::{ApiKey, ServiceAccount};
+use crate::domain::user::{Permission, Role};
+use crate::middleware::authentication::AuthenticatedUser;
+use crate::repository::service_account_repository::{
+    ApiKeyRepositoryImpl, ServiceAccountRepositoryImpl,
+};
+use crate::usecase::service_account::ServiceAccountUsecase;
+use crate::utils::errors::MyError;
+use crate::utils::state::AppState;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+pub type ApiResponse = Result<HttpResponse, MyError>;
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CreateServiceAccountRequest {
+    name: String,
+    role: Role,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchServiceAccountResponse {
+    id: String,
+    name: String,
+    role: Role,
+}
+
+impl FetchServiceAccountResponse {
+    fn from(service_account: ServiceAccount) -> Self {
+        Self {
+            id: service_account.id,
+            name: service_account.name,
+            role: service_account.role,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchServiceAccountsResponse {
+    service_accounts: Vec<FetchServiceAccountResponse>,
+}
+
+impl FetchServiceAccountsResponse {
+    fn from(service_accounts: Vec<ServiceAccount>) -> Self {
+        let service_accounts = service_accounts
+            .into_iter()
+            .map(FetchServiceAccountResponse::from)
+            .collect::<Vec<FetchServiceAccountResponse>>();
+        Self { service_accounts }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct IssueApiKeyRequest {
+    service_account_id: String,
+    scopes: Vec<Permission>,
+    /// never expires if omitted.
+    expires_in_days: Option<i64>,
+}
+
+/// api_key is shown only here.
+#[derive(Deserialize, Serialize)]
+pub struct IssueApiKeyResponse {
+    id: String,
+    api_key: String,
+    scopes: Vec<Permission>,
+    expires_at: Option<DateTime<Local>>,
+}
+
+impl IssueApiKeyResponse {
+    fn from(api_key: ApiKey, raw_key: String) -> Self {
+        Self {
+            id: api_key.id,
+            api_key: raw_key,
+            scopes: api_key.scopes,
+            expires_at: api_key.expires_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct FetchApiKeysParameter {
+    service_account_id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchApiKeyResponse {
+    id: String,
+    prefix: String,
+    scopes: Vec<Permission>,
+    expires_at: Option<DateTime<Local>>,
+    last_used_at: Option<DateTime<Local>>,
+    revoked: bool,
+}
+
+impl FetchApiKeyResponse {
+    fn from(api_key: ApiKey) -> Self {
+        Self {
+            prefix: api_key.prefix(),
+            id: api_key.id,
+            scopes: api_key.scopes,
+            expires_at: api_key.expires_at,
+            last_used_at: api_key.last_used_at,
+            revoked: api_key.revoked,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchApiKeysResponse {
+    api_keys: Vec<FetchApiKeyResponse>,
+}
+
+impl FetchApiKeysResponse {
+    fn from(api_keys: Vec<ApiKey>) -> Self {
+        let api_keys = api_keys
+            .into_iter()
+            .map(FetchApiKeyResponse::from)
+            .collect::<Vec<FetchApiKeyResponse>>();
+        Self { api_keys }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RevokeApiKeyRequest {
+    id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RevokeApiKeyResponse {}
+
+impl RevokeApiKeyResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+pub async fn create_service_account(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<CreateServiceAccountRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let service_account_repository = ServiceAccountRepositoryImpl { conn: &conn };
+    let api_key_repository = ApiKeyRepositoryImpl { conn: &conn };
+    let service_account_usecase = ServiceAccountUsecase {
+        service_account_repository,
+        api_key_repository,
+    };
+
+    let service_account = service_account_usecase
+        .create_service_account(form.name.clone(), form.role, user.user_id)
+        .await?;
+    let create_service_account_response = FetchServiceAccountResponse::from(service_account);
+    Ok(HttpResponse::Ok().json(create_service_account_response))
+}
+
+pub async fn fetch_service_accounts(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let service_account_repository = ServiceAccountRepositoryImpl { conn: &conn };
+    let api_key_repository = ApiKeyRepositoryImpl { conn: &conn };
+    let service_account_usecase = ServiceAccountUsecase {
+        service_account_repository,
+        api_key_repository,
+    };
+
+    let service_accounts = service_account_usecase.fetch_service_accounts().await?;
+    let res = FetchServiceAccountsResponse::from(service_accounts);
+    Ok(HttpResponse::Ok().json(res))
+}
+
+pub async fn issue_api_key(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<IssueApiKeyRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let service_account_repository = ServiceAccountRepositoryImpl { conn: &conn };
+    let api_key_repository = ApiKeyRepositoryImpl { conn: &conn };
+    let service_account_usecase = ServiceAccountUsecase {
+        service_account_repository,
+        api_key_repository,
+    };
+
+    let (api_key, raw_key) = service_account_usecase
+        .issue_api_key(
+            form.service_account_id.clone(),
+            form.scopes.clone(),
+            form.expires_in_days,
+        )
+        .await?;
+    let issue_api_key_response = IssueApiKeyResponse::from(api_key, raw_key);
+    Ok(HttpResponse::Ok().json(issue_api_key_response))
+}
+
+pub async fn fetch_api_keys(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    params: web::Query<FetchApiKeysParameter>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let service_account_repository = ServiceAccountRepositoryImpl { conn: &conn };
+    let api_key_repository = ApiKeyRepositoryImpl { conn: &conn };
+    let service_account_usecase = ServiceAccountUsecase {
+        service_account_repository,
+        api_key_repository,
+    };
+
+    let api_keys = service_account_usecase
+        .fetch_api_keys(params.service_account_id.clone())
+        .await?;
+    let res = FetchApiKeysResponse::from(api_keys);
+    Ok(HttpResponse::Ok().json(res))
+}
+
+pub async fn revoke_api_key(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<RevokeApiKeyRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let service_account_repository = ServiceAccountRepositoryImpl { conn: &conn };
+    let api_key_repository = ApiKeyRepositoryImpl { conn: &conn };
+    let service_account_usecase = ServiceAccountUsecase {
+        service_account_repository,
+        api_key_repository,
+    };
+
+    service_account_usecase
+        .revoke_api_key(form.id.clone())
+        .await?;
+    let revoke_api_key_response = RevokeApiKeyResponse::from();
+    Ok(HttpResponse::Ok().json(revoke_api_key_response))
+}
@@ -0,0 +1,118 @@
+use actix_web::web;
+use chrono::{DateTime, Local};
+
+use crate::domain::session::Session;
+use crate::middleware::authentication::AuthenticatedUser;
+use crate::repository::refresh_token_repository::RefreshTokenRepositoryImpl;
+use crate::repository::session_repository::SessionRepositoryImpl;
+use crate::usecase::session::SessionUsecase;
+use crate::utils::errors::MyError;
+use crate::utils::state::AppState;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+pub type ApiResponse = Result<HttpResponse, MyError>;
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchSessionResponse {
+    id: String,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    created_at: DateTime<Local>,
+    last_seen_at: DateTime<Local>,
+    /// whether this is the session of the request.
+    current: bool,
+}
+
+impl FetchSessionResponse {
+    fn from(session: Session, current_session_id: &Option<String>) -> Self {
+        Self {
+            current: current_session_id.as_ref() == Some(&session.id),
+            id: session.id,
+            ip: session.ip,
+            user_agent: session.user_agent,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchSessionsResponse {
+    sessions: Vec<FetchSessionResponse>,
+}
+
+impl FetchSessionsResponse {
+    fn from(sessions: Vec<Session>, current_session_id: &Option<String>) -> Self {
+        let sessions = sessions
+            .into_iter()
+            .map(|session| FetchSessionResponse::from(session, current_session_id))
+            .collect::<Vec<FetchSessionResponse>>();
+        Self { sessions }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RevokeSessionRequest {
+    id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RevokeSessionResponse {}
+
+impl RevokeSessionResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+pub async fn fetch_sessions(state: web::Data<AppState>, user: AuthenticatedUser) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let session_usecase = SessionUsecase {
+        session_repository,
+        refresh_token_repository,
+    };
+
+    let sessions = session_usecase.fetch_sessions(user.user_id).await?;
+    let res = FetchSessionsResponse::from(sessions, &user.session_id);
+    Ok(HttpResponse::Ok().json(res))
+}
+
+pub async fn revoke_session(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<RevokeSessionRequest>,
+) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let session_usecase = SessionUsecase {
+        session_repository,
+        refresh_token_repository,
+    };
+
+    session_usecase
+        .revoke_session(user.user_id, form.id.clone())
+        .await?;
+    let revoke_session_response = RevokeSessionResponse::from();
+    Ok(HttpResponse::Ok().json(revoke_session_response))
+}
+
+pub async fn revoke_all_sessions(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let session_usecase = SessionUsecase {
+        session_repository,
+        refresh_token_repository,
+    };
+
+    session_usecase.revoke_all_sessions(user.user_id).await?;
+    let revoke_session_response = RevokeSessionResponse::from();
+    Ok(HttpResponse::Ok().json(revoke_session_response))
+}
@@ -1,69 +1,373 @@
+use actix_web::http::header::USER_AGENT;
 use actix_web::{web, HttpRequest};
 
+use crate::repository::login_attempt_repository::LoginAttemptRepositoryImpl;
+use crate::repository::password_history_repository::PasswordHistoryRepositoryImpl;
 use crate::repository::patient_repository::PatientRepositoryImpl;
-use crate::repository::user_repository::{DoctorInChargeRepositoryImpl, UserRepositoryImpl};
-use crate::usecase::user::UserUsecase;
+use crate::repository::refresh_token_repository::RefreshTokenRepositoryImpl;
+use crate::repository::session_repository::SessionRepositoryImpl;
+use crate::repository::user_repository::{
+    DoctorInChargeRepositoryImpl, TwoFactorRepositoryImpl, UserRepositoryImpl,
+};
+use crate::usecase::user::{SignInResult, UserUsecase};
 use crate::utils::errors::MyError;
 use crate::utils::state::AppState;
-use crate::{domain::user::User, middleware::authn};
+use crate::{
+    domain::care_assignment::{CareAssignment, CareRole},
+    domain::delegation::Delegation,
+    domain::emergency_access::EmergencyAccess,
+    domain::patient::Patient,
+    domain::session::ClientInfo,
+    domain::user::{Permission, Role, User},
+    middleware::authentication::AuthenticatedUser,
+};
 use actix_web::HttpResponse;
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 
 pub type ApiResponse = Result<HttpResponse, MyError>;
 
+/// ip and user agent of the request.
+/// peer address is used rather than x-forwarded-for which client can forge.
+pub fn client_info(req: &HttpRequest) -> ClientInfo {
+    ClientInfo {
+        ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+        user_agent: req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|user_agent| user_agent.to_str().ok())
+            .map(|user_agent| user_agent.to_string()),
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
-pub struct SignUpRequest {
-    name: String,
+pub struct SignInRequest {
+    code: String,
     password: String,
-    code: Option<String>,
 }
 
+/// tokens are returned if two factor is not enabled. otherwise two_factor_token is returned.
 #[derive(Deserialize, Serialize)]
-pub struct SignUpResponse {
+pub struct SignInResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    two_factor_required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    two_factor_token: Option<String>,
+}
+
+impl SignInResponse {
+    fn from(result: SignInResult) -> Self {
+        match result {
+            SignInResult::Authenticated(token, refresh_token) => Self {
+                token: Some(token),
+                refresh_token: Some(refresh_token),
+                two_factor_required: false,
+                two_factor_token: None,
+            },
+            SignInResult::TwoFactorRequired(two_factor_token) => Self {
+                token: None,
+                refresh_token: None,
+                two_factor_required: true,
+                two_factor_token: Some(two_factor_token),
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SignInTwoFactorRequest {
+    two_factor_token: String,
     code: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SignInTwoFactorResponse {
     token: String,
+    refresh_token: String,
 }
 
-impl SignUpResponse {
-    fn from(user: User, token: String) -> Self {
+impl SignInTwoFactorResponse {
+    fn from(token: String, refresh_token: String) -> Self {
         Self {
-            code: user.code,
             token,
+            refresh_token,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct EnrollTwoFactorResponse {
+    secret: String,
+    otpauth_uri: String,
+}
+
+impl EnrollTwoFactorResponse {
+    fn from(secret: String, otpauth_uri: String) -> Self {
+        Self {
+            secret,
+            otpauth_uri,
         }
     }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
-pub struct SignInRequest {
+pub struct ConfirmTwoFactorRequest {
     code: String,
-    password: String,
 }
 
 #[derive(Deserialize, Serialize)]
-pub struct SignInResponse {
+pub struct ConfirmTwoFactorResponse {
+    recovery_codes: Vec<String>,
+}
+
+impl ConfirmTwoFactorResponse {
+    fn from(recovery_codes: Vec<String>) -> Self {
+        Self { recovery_codes }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RefreshRequest {
+    refresh_token: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RefreshResponse {
     token: String,
+    refresh_token: String,
 }
 
-impl SignInResponse {
-    fn from(token: String) -> Self {
-        Self { token }
+impl RefreshResponse {
+    fn from(token: String, refresh_token: String) -> Self {
+        Self {
+            token,
+            refresh_token,
+        }
     }
 }
 
+/// code of user to assign. the caller if omitted.
+/// care_role is attending if omitted. starts now if starts_at is omitted, and open ended if ends_at is omitted.
 #[derive(Deserialize, Serialize, Debug)]
 pub struct AssignRequest {
     patient_code: String,
+    code: Option<String>,
+    #[serde(default)]
+    care_role: CareRole,
+    starts_at: Option<DateTime<Local>>,
+    ends_at: Option<DateTime<Local>>,
+    reason: Option<String>,
 }
 
 #[derive(Deserialize, Serialize)]
-pub struct AssignResponse {}
+pub struct AssignResponse {
+    id: String,
+    care_role: CareRole,
+    starts_at: DateTime<Local>,
+    ends_at: Option<DateTime<Local>>,
+}
 
 impl AssignResponse {
+    fn from(care_assignment: CareAssignment) -> Self {
+        Self {
+            id: care_assignment.id,
+            care_role: care_assignment.care_role,
+            starts_at: care_assignment.starts_at,
+            ends_at: care_assignment.ends_at,
+        }
+    }
+}
+
+/// code of user to unassign. the caller if omitted.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct UnassignRequest {
+    patient_code: String,
+    code: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct UnassignResponse {}
+
+impl UnassignResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct TransferRequest {
+    patient_code: String,
+    from_code: String,
+    to_code: String,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TransferResponse {
+    id: String,
+    care_role: CareRole,
+    starts_at: DateTime<Local>,
+    ends_at: Option<DateTime<Local>>,
+}
+
+impl TransferResponse {
+    fn from(care_assignment: CareAssignment) -> Self {
+        Self {
+            id: care_assignment.id,
+            care_role: care_assignment.care_role,
+            starts_at: care_assignment.starts_at,
+            ends_at: care_assignment.ends_at,
+        }
+    }
+}
+
+/// ended assignments are included if include_history is true.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct FetchDoctorsInChargeParameter {
+    patient_code: String,
+    include_history: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchDoctorInChargeResponse {
+    code: String,
+    name: String,
+    role: Role,
+    care_role: CareRole,
+    starts_at: DateTime<Local>,
+    ends_at: Option<DateTime<Local>>,
+    reason: Option<String>,
+}
+
+impl FetchDoctorInChargeResponse {
+    pub fn from((user, care_assignment): (User, CareAssignment)) -> Self {
+        Self {
+            code: user.code,
+            name: user.name,
+            role: user.role,
+            care_role: care_assignment.care_role,
+            starts_at: care_assignment.starts_at,
+            ends_at: care_assignment.ends_at,
+            reason: care_assignment.reason,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchDoctorsInChargeResponse {
+    users: Vec<FetchDoctorInChargeResponse>,
+}
+
+impl FetchDoctorsInChargeResponse {
+    fn from(care_team: Vec<(User, CareAssignment)>) -> Self {
+        let users = care_team
+            .into_iter()
+            .map(FetchDoctorInChargeResponse::from)
+            .collect::<Vec<FetchDoctorInChargeResponse>>();
+        Self { users }
+    }
+}
+
+/// code of user. the caller if omitted.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct FetchAssignedPatientsParameter {
+    code: Option<String>,
+    include_archived: Option<bool>,
+}
+
+/// delegated_by is id of the user who delegated the patient. null for patients of the user's own.
+#[derive(Deserialize, Serialize)]
+pub struct FetchAssignedPatientResponse {
+    code: String,
+    name: String,
+    delegated_by: Option<String>,
+    delegated_until: Option<DateTime<Local>>,
+    archived_at: Option<DateTime<Local>>,
+}
+
+impl FetchAssignedPatientResponse {
+    fn from((patient, delegation): (Patient, Option<Delegation>)) -> Self {
+        Self {
+            code: patient.code,
+            name: patient.name,
+            delegated_by: delegation
+                .as_ref()
+                .map(|delegation| delegation.delegator_id.clone()),
+            delegated_until: delegation.map(|delegation| delegation.ends_at),
+            archived_at: patient.archive.map(|archive| archive.archived_at),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchAssignedPatientsResponse {
+    patients: Vec<FetchAssignedPatientResponse>,
+}
+
+impl FetchAssignedPatientsResponse {
+    fn from(patients: Vec<(Patient, Option<Delegation>)>) -> Self {
+        let patients = patients
+            .into_iter()
+            .map(FetchAssignedPatientResponse::from)
+            .collect::<Vec<FetchAssignedPatientResponse>>();
+        Self { patients }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ChangeRoleRequest {
+    code: String,
+    role: Role,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ChangeRoleResponse {}
+
+impl ChangeRoleResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct UnlockRequest {
+    code: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct UnlockResponse {}
+
+impl UnlockResponse {
     fn from() -> Self {
         Self {}
     }
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ChangePasswordRequest {
+    current_password: String,
+    password: String,
+}
+
+/// other sessions are signed out. client continues with these tokens.
+#[derive(Deserialize, Serialize)]
+pub struct ChangePasswordResponse {
+    token: String,
+    refresh_token: String,
+}
+
+impl ChangePasswordResponse {
+    fn from(token: String, refresh_token: String) -> Self {
+        Self {
+            token,
+            refresh_token,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct FetchUserParameter {
     id: String,
@@ -71,87 +375,1034 @@ pub struct FetchUserParameter {
 
 #[derive(Deserialize, Serialize)]
 pub struct FetchUserResponse {
-    id: String,
-    name: String,
     code: String,
+    name: String,
+    role: Role,
+    active: bool,
+    approved: bool,
 }
 
 impl FetchUserResponse {
     fn from(user: User) -> Self {
         Self {
-            id: user.id,
-            name: user.name,
             code: user.code,
+            name: user.name,
+            role: user.role,
+            active: user.active,
+            approved: user.approved,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchUsersResponse {
+    users: Vec<FetchUserResponse>,
+}
+
+impl FetchUsersResponse {
+    fn from(users: Vec<User>) -> Self {
+        let users = users
+            .into_iter()
+            .map(FetchUserResponse::from)
+            .collect::<Vec<FetchUserResponse>>();
+        Self { users }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct UpdateNameRequest {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ChangeActiveRequest {
+    code: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ChangeActiveResponse {}
+
+impl ChangeActiveResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct FetchEmergencyAccessesParameter {
+    #[serde(default)]
+    unacknowledged_only: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchEmergencyAccessResponse {
+    id: String,
+    user_id: String,
+    patient_code: String,
+    reason: String,
+    granted_at: DateTime<Local>,
+    expires_at: DateTime<Local>,
+    acknowledged_by: Option<String>,
+    acknowledged_at: Option<DateTime<Local>>,
+}
+
+impl FetchEmergencyAccessResponse {
+    fn from(emergency_access: EmergencyAccess) -> Self {
+        Self {
+            id: emergency_access.id,
+            user_id: emergency_access.user_id,
+            patient_code: emergency_access.patient_code,
+            reason: emergency_access.reason,
+            granted_at: emergency_access.granted_at,
+            expires_at: emergency_access.expires_at,
+            acknowledged_by: emergency_access.acknowledged_by,
+            acknowledged_at: emergency_access.acknowledged_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchEmergencyAccessesResponse {
+    emergency_accesses: Vec<FetchEmergencyAccessResponse>,
+}
+
+impl FetchEmergencyAccessesResponse {
+    fn from(emergency_accesses: Vec<EmergencyAccess>) -> Self {
+        let emergency_accesses = emergency_accesses
+            .into_iter()
+            .map(FetchEmergencyAccessResponse::from)
+            .collect::<Vec<FetchEmergencyAccessResponse>>();
+        Self { emergency_accesses }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct AcknowledgeEmergencyAccessRequest {
+    id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AcknowledgeEmergencyAccessResponse {}
+
+impl AcknowledgeEmergencyAccessResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+/// every patient the caller is in charge of if patient_codes is omitted. starts now if starts_at is omitted.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct DelegateRequest {
+    to_code: String,
+    patient_codes: Option<Vec<String>>,
+    starts_at: Option<DateTime<Local>>,
+    ends_at: DateTime<Local>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct DelegateResponse {
+    id: String,
+    starts_at: DateTime<Local>,
+    ends_at: DateTime<Local>,
+}
+
+impl DelegateResponse {
+    fn from(delegation: Delegation) -> Self {
+        Self {
+            id: delegation.id,
+            starts_at: delegation.starts_at,
+            ends_at: delegation.ends_at,
+        }
+    }
+}
+
+/// patient_codes is null if every patient is delegated.
+#[derive(Deserialize, Serialize)]
+pub struct FetchDelegationResponse {
+    id: String,
+    delegator_id: String,
+    delegate_id: String,
+    patient_codes: Option<Vec<String>>,
+    starts_at: DateTime<Local>,
+    ends_at: DateTime<Local>,
+    revoked_at: Option<DateTime<Local>>,
+    created_at: DateTime<Local>,
+}
+
+impl FetchDelegationResponse {
+    fn from(delegation: Delegation) -> Self {
+        Self {
+            id: delegation.id,
+            delegator_id: delegation.delegator_id,
+            delegate_id: delegation.delegate_id,
+            patient_codes: delegation.patient_codes,
+            starts_at: delegation.starts_at,
+            ends_at: delegation.ends_at,
+            revoked_at: delegation.revoked_at,
+            created_at: delegation.created_at,
         }
     }
 }
 
-pub async fn sign_up(
+#[derive(Deserialize, Serialize)]
+pub struct FetchDelegationsResponse {
+    delegations: Vec<FetchDelegationResponse>,
+}
+
+impl FetchDelegationsResponse {
+    fn from(delegations: Vec<Delegation>) -> Self {
+        let delegations = delegations
+            .into_iter()
+            .map(FetchDelegationResponse::from)
+            .collect::<Vec<FetchDelegationResponse>>();
+        Self { delegations }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RevokeDelegationRequest {
+    id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RevokeDelegationResponse {}
+
+impl RevokeDelegationResponse {
+    fn from() -> Self {
+        Self {}
+    }
+}
+
+pub async fn sign_in(
     state: web::Data<AppState>,
     req: HttpRequest,
-    form: web::Json<SignUpRequest>,
+    form: web::Json<SignInRequest>,
 ) -> ApiResponse {
     let conn = state.get_sqls_db_conn()?;
     let user_repository = UserRepositoryImpl { conn: &conn };
     let patient_repository = PatientRepositoryImpl { conn: &conn };
     let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
-
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
     let user_usecase = UserUsecase {
         user_repository,
         patient_repository,
         doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
     };
 
-    let (user, token) = user_usecase
-        .sign_up(form.name.clone(), form.code.clone(), form.password.clone())
+    let result = user_usecase
+        .sign_in(form.code.clone(), form.password.clone(), client_info(&req))
         .await?;
-    let create_muscle_response = SignUpResponse::from(user, token);
-    Ok(HttpResponse::Ok().json(create_muscle_response))
+    let fetch_user_response = SignInResponse::from(result);
+
+    Ok(HttpResponse::Ok().json(fetch_user_response))
 }
 
-pub async fn sign_in(
+pub async fn refresh(
     state: web::Data<AppState>,
     req: HttpRequest,
-    form: web::Json<SignInRequest>,
+    form: web::Json<RefreshRequest>,
 ) -> ApiResponse {
     let conn = state.get_sqls_db_conn()?;
     let user_repository = UserRepositoryImpl { conn: &conn };
     let patient_repository = PatientRepositoryImpl { conn: &conn };
     let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
     let user_usecase = UserUsecase {
         user_repository,
         patient_repository,
         doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
     };
 
-    let token = user_usecase
-        .sign_in(form.code.clone(), form.password.clone())
+    let (token, refresh_token) = user_usecase
+        .refresh(form.refresh_token.clone(), client_info(&req))
         .await?;
-    let fetch_user_response = SignInResponse::from(token);
+    let refresh_response = RefreshResponse::from(token, refresh_token);
 
-    Ok(HttpResponse::Ok().json(fetch_user_response))
+    Ok(HttpResponse::Ok().json(refresh_response))
 }
 
 pub async fn assign(
     state: web::Data<AppState>,
-    req: HttpRequest,
+    user: AuthenticatedUser,
     form: web::Json<AssignRequest>,
 ) -> ApiResponse {
+    user.require(Permission::AssignPatient)?;
     let conn = state.get_sqls_db_conn()?;
-    let user_id = authn::get_user_id_from_header(&req).unwrap();
     let user_repository = UserRepositoryImpl { conn: &conn };
     let patient_repository = PatientRepositoryImpl { conn: &conn };
     let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
     let user_usecase = UserUsecase {
         user_repository,
         patient_repository,
         doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
     };
 
-    user_usecase
-        .assign(user_id.clone(), form.patient_code.clone())
+    let care_assignment = user_usecase
+        .assign(
+            user.user_id.clone(),
+            form.code.clone(),
+            form.patient_code.clone(),
+            form.care_role,
+            form.starts_at,
+            form.ends_at,
+            form.reason.clone(),
+        )
         .await?;
-    let assign_response = AssignResponse::from();
+    let assign_response = AssignResponse::from(care_assignment);
 
     Ok(HttpResponse::Ok().json(assign_response))
 }
+
+pub async fn unassign(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<UnassignRequest>,
+) -> ApiResponse {
+    user.require(Permission::AssignPatient)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    user_usecase
+        .unassign(user.user_id, form.code.clone(), form.patient_code.clone())
+        .await?;
+    let unassign_response = UnassignResponse::from();
+
+    Ok(HttpResponse::Ok().json(unassign_response))
+}
+
+pub async fn transfer(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<TransferRequest>,
+) -> ApiResponse {
+    user.require(Permission::AssignPatient)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let care_assignment = user_usecase
+        .transfer(
+            form.from_code.clone(),
+            form.to_code.clone(),
+            form.patient_code.clone(),
+            form.reason.clone(),
+        )
+        .await?;
+    let transfer_response = TransferResponse::from(care_assignment);
+
+    Ok(HttpResponse::Ok().json(transfer_response))
+}
+
+pub async fn fetch_doctors_in_charge(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    params: web::Query<FetchDoctorsInChargeParameter>,
+) -> ApiResponse {
+    user.require(Permission::ReadPatient)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let care_team = user_usecase
+        .fetch_care_team(
+            params.patient_code.clone(),
+            params.include_history.unwrap_or(false),
+        )
+        .await?;
+    let res = FetchDoctorsInChargeResponse::from(care_team);
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// patients of other user can be seen by who can assign patients.
+pub async fn fetch_assigned_patients(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    params: web::Query<FetchAssignedPatientsParameter>,
+) -> ApiResponse {
+    user.require(Permission::ReadPatient)?;
+    if params.code.is_some() {
+        user.require(Permission::AssignPatient)?;
+    }
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let patients = user_usecase
+        .fetch_assigned_patients(
+            user.user_id,
+            params.code.clone(),
+            params.include_archived.unwrap_or(false),
+        )
+        .await?;
+    let res = FetchAssignedPatientsResponse::from(patients);
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+pub async fn change_role(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<ChangeRoleRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    user_usecase
+        .change_role(form.code.clone(), form.role)
+        .await?;
+    let change_role_response = ChangeRoleResponse::from();
+
+    Ok(HttpResponse::Ok().json(change_role_response))
+}
+
+pub async fn unlock(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<UnlockRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    user_usecase.unlock(form.code.clone()).await?;
+    let unlock_response = UnlockResponse::from();
+
+    Ok(HttpResponse::Ok().json(unlock_response))
+}
+
+pub async fn sign_in_two_factor(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    form: web::Json<SignInTwoFactorRequest>,
+) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let (token, refresh_token) = user_usecase
+        .sign_in_two_factor(
+            form.two_factor_token.clone(),
+            form.code.clone(),
+            client_info(&req),
+        )
+        .await?;
+    let sign_in_two_factor_response = SignInTwoFactorResponse::from(token, refresh_token);
+
+    Ok(HttpResponse::Ok().json(sign_in_two_factor_response))
+}
+
+pub async fn enroll_two_factor(state: web::Data<AppState>, user: AuthenticatedUser) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let (secret, otpauth_uri) = user_usecase.enroll_two_factor(user.user_id).await?;
+    let enroll_two_factor_response = EnrollTwoFactorResponse::from(secret, otpauth_uri);
+
+    Ok(HttpResponse::Ok().json(enroll_two_factor_response))
+}
+
+pub async fn confirm_two_factor(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<ConfirmTwoFactorRequest>,
+) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let recovery_codes = user_usecase
+        .confirm_two_factor(user.user_id, form.code.clone())
+        .await?;
+    let confirm_two_factor_response = ConfirmTwoFactorResponse::from(recovery_codes);
+
+    Ok(HttpResponse::Ok().json(confirm_two_factor_response))
+}
+
+pub async fn change_password(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    form: web::Json<ChangePasswordRequest>,
+) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let (token, refresh_token) = user_usecase
+        .change_password(
+            user.user_id,
+            form.current_password.clone(),
+            form.password.clone(),
+            client_info(&req),
+        )
+        .await?;
+    let change_password_response = ChangePasswordResponse::from(token, refresh_token);
+
+    Ok(HttpResponse::Ok().json(change_password_response))
+}
+
+pub async fn fetch_me(state: web::Data<AppState>, user: AuthenticatedUser) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let me = user_usecase.fetch_me(user.user_id).await?;
+    let fetch_user_response = FetchUserResponse::from(me);
+
+    Ok(HttpResponse::Ok().json(fetch_user_response))
+}
+
+pub async fn update_name(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<UpdateNameRequest>,
+) -> ApiResponse {
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let me = user_usecase
+        .update_name(user.user_id, form.name.clone())
+        .await?;
+    let fetch_user_response = FetchUserResponse::from(me);
+
+    Ok(HttpResponse::Ok().json(fetch_user_response))
+}
+
+pub async fn fetch_users(state: web::Data<AppState>, user: AuthenticatedUser) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let users = user_usecase.fetch_users().await?;
+    let fetch_users_response = FetchUsersResponse::from(users);
+
+    Ok(HttpResponse::Ok().json(fetch_users_response))
+}
+
+pub async fn deactivate(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<ChangeActiveRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    user_usecase
+        .deactivate(user.user_id, form.code.clone())
+        .await?;
+    let change_active_response = ChangeActiveResponse::from();
+
+    Ok(HttpResponse::Ok().json(change_active_response))
+}
+
+pub async fn reactivate(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<ChangeActiveRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    user_usecase.reactivate(form.code.clone()).await?;
+    let change_active_response = ChangeActiveResponse::from();
+
+    Ok(HttpResponse::Ok().json(change_active_response))
+}
+
+pub async fn fetch_emergency_accesses(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    params: web::Query<FetchEmergencyAccessesParameter>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let emergency_accesses = user_usecase
+        .fetch_emergency_accesses(params.unacknowledged_only)
+        .await?;
+    let res = FetchEmergencyAccessesResponse::from(emergency_accesses);
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+pub async fn acknowledge_emergency_access(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<AcknowledgeEmergencyAccessRequest>,
+) -> ApiResponse {
+    user.require(Permission::ManageUser)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    user_usecase
+        .acknowledge_emergency_access(user.user_id, form.id.clone())
+        .await?;
+    let acknowledge_emergency_access_response = AcknowledgeEmergencyAccessResponse::from();
+
+    Ok(HttpResponse::Ok().json(acknowledge_emergency_access_response))
+}
+
+/// the caller lends access to the patients of the own, e.g. before leave.
+pub async fn delegate(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<DelegateRequest>,
+) -> ApiResponse {
+    user.require(Permission::AssignPatient)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let delegation = user_usecase
+        .delegate(
+            user.user_id,
+            form.to_code.clone(),
+            form.patient_codes.clone(),
+            form.starts_at,
+            form.ends_at,
+        )
+        .await?;
+    let delegate_response = DelegateResponse::from(delegation);
+
+    Ok(HttpResponse::Ok().json(delegate_response))
+}
+
+/// delegations the caller gave or received.
+pub async fn fetch_delegations(state: web::Data<AppState>, user: AuthenticatedUser) -> ApiResponse {
+    user.require(Permission::ReadPatient)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    let delegations = user_usecase.fetch_delegations(user.user_id).await?;
+    let res = FetchDelegationsResponse::from(delegations);
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+pub async fn revoke_delegation(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    form: web::Json<RevokeDelegationRequest>,
+) -> ApiResponse {
+    user.require(Permission::ReadPatient)?;
+    let conn = state.get_sqls_db_conn()?;
+    let user_repository = UserRepositoryImpl { conn: &conn };
+    let patient_repository = PatientRepositoryImpl { conn: &conn };
+    let doctor_in_charge_repository = DoctorInChargeRepositoryImpl { conn: &conn };
+    let refresh_token_repository = RefreshTokenRepositoryImpl { conn: &conn };
+    let login_attempt_repository = LoginAttemptRepositoryImpl { conn: &conn };
+    let two_factor_repository = TwoFactorRepositoryImpl { conn: &conn };
+    let password_history_repository = PasswordHistoryRepositoryImpl { conn: &conn };
+    let session_repository = SessionRepositoryImpl { conn: &conn };
+    let user_usecase = UserUsecase {
+        user_repository,
+        patient_repository,
+        doctor_in_charge_repository,
+        refresh_token_repository,
+        login_attempt_repository,
+        two_factor_repository,
+        password_history_repository,
+        session_repository,
+        lockout_policy: state.lockout_policy.clone(),
+        password_policy: state.password_policy.clone(),
+    };
+
+    user_usecase
+        .revoke_delegation(user.user_id, form.id.clone())
+        .await?;
+    let revoke_delegation_response = RevokeDelegationResponse::from();
+
+    Ok(HttpResponse::Ok().json(revoke_delegation_response))
+}
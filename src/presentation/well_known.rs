@@ -0,0 +1,12 @@
+use actix_web::HttpResponse;
+
+use crate::middleware::keyring::keyring;
+use crate::utils::errors::MyError;
+
+pub type ApiResponse = Result<HttpResponse, MyError>;
+
+/// public keys to verify jwt. HS256 keys are not published.
+pub async fn fetch_jwks() -> ApiResponse {
+    let jwk_set = keyring().jwk_set();
+    Ok(HttpResponse::Ok().json(jwk_set))
+}
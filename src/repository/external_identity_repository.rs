@@ -0,0 +1,186 @@
+use crate::domain::oidc::{ExternalIdentity, ExternalIdentityRepository, OidcLoginState};
+use crate::utils::datetime::DATETIME_FMT;
+use crate::utils::errors::MyError;
+
+use async_trait::async_trait;
+use chrono::{Duration, Local, TimeZone};
+use sqlx::MySqlPool;
+
+pub struct ExternalIdentityRepositoryImpl<'a> {
+    pub conn: &'a MySqlPool,
+}
+
+#[async_trait]
+impl ExternalIdentityRepository for ExternalIdentityRepositoryImpl<'_> {
+    async fn save(&self, external_identity: &ExternalIdentity) -> Result<(), MyError> {
+        sqlx::query!(
+            "insert into external_identities(id,user_id,issuer,subject,created_at)
+            values(?,?,?,?,?)
+            ",
+            external_identity.id,
+            external_identity.user_id,
+            external_identity.issuer,
+            external_identity.subject,
+            external_identity
+                .created_at
+                .format(DATETIME_FMT)
+                .to_string(),
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_by_subject(
+        &self,
+        issuer: &String,
+        subject: &String,
+    ) -> Result<Option<ExternalIdentity>, MyError> {
+        let record = sqlx::query!(
+            "select id,user_id,issuer,subject,created_at
+            from external_identities
+            where issuer=? and subject=?
+            ",
+            issuer,
+            subject
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        match record {
+            Some(record) => Ok(Some(ExternalIdentity::from(
+                record.id,
+                record.user_id,
+                record.issuer,
+                record.subject,
+                Local
+                    .datetime_from_str(&record.created_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    async fn save_login_state(&self, login_state: &OidcLoginState) -> Result<(), MyError> {
+        sqlx::query!(
+            "insert into oidc_login_states(id,nonce,code_verifier,expires_at)
+            values(?,?,?,?)
+            ",
+            login_state.id,
+            login_state.nonce,
+            login_state.code_verifier,
+            login_state.expires_at.format(DATETIME_FMT).to_string(),
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn take_login_state(&self, id: &String) -> Result<Option<OidcLoginState>, MyError> {
+        let record = sqlx::query!(
+            "select id,nonce,code_verifier,expires_at
+            from oidc_login_states
+            where id=?
+            ",
+            id
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        let record = match record {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        // only the request which deleted the row can use it, so that one state logs in once.
+        let result = sqlx::query!("delete from oidc_login_states where id=?", id)
+            .execute(self.conn)
+            .await?;
+        if result.rows_affected() != 1 {
+            return Ok(None);
+        }
+        Ok(Some(OidcLoginState::from(
+            record.id,
+            record.nonce,
+            record.code_verifier,
+            Local
+                .datetime_from_str(&record.expires_at.to_string(), DATETIME_FMT)
+                .unwrap(),
+        )))
+    }
+}
+
+pub struct ExternalIdentityRepositoryMockImpl {}
+
+#[async_trait]
+impl ExternalIdentityRepository for ExternalIdentityRepositoryMockImpl {
+    /// nothing is done.
+    async fn save(&self, external_identity: &ExternalIdentity) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return one of get_external_identities() which issuer and subject match.
+    async fn fetch_by_subject(
+        &self,
+        issuer: &String,
+        subject: &String,
+    ) -> Result<Option<ExternalIdentity>, MyError> {
+        Ok(get_external_identities()
+            .into_iter()
+            .find(|external_identity| {
+                &external_identity.issuer == issuer && &external_identity.subject == subject
+            }))
+    }
+
+    /// nothing is done.
+    async fn save_login_state(&self, login_state: &OidcLoginState) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return one of get_login_states() which id matches.
+    async fn take_login_state(&self, id: &String) -> Result<Option<OidcLoginState>, MyError> {
+        Ok(get_login_states()
+            .into_iter()
+            .find(|login_state| &login_state.id == id))
+    }
+}
+
+pub const TEST_ISSUER: &str = "http://localhost:8080";
+/// nonce of every test login state.
+pub const TEST_NONCE: &str = "test_nonce";
+
+/// test data. "test_subject" is linked to "test_id",
+/// and "test_deactivated_subject" is linked to "test_deactivated_id".
+pub fn get_external_identities() -> Vec<ExternalIdentity> {
+    vec![
+        ExternalIdentity::from(
+            "test_external_identity_id_1".to_string(),
+            "test_id".to_string(),
+            TEST_ISSUER.to_string(),
+            "test_subject".to_string(),
+            Local::now(),
+        ),
+        ExternalIdentity::from(
+            "test_external_identity_id_2".to_string(),
+            "test_deactivated_id".to_string(),
+            TEST_ISSUER.to_string(),
+            "test_deactivated_subject".to_string(),
+            Local::now(),
+        ),
+    ]
+}
+
+/// test data. "test_state" is valid and "test_expired_state" has expired.
+pub fn get_login_states() -> Vec<OidcLoginState> {
+    vec![
+        OidcLoginState::from(
+            "test_state".to_string(),
+            TEST_NONCE.to_string(),
+            "test_code_verifier".to_string(),
+            Local::now() + Duration::minutes(10),
+        ),
+        OidcLoginState::from(
+            "test_expired_state".to_string(),
+            TEST_NONCE.to_string(),
+            "test_code_verifier".to_string(),
+            Local::now() - Duration::minutes(1),
+        ),
+    ]
+}
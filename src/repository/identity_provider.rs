@@ -0,0 +1,470 @@
+use crate::domain::oidc::{IdTokenClaims, IdentityProvider, OidcConfig, OidcLoginState};
+use crate::repository::external_identity_repository::{TEST_ISSUER, TEST_NONCE};
+use crate::utils::errors::MyError;
+
+use async_trait::async_trait;
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use log::{error, warn};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::json;
+
+const DISCOVERY_PATH: &str = "/.well-known/openid-configuration";
+const SCOPE: &str = "openid profile";
+
+/// part of discovery document we use.
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+pub struct IdentityProviderImpl {
+    pub config: OidcConfig,
+}
+
+impl IdentityProviderImpl {
+    /// discovery document. fetched on every login instead of cached, as login is not frequent
+    /// and keys rotated by the identity provider are picked up at once.
+    async fn metadata(&self) -> Result<ProviderMetadata, MyError> {
+        let metadata = reqwest::get(format!("{}{}", self.config.issuer, DISCOVERY_PATH))
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(provider_error)?
+            .json::<ProviderMetadata>()
+            .await
+            .map_err(provider_error)?;
+        if metadata.issuer.trim_end_matches('/') != self.config.issuer {
+            error!("issuer of discovery document is {}", metadata.issuer);
+            return Err(MyError::InternalServerError);
+        }
+        Ok(metadata)
+    }
+
+    /// verify signature with JWKS, and iss, aud and exp.
+    /// algorithm is the one of the signing key, not the one id token claims in its header.
+    async fn verify_id_token(
+        &self,
+        id_token: &str,
+        metadata: &ProviderMetadata,
+    ) -> Result<IdTokenClaims, MyError> {
+        let header = decode_header(id_token)?;
+        let jwk_set = reqwest::get(&metadata.jwks_uri)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(provider_error)?
+            .json::<JwkSet>()
+            .await
+            .map_err(provider_error)?;
+        let jwk = match &header.kid {
+            Some(kid) => jwk_set.find(kid),
+            None => jwk_set.keys.first(),
+        }
+        .ok_or_else(|| {
+            MyError::Unauthorized(json!({"error":"signing key of id token is not found"}))
+        })?;
+        let algorithm = jwk_algorithm(jwk).ok_or_else(|| {
+            MyError::Unauthorized(json!({"error":"algorithm of id token is not supported"}))
+        })?;
+        if header.alg != algorithm {
+            return Err(MyError::Unauthorized(
+                json!({"error":"algorithm of id token does not match its signing key"}),
+            ));
+        }
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&metadata.issuer]);
+        let claims =
+            decode::<IdTokenClaims>(id_token, &DecodingKey::from_jwk(jwk)?, &validation)?.claims;
+        Ok(claims)
+    }
+}
+
+/// algorithm of the key. alg of JWK is optional, then it follows from the key type.
+/// RSA key without alg is taken as RS256, the default of OpenID Connect.
+/// shared secret is not published, so that HMAC is not supported.
+fn jwk_algorithm(jwk: &Jwk) -> Option<Algorithm> {
+    let algorithm = match (jwk.common.algorithm, &jwk.algorithm) {
+        (Some(algorithm), _) => algorithm,
+        (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+        (None, AlgorithmParameters::EllipticCurve(params)) => match params.curve {
+            EllipticCurve::P256 => Algorithm::ES256,
+            EllipticCurve::P384 => Algorithm::ES384,
+            _ => return None,
+        },
+        (None, AlgorithmParameters::OctetKeyPair(params)) => match params.curve {
+            EllipticCurve::Ed25519 => Algorithm::EdDSA,
+            _ => return None,
+        },
+        (None, AlgorithmParameters::OctetKey(_)) => return None,
+    };
+    if matches!(
+        algorithm,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return None;
+    }
+    Some(algorithm)
+}
+
+#[async_trait]
+impl IdentityProvider for IdentityProviderImpl {
+    async fn authorization_url(&self, login_state: &OidcLoginState) -> Result<String, MyError> {
+        let metadata = self.metadata().await?;
+        let code_challenge = login_state.code_challenge();
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("scope", SCOPE),
+                ("state", login_state.id.as_str()),
+                ("nonce", login_state.nonce.as_str()),
+                ("code_challenge", code_challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|err| {
+            error!("failed to build authorization url: {}", err);
+            MyError::InternalServerError
+        })?;
+        Ok(url.to_string())
+    }
+
+    async fn exchange_code(
+        &self,
+        code: &str,
+        login_state: &OidcLoginState,
+    ) -> Result<IdTokenClaims, MyError> {
+        let metadata = self.metadata().await?;
+        let response = reqwest::Client::new()
+            .post(&metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("client_id", self.config.client_id.as_str()),
+                ("client_secret", self.config.client_secret.as_str()),
+                ("code_verifier", login_state.code_verifier.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(provider_error)?;
+        if !response.status().is_success() {
+            warn!("token endpoint returned {}", response.status());
+            return Err(MyError::Unauthorized(
+                json!({"error":"authorization code is invalid"}),
+            ));
+        }
+        let token_response = response
+            .json::<TokenResponse>()
+            .await
+            .map_err(provider_error)?;
+        self.verify_id_token(&token_response.id_token, &metadata)
+            .await
+    }
+}
+
+/// identity provider is unreachable or broken.
+fn provider_error(err: reqwest::Error) -> MyError {
+    error!("identity provider request failed: {}", err);
+    MyError::InternalServerError
+}
+
+pub struct IdentityProviderMockImpl {}
+
+#[async_trait]
+impl IdentityProvider for IdentityProviderMockImpl {
+    /// return authorize url of TEST_ISSUER with state.
+    async fn authorization_url(&self, login_state: &OidcLoginState) -> Result<String, MyError> {
+        Ok(format!(
+            "{}/authorize?state={}",
+            TEST_ISSUER, login_state.id
+        ))
+    }
+
+    /// code is used as subject of id token. nonce is TEST_NONCE.
+    /// "test_invalid_code" is Unauthorized, and "test_replayed_code" has other nonce.
+    async fn exchange_code(
+        &self,
+        code: &str,
+        login_state: &OidcLoginState,
+    ) -> Result<IdTokenClaims, MyError> {
+        let nonce = match code {
+            "test_invalid_code" => {
+                return Err(MyError::Unauthorized(
+                    json!({"error":"authorization code is invalid"}),
+                ))
+            }
+            "test_replayed_code" => "other_nonce",
+            _ => TEST_NONCE,
+        };
+        Ok(IdTokenClaims {
+            iss: TEST_ISSUER.to_string(),
+            sub: code.to_string(),
+            nonce: Some(nonce.to_string()),
+            name: Some("test_external_name".to_string()),
+            preferred_username: None,
+        })
+    }
+}
+
+#[cfg(test)]
+
+mod tests {
+    use super::*;
+    use crate::domain::user::Role;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use chrono::{Duration, Local};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+
+    const TEST_CLIENT_ID: &str = "test_client_id";
+    const TEST_CLIENT_SECRET: &str = "test_client_secret";
+    const TEST_IDP_KID: &str = "test_idp_kid";
+
+    /// how MockIdp signs id token.
+    #[derive(Clone, Copy, PartialEq)]
+    enum Signing {
+        /// with the published key.
+        Published,
+        /// with a key which is not published.
+        Forged,
+        /// with the published key, but header claims RS256.
+        AlgorithmReplaced,
+    }
+
+    /// local identity provider serving discovery, JWKS and token endpoints.
+    /// id token is issued to audience for any code except "test_invalid_code".
+    #[derive(Clone)]
+    struct MockIdp {
+        issuer: String,
+        audience: String,
+        published_pkcs8: Vec<u8>,
+        signing_pkcs8: Vec<u8>,
+        signing: Signing,
+    }
+
+    fn generate_pkcs8() -> Vec<u8> {
+        Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .unwrap()
+            .as_ref()
+            .to_vec()
+    }
+
+    async fn discovery(idp: web::Data<MockIdp>) -> HttpResponse {
+        HttpResponse::Ok().json(json!({
+            "issuer": idp.issuer,
+            "authorization_endpoint": format!("{}/authorize", idp.issuer),
+            "token_endpoint": format!("{}/token", idp.issuer),
+            "jwks_uri": format!("{}/jwks", idp.issuer),
+        }))
+    }
+
+    async fn jwks(idp: web::Data<MockIdp>) -> HttpResponse {
+        let key_pair = Ed25519KeyPair::from_pkcs8(&idp.published_pkcs8).unwrap();
+        HttpResponse::Ok().json(json!({
+            "keys": [{
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "x": URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+                "kid": TEST_IDP_KID,
+                "alg": "EdDSA",
+                "use": "sig",
+            }]
+        }))
+    }
+
+    async fn token(
+        idp: web::Data<MockIdp>,
+        form: web::Form<HashMap<String, String>>,
+    ) -> HttpResponse {
+        let valid = form.get("code").map(String::as_str) != Some("test_invalid_code")
+            && form.get("client_secret").map(String::as_str) == Some(TEST_CLIENT_SECRET)
+            && form.contains_key("code_verifier");
+        if !valid {
+            return HttpResponse::BadRequest().json(json!({"error":"invalid_grant"}));
+        }
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(TEST_IDP_KID.to_string());
+        let claims = json!({
+            "iss": idp.issuer,
+            "sub": "test_subject",
+            "aud": idp.audience,
+            "exp": (Local::now() + Duration::minutes(5)).timestamp(),
+            "nonce": "test_nonce",
+            "name": "test_external_name",
+        });
+        let mut id_token = encode(
+            &header,
+            &claims,
+            &EncodingKey::from_ed_der(&idp.signing_pkcs8),
+        )
+        .unwrap();
+        if idp.signing == Signing::AlgorithmReplaced {
+            let replaced_header =
+                URL_SAFE_NO_PAD.encode(json!({"alg":"RS256","kid":TEST_IDP_KID}).to_string());
+            let (_, rest) = id_token.split_once('.').unwrap();
+            id_token = format!("{}.{}", replaced_header, rest);
+        }
+        HttpResponse::Ok().json(json!({
+            "access_token": "test_access_token",
+            "token_type": "Bearer",
+            "id_token": id_token,
+        }))
+    }
+
+    /// start MockIdp on free port and return IdentityProviderImpl configured for it.
+    fn start_mock_idp(audience: &str, signing: Signing) -> IdentityProviderImpl {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let published_pkcs8 = generate_pkcs8();
+        let idp = MockIdp {
+            issuer: issuer.clone(),
+            audience: audience.to_string(),
+            signing_pkcs8: if signing == Signing::Forged {
+                generate_pkcs8()
+            } else {
+                published_pkcs8.clone()
+            },
+            published_pkcs8,
+            signing,
+        };
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(idp.clone()))
+                .route(DISCOVERY_PATH, web::get().to(discovery))
+                .route("/jwks", web::get().to(jwks))
+                .route("/token", web::post().to(token))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+        IdentityProviderImpl {
+            config: OidcConfig {
+                issuer,
+                client_id: TEST_CLIENT_ID.to_string(),
+                client_secret: TEST_CLIENT_SECRET.to_string(),
+                redirect_uri: "http://localhost:8000/api/oidc/callback".to_string(),
+                auto_provision: false,
+                provision_role: Role::Receptionist,
+                provision_approval_required: true,
+            },
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_authorization_url() {
+        let identity_provider = start_mock_idp(TEST_CLIENT_ID, Signing::Published);
+        let login_state = OidcLoginState::new();
+        let url = Url::parse(
+            &identity_provider
+                .authorization_url(&login_state)
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            url.as_str().split('?').next().unwrap(),
+            format!("{}/authorize", identity_provider.config.issuer)
+        );
+        let params = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+        assert_eq!(params["client_id"], TEST_CLIENT_ID);
+        assert_eq!(params["state"], login_state.id);
+        assert_eq!(params["nonce"], login_state.nonce);
+        assert_eq!(params["code_challenge"], login_state.code_challenge());
+        assert_eq!(params["code_challenge_method"], "S256");
+    }
+
+    #[actix_web::test]
+    async fn test_exchange_code() {
+        let identity_provider = start_mock_idp(TEST_CLIENT_ID, Signing::Published);
+        let claims = identity_provider
+            .exchange_code("test_code", &OidcLoginState::new())
+            .await
+            .unwrap();
+        assert_eq!(claims.iss, identity_provider.config.issuer);
+        assert_eq!(claims.sub, "test_subject");
+        assert_eq!(claims.nonce, Some("test_nonce".to_string()));
+        assert_eq!(claims.name, Some("test_external_name".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn test_exchange_code_failed() {
+        let identity_provider = start_mock_idp(TEST_CLIENT_ID, Signing::Published);
+        let err = identity_provider
+            .exchange_code("test_invalid_code", &OidcLoginState::new())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Unauthorized(json!({"error":"authorization code is invalid"}))
+        );
+
+        // id token for other client, or signed with unpublished key.
+        for identity_provider in [
+            start_mock_idp("other_client_id", Signing::Published),
+            start_mock_idp(TEST_CLIENT_ID, Signing::Forged),
+        ] {
+            let err = identity_provider
+                .exchange_code("test_code", &OidcLoginState::new())
+                .await
+                .unwrap_err();
+            assert!(matches!(err, MyError::Unauthorized(_)));
+        }
+
+        // algorithm is not taken from header of id token.
+        let err = start_mock_idp(TEST_CLIENT_ID, Signing::AlgorithmReplaced)
+            .exchange_code("test_code", &OidcLoginState::new())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Unauthorized(
+                json!({"error":"algorithm of id token does not match its signing key"})
+            )
+        );
+    }
+
+    #[test]
+    fn test_jwk_algorithm() {
+        for (jwk, expected) in [
+            (
+                json!({"kty":"OKP","crv":"Ed25519","x":"test_x"}),
+                Some(Algorithm::EdDSA),
+            ),
+            (
+                json!({"kty":"EC","crv":"P-256","x":"test_x","y":"test_y"}),
+                Some(Algorithm::ES256),
+            ),
+            (
+                json!({"kty":"RSA","n":"test_n","e":"AQAB","alg":"PS256"}),
+                Some(Algorithm::PS256),
+            ),
+            (
+                json!({"kty":"RSA","n":"test_n","e":"AQAB"}),
+                Some(Algorithm::RS256),
+            ),
+            (json!({"kty":"oct","k":"test_k","alg":"HS256"}), None),
+        ] {
+            let jwk = serde_json::from_value::<Jwk>(jwk).unwrap();
+            assert_eq!(jwk_algorithm(&jwk), expected);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::domain::invitation::{Invitation, InvitationRepository};
+use crate::domain::user::Role;
+use crate::utils::datetime::DATETIME_FMT;
+use crate::utils::errors::MyError;
+use crate::utils::hash::digest_token;
+
+use async_trait::async_trait;
+use chrono::{Duration, Local, TimeZone};
+use sqlx::MySqlPool;
+use std::str::FromStr;
+
+pub struct InvitationRepositoryImpl<'a> {
+    pub conn: &'a MySqlPool,
+}
+
+#[async_trait]
+impl InvitationRepository for InvitationRepositoryImpl<'_> {
+    async fn save(&self, invitation: &Invitation) -> Result<(), MyError> {
+        sqlx::query!(
+            "insert into invitations(id,role,hashed_code,expires_at,approval_required,created_by,revoked)
+            values(?,?,?,?,?,?,?)
+            ",
+            invitation.id,
+            invitation.role.to_string(),
+            invitation.hashed_code,
+            invitation.expires_at.format(DATETIME_FMT).to_string(),
+            invitation.approval_required,
+            invitation.created_by,
+            invitation.revoked,
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_one(&self, id: &String) -> Result<Option<Invitation>, MyError> {
+        let record = sqlx::query!(
+            "select id,role,hashed_code,expires_at,approval_required,created_by,used_by,revoked
+            from invitations
+            where id=?
+            ",
+            id
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        match record {
+            Some(record) => Ok(Some(Invitation::from(
+                record.id,
+                Role::from_str(&record.role)?,
+                record.hashed_code,
+                Local
+                    .datetime_from_str(&record.expires_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                record.approval_required != 0,
+                record.created_by,
+                record.used_by,
+                record.revoked != 0,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    async fn fetch_all(&self) -> Result<Vec<Invitation>, MyError> {
+        let records = sqlx::query!(
+            "select id,role,hashed_code,expires_at,approval_required,created_by,used_by,revoked
+            from invitations
+            order by created_at
+            "
+        )
+        .fetch_all(self.conn)
+        .await?;
+        let mut invitations = vec![];
+        for record in records {
+            invitations.push(Invitation::from(
+                record.id,
+                Role::from_str(&record.role)?,
+                record.hashed_code,
+                Local
+                    .datetime_from_str(&record.expires_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                record.approval_required != 0,
+                record.created_by,
+                record.used_by,
+                record.revoked != 0,
+            ));
+        }
+        Ok(invitations)
+    }
+
+    async fn revoke(&self, id: &String) -> Result<(), MyError> {
+        sqlx::query!("update invitations set revoked=true where id=?", id)
+            .execute(self.conn)
+            .await?;
+        Ok(())
+    }
+}
+
+pub struct InvitationRepositoryMockImpl {}
+
+#[async_trait]
+impl InvitationRepository for InvitationRepositoryMockImpl {
+    /// nothing is done.
+    async fn save(&self, invitation: &Invitation) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return one of get_invitations() which id matches.
+    async fn fetch_one(&self, id: &String) -> Result<Option<Invitation>, MyError> {
+        Ok(get_invitations()
+            .into_iter()
+            .find(|invitation| &invitation.id == id))
+    }
+
+    /// return get_invitations().
+    async fn fetch_all(&self) -> Result<Vec<Invitation>, MyError> {
+        Ok(get_invitations())
+    }
+
+    /// nothing is done.
+    async fn revoke(&self, id: &String) -> Result<(), MyError> {
+        Ok(())
+    }
+}
+
+/// raw secret of every test invitation.
+pub const TEST_INVITATION_SECRET: &str = "test_secret";
+
+/// test data created by "test_id". _1 is usable for nurse, _2 is usable but requires approval,
+/// _3 has been used, _4 is revoked and _5 has expired.
+pub fn get_invitations() -> Vec<Invitation> {
+    vec![
+        Invitation::from(
+            "test_invitation_id_1".to_string(),
+            Role::Nurse,
+            digest_token(TEST_INVITATION_SECRET),
+            Local::now() + Duration::days(7),
+            false,
+            "test_id".to_string(),
+            None,
+            false,
+        ),
+        Invitation::from(
+            "test_invitation_id_2".to_string(),
+            Role::Doctor,
+            digest_token(TEST_INVITATION_SECRET),
+            Local::now() + Duration::days(7),
+            true,
+            "test_id".to_string(),
+            None,
+            false,
+        ),
+        Invitation::from(
+            "test_invitation_id_3".to_string(),
+            Role::Doctor,
+            digest_token(TEST_INVITATION_SECRET),
+            Local::now() + Duration::days(7),
+            false,
+            "test_id".to_string(),
+            Some("test_two_factor_id".to_string()),
+            false,
+        ),
+        Invitation::from(
+            "test_invitation_id_4".to_string(),
+            Role::Doctor,
+            digest_token(TEST_INVITATION_SECRET),
+            Local::now() + Duration::days(7),
+            false,
+            "test_id".to_string(),
+            None,
+            true,
+        ),
+        Invitation::from(
+            "test_invitation_id_5".to_string(),
+            Role::Doctor,
+            digest_token(TEST_INVITATION_SECRET),
+            Local::now() - Duration::days(1),
+            false,
+            "test_id".to_string(),
+            None,
+            false,
+        ),
+    ]
+}
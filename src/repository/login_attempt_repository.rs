@@ -0,0 +1,168 @@
+use crate::domain::login_attempt::{
+    AttemptTarget, LockoutPolicy, LoginAttempt, LoginAttemptRepository,
+};
+use crate::utils::datetime::DATETIME_FMT;
+use crate::utils::errors::MyError;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Local, TimeZone};
+use sqlx::MySqlPool;
+
+pub struct LoginAttemptRepositoryImpl<'a> {
+    pub conn: &'a MySqlPool,
+}
+
+#[async_trait]
+impl LoginAttemptRepository for LoginAttemptRepositoryImpl<'_> {
+    async fn fetch(
+        &self,
+        target_type: &AttemptTarget,
+        target: &String,
+    ) -> Result<LoginAttempt, MyError> {
+        let record = sqlx::query!(
+            "select failed_count,last_failed_at,locked_until
+            from login_attempts
+            where target_type=? and target=?
+            ",
+            target_type.to_string(),
+            target
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        if let Some(record) = record {
+            let login_attempt = LoginAttempt::from(
+                *target_type,
+                target.clone(),
+                record.failed_count as u32,
+                record.last_failed_at.map(|last_failed_at| {
+                    Local
+                        .datetime_from_str(&last_failed_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+                record.locked_until.map(|locked_until| {
+                    Local
+                        .datetime_from_str(&locked_until.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+            );
+            Ok(login_attempt)
+        } else {
+            Ok(LoginAttempt::new(*target_type, target.clone()))
+        }
+    }
+
+    async fn record_failure(
+        &self,
+        target_type: &AttemptTarget,
+        target: &String,
+        now: DateTime<Local>,
+        policy: &LockoutPolicy,
+    ) -> Result<LoginAttempt, MyError> {
+        let max_failures = policy.max_failures_of(*target_type);
+        let locked_until = (now + policy.lockout_duration)
+            .format(DATETIME_FMT)
+            .to_string();
+        let window_start = (now - policy.failure_window)
+            .format(DATETIME_FMT)
+            .to_string();
+        let now = now.format(DATETIME_FMT).to_string();
+        // assignments are evaluated in order. locked_until sees failed_count already counted up,
+        // which is 1 if failures have started over.
+        sqlx::query!(
+            "insert into login_attempts(target_type,target,failed_count,last_failed_at,locked_until)
+            values(?,?,1,?,if(1>=?,?,null))
+            on duplicate key update
+            failed_count=if(locked_until<=? or last_failed_at<=?,1,failed_count+1),
+            locked_until=if(failed_count>=?,?,if(failed_count=1,null,locked_until)),
+            last_failed_at=values(last_failed_at)
+            ",
+            target_type.to_string(),
+            target,
+            now,
+            max_failures,
+            locked_until,
+            now,
+            window_start,
+            max_failures,
+            locked_until,
+        )
+        .execute(self.conn)
+        .await?;
+        self.fetch(target_type, target).await
+    }
+
+    async fn delete(&self, target_type: &AttemptTarget, target: &String) -> Result<(), MyError> {
+        sqlx::query!(
+            "delete from login_attempts where target_type=? and target=?",
+            target_type.to_string(),
+            target
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+}
+
+pub struct LoginAttemptRepositoryMockImpl {}
+
+#[async_trait]
+impl LoginAttemptRepository for LoginAttemptRepositoryMockImpl {
+    /// return one of get_login_attempts() which target matches.
+    /// not found then return LoginAttempt without failure.
+    async fn fetch(
+        &self,
+        target_type: &AttemptTarget,
+        target: &String,
+    ) -> Result<LoginAttempt, MyError> {
+        match get_login_attempts().into_iter().find(|login_attempt| {
+            &login_attempt.target_type == target_type && &login_attempt.target == target
+        }) {
+            Some(login_attempt) => Ok(login_attempt),
+            None => Ok(LoginAttempt::new(*target_type, target.clone())),
+        }
+    }
+
+    /// count up failure of the one fetch() returns. nothing is stored.
+    async fn record_failure(
+        &self,
+        target_type: &AttemptTarget,
+        target: &String,
+        now: DateTime<Local>,
+        policy: &LockoutPolicy,
+    ) -> Result<LoginAttempt, MyError> {
+        let mut login_attempt = self.fetch(target_type, target).await?;
+        login_attempt.record_failure(now, policy);
+        Ok(login_attempt)
+    }
+
+    /// nothing is done.
+    async fn delete(&self, target_type: &AttemptTarget, target: &String) -> Result<(), MyError> {
+        Ok(())
+    }
+}
+
+/// locked account code of get_login_attempts().
+pub const TEST_LOCKED_CODE: &str = "test_locked_code";
+/// ip of get_login_attempts() which is locked.
+pub const TEST_LOCKED_IP: &str = "192.0.2.1";
+
+/// test data. locked account and locked ip.
+pub fn get_login_attempts() -> Vec<LoginAttempt> {
+    let now = Local::now();
+    vec![
+        LoginAttempt::from(
+            AttemptTarget::Account,
+            TEST_LOCKED_CODE.to_string(),
+            5,
+            Some(now),
+            Some(now + Duration::minutes(15)),
+        ),
+        LoginAttempt::from(
+            AttemptTarget::Ip,
+            TEST_LOCKED_IP.to_string(),
+            20,
+            Some(now),
+            Some(now + Duration::minutes(15)),
+        ),
+    ]
+}
@@ -1,3 +1,13 @@
+pub mod external_identity_repository;
+pub mod identity_provider;
+pub mod invitation_repository;
+pub mod login_attempt_repository;
 pub mod medical_examination_repository;
+pub mod notifier;
+pub mod password_history_repository;
+pub mod password_reset_repository;
 pub mod patient_repository;
+pub mod refresh_token_repository;
+pub mod service_account_repository;
+pub mod session_repository;
 pub mod user_repository;
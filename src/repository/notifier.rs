@@ -0,0 +1,88 @@
+use crate::constants::env_key;
+use crate::domain::notification::{Notification, Notifier};
+use crate::utils::datetime::DATETIME_FMT;
+use crate::utils::errors::MyError;
+
+use async_trait::async_trait;
+use log::error;
+use sqlx::MySqlPool;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+const DEFAULT_NOTIFIER_FILE: &str = "notifications.jsonl";
+
+/// where notifications go. NOTIFIER=outbox(default) or file. NOTIFIER_FILE is path of file.
+/// both are for local use instead of real email. a mail sender can read outbox table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotifierConfig {
+    Outbox,
+    File(String),
+}
+
+impl NotifierConfig {
+    pub fn load() -> Self {
+        match env::var(env_key::NOTIFIER).as_deref() {
+            Ok("file") => NotifierConfig::File(
+                env::var(env_key::NOTIFIER_FILE)
+                    .unwrap_or_else(|_| DEFAULT_NOTIFIER_FILE.to_string()),
+            ),
+            Ok("outbox") | Err(_) => NotifierConfig::Outbox,
+            Ok(notifier) => panic!("NOTIFIER={} is not supported", notifier),
+        }
+    }
+}
+
+pub struct NotifierImpl<'a> {
+    pub conn: &'a MySqlPool,
+    pub config: NotifierConfig,
+}
+
+#[async_trait]
+impl Notifier for NotifierImpl<'_> {
+    async fn notify(&self, notification: &Notification) -> Result<(), MyError> {
+        match &self.config {
+            NotifierConfig::Outbox => {
+                sqlx::query!(
+                    "insert into outbox_notifications(id,user_id,subject,body,created_at)
+                    values(?,?,?,?,?)
+                    ",
+                    notification.id,
+                    notification.user_id,
+                    notification.subject,
+                    notification.body,
+                    notification.created_at.format(DATETIME_FMT).to_string(),
+                )
+                .execute(self.conn)
+                .await?;
+            }
+            NotifierConfig::File(path) => {
+                // one json per line.
+                let line = serde_json::to_string(notification).map_err(|err| {
+                    error!("failed to serialize notification: {}", err);
+                    MyError::InternalServerError
+                })?;
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut file| writeln!(file, "{}", line))
+                    .map_err(|err| {
+                        error!("failed to write notification to file: {}", err);
+                        MyError::InternalServerError
+                    })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct NotifierMockImpl {}
+
+#[async_trait]
+impl Notifier for NotifierMockImpl {
+    /// nothing is done.
+    async fn notify(&self, notification: &Notification) -> Result<(), MyError> {
+        Ok(())
+    }
+}
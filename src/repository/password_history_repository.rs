@@ -0,0 +1,68 @@
+use crate::domain::password_policy::PasswordHistoryRepository;
+use crate::utils::errors::MyError;
+
+use async_trait::async_trait;
+use sqlx::MySqlPool;
+use ulid::Ulid;
+
+pub struct PasswordHistoryRepositoryImpl<'a> {
+    pub conn: &'a MySqlPool,
+}
+
+#[async_trait]
+impl PasswordHistoryRepository for PasswordHistoryRepositoryImpl<'_> {
+    async fn save(&self, user_id: &String, hashed_password: &String) -> Result<(), MyError> {
+        sqlx::query!(
+            "insert into password_histories(id,user_id,hashed_password)
+            values(?,?,?)
+            ",
+            Ulid::new().to_string(),
+            user_id,
+            hashed_password,
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_recent(&self, user_id: &String, limit: usize) -> Result<Vec<String>, MyError> {
+        // id is ulid, so it is ordered by creation.
+        let hashed_passwords = sqlx::query!(
+            "select hashed_password
+            from password_histories
+            where user_id=?
+            order by id desc
+            limit ?
+            ",
+            user_id,
+            limit as u64,
+        )
+        .fetch_all(self.conn)
+        .await?
+        .into_iter()
+        .map(|record| record.hashed_password)
+        .collect::<Vec<String>>();
+        Ok(hashed_passwords)
+    }
+}
+
+pub struct PasswordHistoryRepositoryMockImpl {}
+
+#[async_trait]
+impl PasswordHistoryRepository for PasswordHistoryRepositoryMockImpl {
+    /// nothing is done.
+    async fn save(&self, user_id: &String, hashed_password: &String) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return hash of TEST_OLD_PASSWORD for every user.
+    async fn fetch_recent(&self, user_id: &String, limit: usize) -> Result<Vec<String>, MyError> {
+        Ok(vec![TEST_HASHED_OLD_PASSWORD.to_string()])
+    }
+}
+
+/// raw password in history of every test user.
+pub const TEST_OLD_PASSWORD: &str = "test_secret";
+/// bcrypt hash of TEST_OLD_PASSWORD.
+const TEST_HASHED_OLD_PASSWORD: &str =
+    "$2b$04$37dCApbaxDF3IowFZ/XUgu/V8jxKxHR/Dae54oJmYN62bpnxc1XA.";
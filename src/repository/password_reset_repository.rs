@@ -0,0 +1,164 @@
+use crate::domain::password_reset::{PasswordResetToken, PasswordResetTokenRepository};
+use crate::utils::datetime::DATETIME_FMT;
+use crate::utils::errors::MyError;
+
+use async_trait::async_trait;
+use chrono::{Duration, Local, TimeZone};
+use serde_json::json;
+use sqlx::MySqlPool;
+
+pub struct PasswordResetTokenRepositoryImpl<'a> {
+    pub conn: &'a MySqlPool,
+}
+
+#[async_trait]
+impl PasswordResetTokenRepository for PasswordResetTokenRepositoryImpl<'_> {
+    async fn save(&self, password_reset_token: &PasswordResetToken) -> Result<(), MyError> {
+        sqlx::query!(
+            "insert into password_reset_tokens(id,user_id,hashed_token,expires_at,used)
+            values(?,?,?,?,?)
+            ",
+            password_reset_token.id,
+            password_reset_token.user_id,
+            password_reset_token.hashed_token,
+            password_reset_token
+                .expires_at
+                .format(DATETIME_FMT)
+                .to_string(),
+            password_reset_token.used,
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_one(&self, id: &String) -> Result<PasswordResetToken, MyError> {
+        let record = sqlx::query!(
+            "select id,user_id,hashed_token,expires_at,used
+            from password_reset_tokens
+            where id=?
+            ",
+            id
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        if let Some(record) = record {
+            let password_reset_token = PasswordResetToken::from(
+                record.id,
+                record.user_id,
+                record.hashed_token,
+                Local
+                    .datetime_from_str(&record.expires_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                record.used != 0,
+            );
+            Ok(password_reset_token)
+        } else {
+            return Err(MyError::BadRequest(
+                json!({"error":"password reset token is invalid"}),
+            ));
+        }
+    }
+
+    async fn mark_used(&self, id: &String) -> Result<bool, MyError> {
+        // conditional update, so that two resets at once can not share one token.
+        let result = sqlx::query!(
+            "update password_reset_tokens set used=true where id=? and used=false",
+            id
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn revoke_by_user_id(&self, user_id: &String) -> Result<(), MyError> {
+        sqlx::query!(
+            "update password_reset_tokens set used=true where user_id=?",
+            user_id
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+}
+
+pub struct PasswordResetTokenRepositoryMockImpl {}
+
+#[async_trait]
+impl PasswordResetTokenRepository for PasswordResetTokenRepositoryMockImpl {
+    /// nothing is done.
+    async fn save(&self, password_reset_token: &PasswordResetToken) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return one of get_password_reset_tokens() which id matches.
+    /// id is not correct then return Error
+    async fn fetch_one(&self, id: &String) -> Result<PasswordResetToken, MyError> {
+        match get_password_reset_tokens()
+            .into_iter()
+            .find(|password_reset_token| &password_reset_token.id == id)
+        {
+            Some(password_reset_token) => Ok(password_reset_token),
+            None => Err(MyError::BadRequest(
+                json!({"error":"password reset token is invalid"}),
+            )),
+        }
+    }
+
+    /// true if one of get_password_reset_tokens() which id matches is not used.
+    /// fourth one is taken as used by another reset meanwhile.
+    async fn mark_used(&self, id: &String) -> Result<bool, MyError> {
+        Ok(id != "test_password_reset_token_id_4"
+            && get_password_reset_tokens()
+                .into_iter()
+                .any(|password_reset_token| {
+                    &password_reset_token.id == id && !password_reset_token.used
+                }))
+    }
+
+    /// nothing is done.
+    async fn revoke_by_user_id(&self, user_id: &String) -> Result<(), MyError> {
+        Ok(())
+    }
+}
+
+/// raw secret of every test password reset token.
+pub const TEST_PASSWORD_RESET_TOKEN_SECRET: &str = "test_secret";
+/// bcrypt hash of TEST_PASSWORD_RESET_TOKEN_SECRET.
+const TEST_HASHED_PASSWORD_RESET_TOKEN: &str =
+    "$2b$04$37dCApbaxDF3IowFZ/XUgu/V8jxKxHR/Dae54oJmYN62bpnxc1XA.";
+
+/// test data of user "test_id". first one is usable, second one is used, third one is expired.
+/// fourth one is usable.
+pub fn get_password_reset_tokens() -> Vec<PasswordResetToken> {
+    vec![
+        PasswordResetToken::from(
+            "test_password_reset_token_id_1".to_string(),
+            "test_id".to_string(),
+            TEST_HASHED_PASSWORD_RESET_TOKEN.to_string(),
+            Local::now() + Duration::minutes(30),
+            false,
+        ),
+        PasswordResetToken::from(
+            "test_password_reset_token_id_2".to_string(),
+            "test_id".to_string(),
+            TEST_HASHED_PASSWORD_RESET_TOKEN.to_string(),
+            Local::now() + Duration::minutes(30),
+            true,
+        ),
+        PasswordResetToken::from(
+            "test_password_reset_token_id_3".to_string(),
+            "test_id".to_string(),
+            TEST_HASHED_PASSWORD_RESET_TOKEN.to_string(),
+            Local::now() - Duration::minutes(1),
+            false,
+        ),
+        PasswordResetToken::from(
+            "test_password_reset_token_id_4".to_string(),
+            "test_id".to_string(),
+            TEST_HASHED_PASSWORD_RESET_TOKEN.to_string(),
+            Local::now() + Duration::minutes(30),
+            false,
+        ),
+    ]
+}
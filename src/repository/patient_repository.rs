@@ -1,9 +1,23 @@
 use crate::{
-    domain::patient::{Patient, PatientRepository},
-    utils::errors::MyError,
+    domain::{
+        care_assignment::CareAssignment,
+        medical_examination::MedicalExamination,
+        patient::{
+            Archive, BloodType, Demographics, NameMatch, Patient, PatientQuery, PatientRepository,
+            Sex,
+        },
+    },
+    repository::user_repository::get_care_assignments,
+    utils::{
+        datetime::{DATETIME_FMT, DATE_FMT},
+        errors::MyError,
+    },
 };
 use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use serde_json::json;
 use sqlx::MySqlPool;
+use std::str::FromStr;
 
 pub struct PatientRepositoryImpl<'a> {
     pub conn: &'a MySqlPool,
@@ -12,44 +26,462 @@ pub struct PatientRepositoryImpl<'a> {
 #[async_trait]
 impl PatientRepository for PatientRepositoryImpl<'_> {
     async fn save(&self, patient: &Patient) -> Result<(), MyError> {
+        let demographics = &patient.demographics;
+        let date_of_birth = demographics
+            .date_of_birth
+            .map(|date_of_birth| date_of_birth.format(DATE_FMT).to_string());
+        let sex = demographics.sex.map(|sex| sex.to_string());
+        let blood_type = demographics
+            .blood_type
+            .map(|blood_type| blood_type.to_string());
         sqlx::query!(
-            "insert into patients(id,code,name)
-            values(?,?,?)
+            "insert into patients(id,code,name,name_kana,date_of_birth,sex,phone,address,postal_code,blood_type,search_name,search_kana)
+            values(?,?,?,?,?,?,?,?,?,?,?,?)
             ",
             patient.id,
             patient.code,
             patient.name,
+            demographics.name_kana,
+            date_of_birth,
+            sex,
+            demographics.phone,
+            demographics.address,
+            demographics.postal_code,
+            blood_type,
+            patient.search_name(),
+            patient.search_kana(),
         )
         .execute(self.conn)
         .await?;
         Ok(())
     }
 
+    async fn save_with_medical_examination(
+        &self,
+        patient: &Patient,
+        care_assignment: &CareAssignment,
+        medical_examination: &MedicalExamination,
+    ) -> Result<(), MyError> {
+        let demographics = &patient.demographics;
+        let date_of_birth = demographics
+            .date_of_birth
+            .map(|date_of_birth| date_of_birth.format(DATE_FMT).to_string());
+        let sex = demographics.sex.map(|sex| sex.to_string());
+        let blood_type = demographics
+            .blood_type
+            .map(|blood_type| blood_type.to_string());
+        let mut tx = self.conn.begin().await?;
+        sqlx::query!(
+            "insert into patients(id,code,name,name_kana,date_of_birth,sex,phone,address,postal_code,blood_type,search_name,search_kana)
+            values(?,?,?,?,?,?,?,?,?,?,?,?)
+            ",
+            patient.id,
+            patient.code,
+            patient.name,
+            demographics.name_kana,
+            date_of_birth,
+            sex,
+            demographics.phone,
+            demographics.address,
+            demographics.postal_code,
+            blood_type,
+            patient.search_name(),
+            patient.search_kana(),
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "insert into doctor_in_charges(id,user_id,patient_code,care_role,starts_at,ends_at,reason)
+            values(?,?,?,?,?,?,?)
+            ",
+            care_assignment.id,
+            care_assignment.user_id,
+            care_assignment.patient_code,
+            care_assignment.care_role.to_string(),
+            care_assignment.starts_at.format(DATETIME_FMT).to_string(),
+            care_assignment
+                .ends_at
+                .map(|ends_at| ends_at.format(DATETIME_FMT).to_string()),
+            care_assignment.reason,
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "insert into medical_examinations(id,user_id,patient_code,interviewed_at,symptom)
+            values(?,?,?,?,?)
+            ",
+            medical_examination.id,
+            care_assignment.user_id,
+            patient.code,
+            medical_examination
+                .interviewed_at
+                .unwrap()
+                .format(DATETIME_FMT)
+                .to_string(),
+            medical_examination.symptom,
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn update(&self, patient: &Patient) -> Result<bool, MyError> {
+        let demographics = &patient.demographics;
+        let date_of_birth = demographics
+            .date_of_birth
+            .map(|date_of_birth| date_of_birth.format(DATE_FMT).to_string());
+        let sex = demographics.sex.map(|sex| sex.to_string());
+        let blood_type = demographics
+            .blood_type
+            .map(|blood_type| blood_type.to_string());
+        let archive = patient.archive.as_ref();
+        let archived_at =
+            archive.map(|archive| archive.archived_at.format(DATETIME_FMT).to_string());
+        let result = sqlx::query!(
+            "update patients
+            set name=?,name_kana=?,date_of_birth=?,sex=?,phone=?,address=?,postal_code=?,blood_type=?,
+            search_name=?,search_kana=?,version=?,archived_at=?,archived_by=?,archive_reason=?
+            where code=? and version=?
+            ",
+            patient.name,
+            demographics.name_kana,
+            date_of_birth,
+            sex,
+            demographics.phone,
+            demographics.address,
+            demographics.postal_code,
+            blood_type,
+            patient.search_name(),
+            patient.search_kana(),
+            patient.version,
+            archived_at,
+            archive.map(|archive| &archive.archived_by),
+            archive.map(|archive| &archive.reason),
+            patient.code,
+            patient.version - 1,
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
     async fn fetch_one(&self, id: &String) -> Result<Patient, MyError> {
-        let record = sqlx::query!("select id, code,name from patients where id=?", id)
-            .fetch_one(self.conn)
-            .await?;
-        Ok(Patient::from(record.id, record.code, record.name))
+        let record = sqlx::query!(
+            "select id,code,name,name_kana,date_of_birth,sex,phone,address,postal_code,blood_type,version,archived_at,archived_by,archive_reason
+            from patients where id=?",
+            id
+        )
+        .fetch_one(self.conn)
+        .await?;
+        Ok(Patient::from(
+            record.id,
+            record.code,
+            record.name,
+            demographics_from(
+                record.name_kana,
+                record.date_of_birth,
+                record.sex,
+                record.phone,
+                record.address,
+                record.postal_code,
+                record.blood_type,
+            )?,
+            record.version,
+            archive_from(
+                record.archived_at,
+                record.archived_by,
+                record.archive_reason,
+            ),
+        ))
     }
 
     async fn fetch_by_code(&self, code: &String) -> Result<Patient, MyError> {
-        let record = sqlx::query!("select id, code,name from patients where code=?", code)
-            .fetch_one(self.conn)
-            .await?;
-        Ok(Patient::from(record.id, record.code, record.name))
+        let record = sqlx::query!(
+            "select id,code,name,name_kana,date_of_birth,sex,phone,address,postal_code,blood_type,version,archived_at,archived_by,archive_reason
+            from patients where code=?",
+            code
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        if let Some(record) = record {
+            Ok(Patient::from(
+                record.id,
+                record.code,
+                record.name,
+                demographics_from(
+                    record.name_kana,
+                    record.date_of_birth,
+                    record.sex,
+                    record.phone,
+                    record.address,
+                    record.postal_code,
+                    record.blood_type,
+                )?,
+                record.version,
+                archive_from(
+                    record.archived_at,
+                    record.archived_by,
+                    record.archive_reason,
+                ),
+            ))
+        } else {
+            return Err(MyError::NotFound(json!({
+                "error": format!("no record of code={}.", code)
+            })));
+        }
+    }
+
+    async fn fetch_all(&self, include_archived: bool) -> Result<Vec<Patient>, MyError> {
+        let records = sqlx::query!(
+            "select id,code,name,name_kana,date_of_birth,sex,phone,address,postal_code,blood_type,version,archived_at,archived_by,archive_reason
+            from patients
+            where ? or archived_at is null",
+            include_archived
+        )
+        .fetch_all(self.conn)
+        .await?;
+        let mut patients = vec![];
+        for record in records {
+            patients.push(Patient::from(
+                record.id,
+                record.code,
+                record.name,
+                demographics_from(
+                    record.name_kana,
+                    record.date_of_birth,
+                    record.sex,
+                    record.phone,
+                    record.address,
+                    record.postal_code,
+                    record.blood_type,
+                )?,
+                record.version,
+                archive_from(
+                    record.archived_at,
+                    record.archived_by,
+                    record.archive_reason,
+                ),
+            ));
+        }
+        Ok(patients)
+    }
+
+    async fn search(
+        &self,
+        query: &PatientQuery,
+        name_match: NameMatch,
+        limit: u32,
+    ) -> Result<Vec<Patient>, MyError> {
+        let date_of_birth = query
+            .date_of_birth
+            .map(|date_of_birth| date_of_birth.format(DATE_FMT).to_string());
+        let name = query.name.as_ref().map(|name| escape_like(name));
+        let prefix = name.as_ref().map(|name| format!("{}%", name));
+        // 'name%' can use indexes of search_name and search_kana, '%name%' can not.
+        let pattern = match name_match {
+            NameMatch::Exact | NameMatch::Prefix => prefix.clone(),
+            NameMatch::Partial => name.as_ref().map(|name| format!("%{}%", name)),
+        };
+        // exact and prefix matches first, so that they are not cut by limit.
+        let records = sqlx::query!(
+            "select id,code,name,name_kana,date_of_birth,sex,phone,address,postal_code,blood_type,version,archived_at,archived_by,archive_reason
+            from patients
+            where (? or archived_at is null)
+            and (? is null or date_of_birth=?)
+            and (? is null or search_name like ? or search_kana like ?)
+            order by
+                case when search_name=? or search_kana=? then 0
+                when search_name like ? or search_kana like ? then 1
+                else 2 end,
+                search_kana,search_name
+            limit ?",
+            query.include_archived,
+            date_of_birth,
+            date_of_birth,
+            pattern,
+            pattern,
+            pattern,
+            query.name,
+            query.name,
+            prefix,
+            prefix,
+            limit,
+        )
+        .fetch_all(self.conn)
+        .await?;
+        let mut patients = vec![];
+        for record in records {
+            patients.push(Patient::from(
+                record.id,
+                record.code,
+                record.name,
+                demographics_from(
+                    record.name_kana,
+                    record.date_of_birth,
+                    record.sex,
+                    record.phone,
+                    record.address,
+                    record.postal_code,
+                    record.blood_type,
+                )?,
+                record.version,
+                archive_from(
+                    record.archived_at,
+                    record.archived_by,
+                    record.archive_reason,
+                ),
+            ));
+        }
+        Ok(patients)
     }
 
-    async fn fetch_all(&self) -> Result<Vec<Patient>, MyError> {
+    async fn fetch_without_search_name(&self) -> Result<Vec<Patient>, MyError> {
         let records = sqlx::query!(
-            "select id,code,name
-            from patients"
+            "select id,code,name,name_kana,date_of_birth,sex,phone,address,postal_code,blood_type,version,archived_at,archived_by,archive_reason
+            from patients
+            where search_name=''"
         )
         .fetch_all(self.conn)
-        .await?
-        .into_iter()
-        .map(|record| Patient::from(record.id, record.code, record.name))
-        .collect::<Vec<Patient>>();
-        Ok(records)
+        .await?;
+        let mut patients = vec![];
+        for record in records {
+            patients.push(Patient::from(
+                record.id,
+                record.code,
+                record.name,
+                demographics_from(
+                    record.name_kana,
+                    record.date_of_birth,
+                    record.sex,
+                    record.phone,
+                    record.address,
+                    record.postal_code,
+                    record.blood_type,
+                )?,
+                record.version,
+                archive_from(
+                    record.archived_at,
+                    record.archived_by,
+                    record.archive_reason,
+                ),
+            ));
+        }
+        Ok(patients)
+    }
+
+    async fn update_search_columns(&self, patient: &Patient) -> Result<(), MyError> {
+        sqlx::query!(
+            "update patients set search_name=?,search_kana=? where id=?",
+            patient.search_name(),
+            patient.search_kana(),
+            patient.id,
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_by_user_id(
+        &self,
+        user_id: &String,
+        now: DateTime<Local>,
+    ) -> Result<Vec<Patient>, MyError> {
+        let now = now.format(DATETIME_FMT).to_string();
+        let records = sqlx::query!(
+            "select distinct patients.id,patients.code,patients.name,patients.name_kana,patients.date_of_birth,patients.sex,patients.phone,patients.address,patients.postal_code,patients.blood_type,patients.version,patients.archived_at,patients.archived_by,patients.archive_reason
+            from patients
+            inner join doctor_in_charges on doctor_in_charges.patient_code=patients.code
+            where doctor_in_charges.user_id=? and doctor_in_charges.starts_at<=?
+            and (doctor_in_charges.ends_at is null or doctor_in_charges.ends_at>?)",
+            user_id,
+            now,
+            now,
+        )
+        .fetch_all(self.conn)
+        .await?;
+        let mut patients = vec![];
+        for record in records {
+            patients.push(Patient::from(
+                record.id,
+                record.code,
+                record.name,
+                demographics_from(
+                    record.name_kana,
+                    record.date_of_birth,
+                    record.sex,
+                    record.phone,
+                    record.address,
+                    record.postal_code,
+                    record.blood_type,
+                )?,
+                record.version,
+                archive_from(
+                    record.archived_at,
+                    record.archived_by,
+                    record.archive_reason,
+                ),
+            ));
+        }
+        Ok(patients)
+    }
+}
+
+/// escape wildcards of LIKE in value.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// columns of patients to Demographics. they have been validated on save.
+fn demographics_from(
+    name_kana: Option<String>,
+    date_of_birth: Option<impl ToString>,
+    sex: Option<String>,
+    phone: Option<String>,
+    address: Option<String>,
+    postal_code: Option<String>,
+    blood_type: Option<String>,
+) -> Result<Demographics, MyError> {
+    let date_of_birth = date_of_birth.map(|date_of_birth| {
+        NaiveDate::parse_from_str(&date_of_birth.to_string(), DATE_FMT).unwrap()
+    });
+    let sex = match sex {
+        Some(sex) => Some(Sex::from_str(&sex)?),
+        None => None,
+    };
+    let blood_type = match blood_type {
+        Some(blood_type) => Some(BloodType::from_str(&blood_type)?),
+        None => None,
+    };
+    Ok(Demographics {
+        name_kana,
+        date_of_birth,
+        sex,
+        phone,
+        address,
+        postal_code,
+        blood_type,
+    })
+}
+
+/// columns of patients to Archive. None unless archived.
+fn archive_from(
+    archived_at: Option<impl ToString>,
+    archived_by: Option<String>,
+    archive_reason: Option<String>,
+) -> Option<Archive> {
+    match (archived_at, archived_by) {
+        (Some(archived_at), Some(archived_by)) => Some(Archive {
+            archived_at: Local
+                .datetime_from_str(&archived_at.to_string(), DATETIME_FMT)
+                .unwrap(),
+            archived_by,
+            reason: archive_reason.unwrap_or_default(),
+        }),
+        _ => None,
     }
 }
 
@@ -62,20 +494,93 @@ impl PatientRepository for PatientRepositoryMockImpl {
         Ok(())
     }
 
+    /// return Ok
+    async fn save_with_medical_examination(
+        &self,
+        patient: &Patient,
+        care_assignment: &CareAssignment,
+        medical_examination: &MedicalExamination,
+    ) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return true if one of get_patients() has the code and the version before update.
+    async fn update(&self, patient: &Patient) -> Result<bool, MyError> {
+        Ok(get_patients()
+            .into_iter()
+            .any(|current| current.code == patient.code && current.version + 1 == patient.version))
+    }
+
     /// return Ok
     async fn fetch_one(&self, id: &String) -> Result<Patient, MyError> {
         Ok(get_patients()[0].clone())
     }
 
-    /// return Ok
+    /// return one of get_patients() which code matches.
+    /// code is not correct then return NotFound
     async fn fetch_by_code(&self, code: &String) -> Result<Patient, MyError> {
-        Ok(get_patients()[0].clone())
+        match get_patients()
+            .into_iter()
+            .find(|patient| &patient.code == code)
+        {
+            Some(patient) => Ok(patient),
+            None => Err(MyError::NotFound(json!({
+                "error": format!("no record of code={}.", code)
+            }))),
+        }
     }
 
     /// return Ok
-    async fn fetch_all(&self) -> Result<Vec<Patient>, MyError> {
+    async fn fetch_all(&self, include_archived: bool) -> Result<Vec<Patient>, MyError> {
         Ok(get_patients())
     }
+
+    /// return get_patients() which match the query no worse than name_match, at most limit.
+    async fn search(
+        &self,
+        query: &PatientQuery,
+        name_match: NameMatch,
+        limit: u32,
+    ) -> Result<Vec<Patient>, MyError> {
+        Ok(get_patients()
+            .into_iter()
+            .filter(|patient| {
+                query
+                    .matches(patient)
+                    .map_or(false, |matched| matched <= name_match)
+            })
+            .take(limit as usize)
+            .collect())
+    }
+
+    /// return get_patients(), as if none of them has search_name.
+    async fn fetch_without_search_name(&self) -> Result<Vec<Patient>, MyError> {
+        Ok(get_patients())
+    }
+
+    /// return Ok
+    async fn update_search_columns(&self, patient: &Patient) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return get_patients() assigned by get_care_assignments() which are active at now.
+    async fn fetch_by_user_id(
+        &self,
+        user_id: &String,
+        now: DateTime<Local>,
+    ) -> Result<Vec<Patient>, MyError> {
+        let patient_codes = get_care_assignments()
+            .into_iter()
+            .filter(|care_assignment| {
+                &care_assignment.user_id == user_id && care_assignment.is_active(now)
+            })
+            .map(|care_assignment| care_assignment.patient_code)
+            .collect::<Vec<String>>();
+        Ok(get_patients()
+            .into_iter()
+            .filter(|patient| patient_codes.contains(&patient.code))
+            .collect())
+    }
 }
 
 pub fn get_patients() -> Vec<Patient> {
@@ -84,11 +589,42 @@ pub fn get_patients() -> Vec<Patient> {
             "1".to_string(),
             "a".to_string(),
             "test_patient_name_1".to_string(),
+            Demographics {
+                name_kana: Some("テスト カンジャ イチ".to_string()),
+                date_of_birth: NaiveDate::from_ymd_opt(1980, 1, 15),
+                sex: Some(Sex::Male),
+                phone: Some("0312345678".to_string()),
+                address: Some("東京都千代田区千代田1-1".to_string()),
+                postal_code: Some("100-0001".to_string()),
+                blood_type: Some(BloodType::APositive),
+            },
+            1,
+            None,
         ),
         Patient::from(
             "2".to_string(),
             "b".to_string(),
             "test_patient_name_2".to_string(),
+            Demographics {
+                name_kana: Some("テスト カンジャ ニ".to_string()),
+                ..Default::default()
+            },
+            3,
+            None,
+        ),
+        Patient::from(
+            "3".to_string(),
+            "c".to_string(),
+            "test_patient_name_3".to_string(),
+            Demographics::default(),
+            2,
+            Some(Archive {
+                archived_at: Local
+                    .datetime_from_str("2022-12-12 12:12:12", DATETIME_FMT)
+                    .unwrap(),
+                archived_by: "test_id".to_string(),
+                reason: "duplicated".to_string(),
+            }),
         ),
     ]
 }
@@ -0,0 +1,179 @@
+use crate::domain::refresh_token::{RefreshToken, RefreshTokenRepository};
+use crate::utils::datetime::DATETIME_FMT;
+use crate::utils::errors::MyError;
+use crate::utils::hash::hash_token;
+
+use async_trait::async_trait;
+use chrono::{Duration, Local, TimeZone};
+use serde_json::json;
+use sqlx::MySqlPool;
+
+pub struct RefreshTokenRepositoryImpl<'a> {
+    pub conn: &'a MySqlPool,
+}
+
+#[async_trait]
+impl RefreshTokenRepository for RefreshTokenRepositoryImpl<'_> {
+    async fn save(&self, refresh_token: &RefreshToken) -> Result<(), MyError> {
+        sqlx::query!(
+            "insert into refresh_tokens(id,user_id,family_id,hashed_token,expires_at,revoked)
+            values(?,?,?,?,?,?)
+            ",
+            refresh_token.id,
+            refresh_token.user_id,
+            refresh_token.family_id,
+            refresh_token.hashed_token,
+            refresh_token.expires_at.format(DATETIME_FMT).to_string(),
+            refresh_token.revoked,
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_one(&self, id: &String) -> Result<RefreshToken, MyError> {
+        let record = sqlx::query!(
+            "select id,user_id,family_id,hashed_token,expires_at,revoked
+            from refresh_tokens
+            where id=?
+            ",
+            id
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        if let Some(record) = record {
+            let refresh_token = RefreshToken::from(
+                record.id,
+                record.user_id,
+                record.family_id,
+                record.hashed_token,
+                Local
+                    .datetime_from_str(&record.expires_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                record.revoked != 0,
+            );
+            Ok(refresh_token)
+        } else {
+            return Err(MyError::Unauthorized(
+                json!({"error":"refresh token is invalid"}),
+            ));
+        }
+    }
+
+    async fn revoke(&self, id: &String) -> Result<bool, MyError> {
+        // conditional update, so that two refreshes at once can not share one token.
+        let result = sqlx::query!(
+            "update refresh_tokens set revoked=true where id=? and revoked=false",
+            id
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn revoke_family(&self, family_id: &String) -> Result<(), MyError> {
+        sqlx::query!(
+            "update refresh_tokens set revoked=true where family_id=?",
+            family_id
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn revoke_by_user_id(&self, user_id: &String) -> Result<(), MyError> {
+        sqlx::query!(
+            "update refresh_tokens set revoked=true where user_id=?",
+            user_id
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+}
+
+pub struct RefreshTokenRepositoryMockImpl {}
+
+#[async_trait]
+impl RefreshTokenRepository for RefreshTokenRepositoryMockImpl {
+    /// nothing is done.
+    async fn save(&self, refresh_token: &RefreshToken) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return one of get_refresh_tokens() which id matches.
+    /// id is not correct then return Error
+    async fn fetch_one(&self, id: &String) -> Result<RefreshToken, MyError> {
+        match get_refresh_tokens()
+            .into_iter()
+            .find(|refresh_token| &refresh_token.id == id)
+        {
+            Some(refresh_token) => Ok(refresh_token),
+            None => Err(MyError::Unauthorized(
+                json!({"error":"refresh token is invalid"}),
+            )),
+        }
+    }
+
+    /// true if one of get_refresh_tokens() which id matches is not revoked.
+    /// fourth one is taken as revoked by another refresh meanwhile.
+    async fn revoke(&self, id: &String) -> Result<bool, MyError> {
+        Ok(id != "test_refresh_token_id_4"
+            && get_refresh_tokens()
+                .into_iter()
+                .any(|refresh_token| &refresh_token.id == id && !refresh_token.revoked))
+    }
+
+    /// nothing is done.
+    async fn revoke_family(&self, family_id: &String) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// nothing is done.
+    async fn revoke_by_user_id(&self, user_id: &String) -> Result<(), MyError> {
+        Ok(())
+    }
+}
+
+/// raw secret of every test refresh token.
+pub const TEST_REFRESH_TOKEN_SECRET: &str = "test_secret";
+
+/// test data. first one is active, second one is already rotated(revoked).
+/// third one is active but its session is revoked. fourth one is active.
+pub fn get_refresh_tokens() -> Vec<RefreshToken> {
+    let hashed_token = hash_token(TEST_REFRESH_TOKEN_SECRET).unwrap();
+    vec![
+        RefreshToken::from(
+            "test_refresh_token_id_1".to_string(),
+            "test_id".to_string(),
+            "test_family_id".to_string(),
+            hashed_token.clone(),
+            Local::now() + Duration::days(1),
+            false,
+        ),
+        RefreshToken::from(
+            "test_refresh_token_id_2".to_string(),
+            "test_id".to_string(),
+            "test_family_id".to_string(),
+            hashed_token.clone(),
+            Local::now() + Duration::days(1),
+            true,
+        ),
+        RefreshToken::from(
+            "test_refresh_token_id_3".to_string(),
+            "test_id".to_string(),
+            "test_revoked_session_id".to_string(),
+            hashed_token.clone(),
+            Local::now() + Duration::days(1),
+            false,
+        ),
+        RefreshToken::from(
+            "test_refresh_token_id_4".to_string(),
+            "test_id".to_string(),
+            "test_family_id".to_string(),
+            hashed_token,
+            Local::now() + Duration::days(1),
+            false,
+        ),
+    ]
+}
@@ -0,0 +1,328 @@
+use crate::domain::service_account::{
+    ApiKey, ApiKeyRepository, ServiceAccount, ServiceAccountRepository,
+};
+use crate::domain::user::{Permission, Role};
+use crate::utils::datetime::DATETIME_FMT;
+use crate::utils::errors::MyError;
+use crate::utils::hash::digest_token;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Local, TimeZone};
+use serde_json::json;
+use sqlx::MySqlPool;
+use std::str::FromStr;
+
+const SCOPE_SEPARATOR: &str = ",";
+
+pub struct ServiceAccountRepositoryImpl<'a> {
+    pub conn: &'a MySqlPool,
+}
+
+#[async_trait]
+impl ServiceAccountRepository for ServiceAccountRepositoryImpl<'_> {
+    async fn save(&self, service_account: &ServiceAccount) -> Result<(), MyError> {
+        sqlx::query!(
+            "insert into service_accounts(id,name,role,created_by)
+            values(?,?,?,?)
+            ",
+            service_account.id,
+            service_account.name,
+            service_account.role.to_string(),
+            service_account.created_by,
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_one(&self, id: &String) -> Result<ServiceAccount, MyError> {
+        let record = sqlx::query!(
+            "select id,name,role,created_by
+            from service_accounts
+            where id=?
+            ",
+            id
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        if let Some(record) = record {
+            Ok(ServiceAccount::from(
+                record.id,
+                record.name,
+                Role::from_str(&record.role)?,
+                record.created_by,
+            ))
+        } else {
+            return Err(MyError::NotFound(json!({
+                "error": format!("no service account of id={}.", id)
+            })));
+        }
+    }
+
+    async fn fetch_all(&self) -> Result<Vec<ServiceAccount>, MyError> {
+        let records = sqlx::query!(
+            "select id,name,role,created_by
+            from service_accounts
+            order by created_at
+            "
+        )
+        .fetch_all(self.conn)
+        .await?;
+        let mut service_accounts = vec![];
+        for record in records {
+            service_accounts.push(ServiceAccount::from(
+                record.id,
+                record.name,
+                Role::from_str(&record.role)?,
+                record.created_by,
+            ));
+        }
+        Ok(service_accounts)
+    }
+}
+
+pub struct ApiKeyRepositoryImpl<'a> {
+    pub conn: &'a MySqlPool,
+}
+
+#[async_trait]
+impl ApiKeyRepository for ApiKeyRepositoryImpl<'_> {
+    async fn save(&self, api_key: &ApiKey) -> Result<(), MyError> {
+        let expires_at = api_key
+            .expires_at
+            .map(|expires_at| expires_at.format(DATETIME_FMT).to_string());
+        sqlx::query!(
+            "insert into api_keys(id,service_account_id,hashed_key,scopes,expires_at,revoked)
+            values(?,?,?,?,?,?)
+            ",
+            api_key.id,
+            api_key.service_account_id,
+            api_key.hashed_key,
+            join_scopes(&api_key.scopes),
+            expires_at,
+            api_key.revoked,
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_one(&self, id: &String) -> Result<Option<ApiKey>, MyError> {
+        let record = sqlx::query!(
+            "select id,service_account_id,hashed_key,scopes,expires_at,last_used_at,revoked
+            from api_keys
+            where id=?
+            ",
+            id
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        match record {
+            Some(record) => Ok(Some(ApiKey::from(
+                record.id,
+                record.service_account_id,
+                record.hashed_key,
+                split_scopes(&record.scopes)?,
+                record.expires_at.map(|expires_at| {
+                    Local
+                        .datetime_from_str(&expires_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+                record.last_used_at.map(|last_used_at| {
+                    Local
+                        .datetime_from_str(&last_used_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+                record.revoked != 0,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    async fn fetch_by_service_account_id(
+        &self,
+        service_account_id: &String,
+    ) -> Result<Vec<ApiKey>, MyError> {
+        let records = sqlx::query!(
+            "select id,service_account_id,hashed_key,scopes,expires_at,last_used_at,revoked
+            from api_keys
+            where service_account_id=?
+            order by created_at
+            ",
+            service_account_id
+        )
+        .fetch_all(self.conn)
+        .await?;
+        let mut api_keys = vec![];
+        for record in records {
+            api_keys.push(ApiKey::from(
+                record.id,
+                record.service_account_id,
+                record.hashed_key,
+                split_scopes(&record.scopes)?,
+                record.expires_at.map(|expires_at| {
+                    Local
+                        .datetime_from_str(&expires_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+                record.last_used_at.map(|last_used_at| {
+                    Local
+                        .datetime_from_str(&last_used_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+                record.revoked != 0,
+            ));
+        }
+        Ok(api_keys)
+    }
+
+    async fn update_last_used_at(&self, id: &String, now: DateTime<Local>) -> Result<(), MyError> {
+        sqlx::query!(
+            "update api_keys set last_used_at=? where id=?",
+            now.format(DATETIME_FMT).to_string(),
+            id
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn revoke(&self, id: &String) -> Result<(), MyError> {
+        sqlx::query!("update api_keys set revoked=true where id=?", id)
+            .execute(self.conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// scopes are stored as comma separated permissions.
+fn join_scopes(scopes: &[Permission]) -> String {
+    scopes
+        .iter()
+        .map(|scope| scope.to_string())
+        .collect::<Vec<String>>()
+        .join(SCOPE_SEPARATOR)
+}
+
+fn split_scopes(scopes: &str) -> Result<Vec<Permission>, MyError> {
+    let mut permissions = vec![];
+    for scope in scopes
+        .split(SCOPE_SEPARATOR)
+        .filter(|scope| !scope.is_empty())
+    {
+        permissions.push(Permission::from_str(scope)?);
+    }
+    Ok(permissions)
+}
+
+pub struct ServiceAccountRepositoryMockImpl {}
+
+#[async_trait]
+impl ServiceAccountRepository for ServiceAccountRepositoryMockImpl {
+    /// nothing is done.
+    async fn save(&self, service_account: &ServiceAccount) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return one of get_service_accounts() which id matches.
+    /// id is not correct then return Error
+    async fn fetch_one(&self, id: &String) -> Result<ServiceAccount, MyError> {
+        match get_service_accounts()
+            .into_iter()
+            .find(|service_account| &service_account.id == id)
+        {
+            Some(service_account) => Ok(service_account),
+            None => Err(MyError::NotFound(json!({
+                "error": format!("no service account of id={}.", id)
+            }))),
+        }
+    }
+
+    /// return get_service_accounts().
+    async fn fetch_all(&self) -> Result<Vec<ServiceAccount>, MyError> {
+        Ok(get_service_accounts())
+    }
+}
+
+/// test data. nurse service account.
+pub fn get_service_accounts() -> Vec<ServiceAccount> {
+    vec![ServiceAccount::from(
+        "test_service_account_id".to_string(),
+        "test_lab_interface".to_string(),
+        Role::Nurse,
+        "test_id".to_string(),
+    )]
+}
+
+pub struct ApiKeyRepositoryMockImpl {}
+
+#[async_trait]
+impl ApiKeyRepository for ApiKeyRepositoryMockImpl {
+    /// nothing is done.
+    async fn save(&self, api_key: &ApiKey) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return one of get_api_keys() which id matches.
+    async fn fetch_one(&self, id: &String) -> Result<Option<ApiKey>, MyError> {
+        Ok(get_api_keys().into_iter().find(|api_key| &api_key.id == id))
+    }
+
+    /// return get_api_keys() of the service account.
+    async fn fetch_by_service_account_id(
+        &self,
+        service_account_id: &String,
+    ) -> Result<Vec<ApiKey>, MyError> {
+        Ok(get_api_keys()
+            .into_iter()
+            .filter(|api_key| &api_key.service_account_id == service_account_id)
+            .collect())
+    }
+
+    /// nothing is done.
+    async fn update_last_used_at(&self, id: &String, now: DateTime<Local>) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// nothing is done.
+    async fn revoke(&self, id: &String) -> Result<(), MyError> {
+        Ok(())
+    }
+}
+
+/// raw secret of every test api key.
+pub const TEST_API_KEY_SECRET: &str = "test_secret";
+
+/// test data. _1 is usable, _2 is revoked and _3 has expired.
+pub fn get_api_keys() -> Vec<ApiKey> {
+    let scopes = vec![Permission::ReadPatient, Permission::ReadMedicalExamination];
+    vec![
+        ApiKey::from(
+            "test_api_key_id_1".to_string(),
+            "test_service_account_id".to_string(),
+            digest_token(TEST_API_KEY_SECRET),
+            scopes.clone(),
+            None,
+            None,
+            false,
+        ),
+        ApiKey::from(
+            "test_api_key_id_2".to_string(),
+            "test_service_account_id".to_string(),
+            digest_token(TEST_API_KEY_SECRET),
+            scopes.clone(),
+            None,
+            None,
+            true,
+        ),
+        ApiKey::from(
+            "test_api_key_id_3".to_string(),
+            "test_service_account_id".to_string(),
+            digest_token(TEST_API_KEY_SECRET),
+            scopes,
+            Some(Local::now() - Duration::days(1)),
+            None,
+            false,
+        ),
+    ]
+}
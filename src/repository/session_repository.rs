@@ -0,0 +1,206 @@
+use crate::domain::session::{truncate_user_agent, ClientInfo, Session, SessionRepository};
+use crate::utils::datetime::DATETIME_FMT;
+use crate::utils::errors::MyError;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Local, TimeZone};
+use sqlx::MySqlPool;
+
+pub struct SessionRepositoryImpl<'a> {
+    pub conn: &'a MySqlPool,
+}
+
+#[async_trait]
+impl SessionRepository for SessionRepositoryImpl<'_> {
+    async fn save(&self, session: &Session) -> Result<(), MyError> {
+        sqlx::query!(
+            "insert into sessions(id,user_id,ip,user_agent,created_at,last_seen_at)
+            values(?,?,?,?,?,?)
+            ",
+            session.id,
+            session.user_id,
+            session.ip,
+            session.user_agent,
+            session.created_at.format(DATETIME_FMT).to_string(),
+            session.last_seen_at.format(DATETIME_FMT).to_string(),
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_one(&self, id: &String) -> Result<Option<Session>, MyError> {
+        let record = sqlx::query!(
+            "select id,user_id,ip,user_agent,created_at,last_seen_at,revoked_at
+            from sessions
+            where id=?
+            ",
+            id
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        Ok(record.map(|record| {
+            Session::from(
+                record.id,
+                record.user_id,
+                record.ip,
+                record.user_agent,
+                Local
+                    .datetime_from_str(&record.created_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                Local
+                    .datetime_from_str(&record.last_seen_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                record.revoked_at.map(|revoked_at| {
+                    Local
+                        .datetime_from_str(&revoked_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+            )
+        }))
+    }
+
+    async fn fetch_not_revoked_by_user_id(
+        &self,
+        user_id: &String,
+    ) -> Result<Vec<Session>, MyError> {
+        let sessions = sqlx::query!(
+            "select id,user_id,ip,user_agent,created_at,last_seen_at,revoked_at
+            from sessions
+            where user_id=? and revoked_at is null
+            order by last_seen_at desc
+            ",
+            user_id
+        )
+        .fetch_all(self.conn)
+        .await?
+        .into_iter()
+        .map(|record| {
+            Session::from(
+                record.id,
+                record.user_id,
+                record.ip,
+                record.user_agent,
+                Local
+                    .datetime_from_str(&record.created_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                Local
+                    .datetime_from_str(&record.last_seen_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                record.revoked_at.map(|revoked_at| {
+                    Local
+                        .datetime_from_str(&revoked_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+            )
+        })
+        .collect::<Vec<Session>>();
+        Ok(sessions)
+    }
+
+    async fn touch(
+        &self,
+        id: &String,
+        client: &ClientInfo,
+        now: DateTime<Local>,
+    ) -> Result<(), MyError> {
+        sqlx::query!(
+            "update sessions set ip=?,user_agent=?,last_seen_at=? where id=?",
+            client.ip,
+            truncate_user_agent(client.user_agent.clone()),
+            now.format(DATETIME_FMT).to_string(),
+            id
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn revoke(&self, id: &String, now: DateTime<Local>) -> Result<(), MyError> {
+        sqlx::query!(
+            "update sessions set revoked_at=? where id=? and revoked_at is null",
+            now.format(DATETIME_FMT).to_string(),
+            id
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+}
+
+pub struct SessionRepositoryMockImpl {}
+
+#[async_trait]
+impl SessionRepository for SessionRepositoryMockImpl {
+    /// nothing is done.
+    async fn save(&self, session: &Session) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return one of get_sessions() which id matches.
+    async fn fetch_one(&self, id: &String) -> Result<Option<Session>, MyError> {
+        Ok(get_sessions().into_iter().find(|session| &session.id == id))
+    }
+
+    /// return not revoked get_sessions() of the user.
+    async fn fetch_not_revoked_by_user_id(
+        &self,
+        user_id: &String,
+    ) -> Result<Vec<Session>, MyError> {
+        Ok(get_sessions()
+            .into_iter()
+            .filter(|session| &session.user_id == user_id && session.revoked_at.is_none())
+            .collect())
+    }
+
+    /// nothing is done.
+    async fn touch(
+        &self,
+        id: &String,
+        client: &ClientInfo,
+        now: DateTime<Local>,
+    ) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// nothing is done.
+    async fn revoke(&self, id: &String, now: DateTime<Local>) -> Result<(), MyError> {
+        Ok(())
+    }
+}
+
+/// test data. sessions of "test_id".
+/// "test_family_id" is the session of get_refresh_tokens(), "test_idle_session_id" has not been
+/// refreshed for long and "test_revoked_session_id" is revoked.
+pub fn get_sessions() -> Vec<Session> {
+    let now = Local::now();
+    vec![
+        Session::from(
+            "test_family_id".to_string(),
+            "test_id".to_string(),
+            Some("192.0.2.2".to_string()),
+            Some("test_user_agent".to_string()),
+            now - Duration::days(1),
+            now,
+            None,
+        ),
+        Session::from(
+            "test_idle_session_id".to_string(),
+            "test_id".to_string(),
+            None,
+            None,
+            now - Duration::days(30),
+            now - Duration::days(30),
+            None,
+        ),
+        Session::from(
+            "test_revoked_session_id".to_string(),
+            "test_id".to_string(),
+            None,
+            None,
+            now - Duration::days(1),
+            now - Duration::days(1),
+            Some(now),
+        ),
+    ]
+}
@@ -1,12 +1,23 @@
 use serde_json::json;
+use std::str::FromStr;
 
 use crate::{
-    domain::user::{DoctorInChargeRepository, User, UserRepository},
-    utils::errors::MyError,
+    domain::{
+        care_assignment::{already_in_charge, CareAssignment, CareRole},
+        delegation::Delegation,
+        emergency_access::EmergencyAccess,
+        user::{
+            DoctorInChargeRepository, RecoveryCode, Role, TwoFactor, TwoFactorRepository, User,
+            UserRepository,
+        },
+    },
+    repository::invitation_repository::get_invitations,
+    utils::{datetime::DATETIME_FMT, errors::MyError},
 };
 use async_trait::async_trait;
+use chrono::{DateTime, Duration, Local, TimeZone};
 
-use sqlx::MySqlPool;
+use sqlx::{MySql, MySqlPool, Transaction};
 
 pub struct UserRepositoryImpl<'a> {
     pub conn: &'a MySqlPool,
@@ -16,22 +27,80 @@ pub struct UserRepositoryImpl<'a> {
 impl UserRepository for UserRepositoryImpl<'_> {
     async fn save(&self, user: &User) -> Result<(), MyError> {
         sqlx::query!(
-            "insert into users(id,code,name,password)
-            values(?,?,?,?)
+            "insert into users(id,code,name,password,role,approved)
+            values(?,?,?,?,?,?)
             ",
             user.id,
             user.code,
             user.name,
-            user.hashed_password
+            user.hashed_password,
+            user.role.to_string(),
+            user.approved,
         )
         .execute(self.conn)
         .await?;
         Ok(())
     }
 
+    async fn save_first(&self, user: &User) -> Result<bool, MyError> {
+        // conditional insert. first sign ups at once conflict on locks taken by the select.
+        let result = sqlx::query!(
+            "insert into users(id,code,name,password,role,approved)
+            select ?,?,?,?,?,? from dual
+            where not exists (select 1 from users)
+            ",
+            user.id,
+            user.code,
+            user.name,
+            user.hashed_password,
+            user.role.to_string(),
+            user.approved,
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn save_with_invitation(
+        &self,
+        user: &User,
+        invitation_id: &String,
+    ) -> Result<bool, MyError> {
+        // conditional update, so that two sign ups at once can not share one invitation.
+        let mut tx = self.conn.begin().await?;
+        let result = sqlx::query!(
+            "update invitations set used_by=?,used_at=current_timestamp
+            where id=? and used_by is null and revoked=false
+            ",
+            user.id,
+            invitation_id
+        )
+        .execute(&mut tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+        sqlx::query!(
+            "insert into users(id,code,name,password,role,approved)
+            values(?,?,?,?,?,?)
+            ",
+            user.id,
+            user.code,
+            user.name,
+            user.hashed_password,
+            user.role.to_string(),
+            user.approved,
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
     async fn fetch_one(&self, id: &String) -> Result<User, MyError> {
         let record = sqlx::query!(
-            "select id, code,name, password
+            "select id, code,name, password, role, active, approved
             from users 
             where users.id=? 
             ",
@@ -40,7 +109,15 @@ impl UserRepository for UserRepositoryImpl<'_> {
         .fetch_optional(self.conn)
         .await?;
         if let Some(record) = record {
-            let user = User::from(record.id, record.code, record.name, record.password)?;
+            let user = User::from(
+                record.id,
+                record.code,
+                record.name,
+                record.password,
+                Role::from_str(&record.role)?,
+                record.active != 0,
+                record.approved != 0,
+            )?;
             Ok(user)
         } else {
             return Err(MyError::BadRequest(json!({
@@ -51,15 +128,103 @@ impl UserRepository for UserRepositoryImpl<'_> {
 
     async fn find_by_code(&self, code: &String) -> Result<User, MyError> {
         let record = sqlx::query!(
-            "select id, code, name, password
+            "select id, code, name, password, role, active, approved
             from users
             where users.code=?",
             code
         )
-        .fetch_one(self.conn)
+        .fetch_optional(self.conn)
+        .await?;
+        if let Some(record) = record {
+            let user = User::from(
+                record.id,
+                record.code,
+                record.name,
+                record.password,
+                Role::from_str(&record.role)?,
+                record.active != 0,
+                record.approved != 0,
+            )?;
+            Ok(user)
+        } else {
+            return Err(MyError::BadRequest(json!({
+                "error": format!("no record of code={}.", code)
+            })));
+        }
+    }
+
+    async fn update_role(&self, code: &String, role: &Role) -> Result<(), MyError> {
+        sqlx::query!(
+            "update users set role=? where code=?",
+            role.to_string(),
+            code
+        )
+        .execute(self.conn)
         .await?;
-        let user = User::from(record.id, record.code, record.name, record.password)?;
-        Ok(user)
+        Ok(())
+    }
+
+    async fn update_password(&self, id: &String, hashed_password: &String) -> Result<(), MyError> {
+        sqlx::query!(
+            "update users set password=? where id=?",
+            hashed_password,
+            id
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn update_name(&self, id: &String, name: &String) -> Result<(), MyError> {
+        sqlx::query!("update users set name=? where id=?", name, id)
+            .execute(self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_active(&self, code: &String, active: bool) -> Result<(), MyError> {
+        sqlx::query!("update users set active=? where code=?", active, code)
+            .execute(self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_approved(&self, code: &String, approved: bool) -> Result<(), MyError> {
+        sqlx::query!("update users set approved=? where code=?", approved, code)
+            .execute(self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn fetch_all(&self) -> Result<Vec<User>, MyError> {
+        let records = sqlx::query!(
+            "select id, code, name, password, role, active, approved
+            from users
+            order by code
+            "
+        )
+        .fetch_all(self.conn)
+        .await?;
+        let mut users = vec![];
+        for record in records {
+            users.push(User::from(
+                record.id,
+                record.code,
+                record.name,
+                record.password,
+                Role::from_str(&record.role)?,
+                record.active != 0,
+                record.approved != 0,
+            )?);
+        }
+        Ok(users)
+    }
+
+    async fn count(&self) -> Result<i64, MyError> {
+        let record = sqlx::query!("select count(*) as count from users")
+            .fetch_one(self.conn)
+            .await?;
+        Ok(record.count)
     }
 }
 
@@ -69,13 +234,461 @@ pub struct DoctorInChargeRepositoryImpl<'a> {
 
 #[async_trait]
 impl DoctorInChargeRepository for DoctorInChargeRepositoryImpl<'_> {
-    async fn save(&self, user_id: &String, patient_code: &String) -> Result<(), MyError> {
+    async fn save(&self, care_assignment: &CareAssignment) -> Result<(), MyError> {
+        let mut tx = self.conn.begin().await?;
+        if lock_and_find_overlapping(&mut tx, care_assignment).await? {
+            tx.rollback().await?;
+            return Err(already_in_charge(&care_assignment.patient_code));
+        }
+        sqlx::query!(
+            "insert into doctor_in_charges(id,user_id,patient_code,care_role,starts_at,ends_at,reason)
+            values(?,?,?,?,?,?,?)
+            ",
+            care_assignment.id,
+            care_assignment.user_id,
+            care_assignment.patient_code,
+            care_assignment.care_role.to_string(),
+            care_assignment.starts_at.format(DATETIME_FMT).to_string(),
+            care_assignment
+                .ends_at
+                .map(|ends_at| ends_at.format(DATETIME_FMT).to_string()),
+            care_assignment.reason,
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn exists(
+        &self,
+        user_id: &String,
+        patient_code: &String,
+        now: DateTime<Local>,
+    ) -> Result<bool, MyError> {
+        let now = now.format(DATETIME_FMT).to_string();
+        let record = sqlx::query!(
+            "select id from doctor_in_charges
+            where user_id=? and patient_code=? and starts_at<=? and (ends_at is null or ends_at>?)
+            limit 1
+            ",
+            user_id,
+            patient_code,
+            now,
+            now,
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        Ok(record.is_some())
+    }
+
+    async fn fetch_by_patient_code(
+        &self,
+        patient_code: &String,
+    ) -> Result<Vec<CareAssignment>, MyError> {
+        let records = sqlx::query!(
+            "select id,user_id,patient_code,care_role,starts_at,ends_at,reason
+            from doctor_in_charges
+            where patient_code=?
+            order by starts_at desc
+            ",
+            patient_code
+        )
+        .fetch_all(self.conn)
+        .await?;
+        let mut care_assignments = vec![];
+        for record in records {
+            care_assignments.push(CareAssignment::from(
+                record.id,
+                record.user_id,
+                record.patient_code,
+                CareRole::from_str(&record.care_role)?,
+                Local
+                    .datetime_from_str(&record.starts_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                record.ends_at.map(|ends_at| {
+                    Local
+                        .datetime_from_str(&ends_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+                record.reason,
+            ));
+        }
+        Ok(care_assignments)
+    }
+
+    async fn end(
+        &self,
+        user_id: &String,
+        patient_code: &String,
+        now: DateTime<Local>,
+    ) -> Result<bool, MyError> {
+        let now = now.format(DATETIME_FMT).to_string();
+        let result = sqlx::query!(
+            "update doctor_in_charges set ends_at=?
+            where user_id=? and patient_code=? and starts_at<=? and (ends_at is null or ends_at>?)
+            ",
+            now,
+            user_id,
+            patient_code,
+            now,
+            now,
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn transfer(
+        &self,
+        from_user_id: &String,
+        care_assignment: &CareAssignment,
+    ) -> Result<bool, MyError> {
+        // patient is never left without the doctor in charge, nor with both of them.
+        let starts_at = care_assignment.starts_at.format(DATETIME_FMT).to_string();
+        let mut tx = self.conn.begin().await?;
+        if lock_and_find_overlapping(&mut tx, care_assignment).await? {
+            tx.rollback().await?;
+            return Err(already_in_charge(&care_assignment.patient_code));
+        }
+        let result = sqlx::query!(
+            "update doctor_in_charges set ends_at=?
+            where user_id=? and patient_code=? and starts_at<=? and (ends_at is null or ends_at>?)
+            ",
+            starts_at,
+            from_user_id,
+            care_assignment.patient_code,
+            starts_at,
+            starts_at,
+        )
+        .execute(&mut tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
         sqlx::query!(
-            "insert into doctor_in_charges(user_id,patient_code)
-            values(?,?)
+            "insert into doctor_in_charges(id,user_id,patient_code,care_role,starts_at,ends_at,reason)
+            values(?,?,?,?,?,?,?)
+            ",
+            care_assignment.id,
+            care_assignment.user_id,
+            care_assignment.patient_code,
+            care_assignment.care_role.to_string(),
+            starts_at,
+            care_assignment
+                .ends_at
+                .map(|ends_at| ends_at.format(DATETIME_FMT).to_string()),
+            care_assignment.reason,
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn save_emergency_access(
+        &self,
+        emergency_access: &EmergencyAccess,
+    ) -> Result<(), MyError> {
+        sqlx::query!(
+            "insert into emergency_accesses(id,user_id,patient_code,reason,granted_at,expires_at)
+            values(?,?,?,?,?,?)
+            ",
+            emergency_access.id,
+            emergency_access.user_id,
+            emergency_access.patient_code,
+            emergency_access.reason,
+            emergency_access.granted_at.format(DATETIME_FMT).to_string(),
+            emergency_access.expires_at.format(DATETIME_FMT).to_string(),
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn has_emergency_access(
+        &self,
+        user_id: &String,
+        patient_code: &String,
+        now: DateTime<Local>,
+    ) -> Result<bool, MyError> {
+        let now = now.format(DATETIME_FMT).to_string();
+        let record = sqlx::query!(
+            "select id from emergency_accesses
+            where user_id=? and patient_code=? and granted_at<=? and expires_at>?
+            limit 1
             ",
             user_id,
             patient_code,
+            now,
+            now,
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        Ok(record.is_some())
+    }
+
+    async fn fetch_emergency_access(
+        &self,
+        id: &String,
+    ) -> Result<Option<EmergencyAccess>, MyError> {
+        let record = sqlx::query!(
+            "select id,user_id,patient_code,reason,granted_at,expires_at,acknowledged_by,acknowledged_at
+            from emergency_accesses
+            where id=?
+            ",
+            id
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        Ok(record.map(|record| {
+            EmergencyAccess::from(
+                record.id,
+                record.user_id,
+                record.patient_code,
+                record.reason,
+                Local
+                    .datetime_from_str(&record.granted_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                Local
+                    .datetime_from_str(&record.expires_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                record.acknowledged_by,
+                record.acknowledged_at.map(|acknowledged_at| {
+                    Local
+                        .datetime_from_str(&acknowledged_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+            )
+        }))
+    }
+
+    async fn fetch_emergency_accesses(
+        &self,
+        unacknowledged_only: bool,
+    ) -> Result<Vec<EmergencyAccess>, MyError> {
+        let emergency_accesses = sqlx::query!(
+            "select id,user_id,patient_code,reason,granted_at,expires_at,acknowledged_by,acknowledged_at
+            from emergency_accesses
+            where acknowledged_at is null or ?=false
+            order by granted_at desc
+            ",
+            unacknowledged_only
+        )
+        .fetch_all(self.conn)
+        .await?
+        .into_iter()
+        .map(|record| {
+            EmergencyAccess::from(
+                record.id,
+                record.user_id,
+                record.patient_code,
+                record.reason,
+                Local
+                    .datetime_from_str(&record.granted_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                Local
+                    .datetime_from_str(&record.expires_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                record.acknowledged_by,
+                record.acknowledged_at.map(|acknowledged_at| {
+                    Local
+                        .datetime_from_str(&acknowledged_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+            )
+        })
+        .collect::<Vec<EmergencyAccess>>();
+        Ok(emergency_accesses)
+    }
+
+    async fn acknowledge_emergency_access(
+        &self,
+        id: &String,
+        acknowledged_by: &String,
+        now: DateTime<Local>,
+    ) -> Result<(), MyError> {
+        sqlx::query!(
+            "update emergency_accesses set acknowledged_by=?,acknowledged_at=? where id=?",
+            acknowledged_by,
+            now.format(DATETIME_FMT).to_string(),
+            id
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn save_delegation(&self, delegation: &Delegation) -> Result<(), MyError> {
+        let mut tx = self.conn.begin().await?;
+        sqlx::query!(
+            "insert into delegations(id,delegator_id,delegate_id,all_patients,starts_at,ends_at,created_at)
+            values(?,?,?,?,?,?,?)
+            ",
+            delegation.id,
+            delegation.delegator_id,
+            delegation.delegate_id,
+            delegation.patient_codes.is_none(),
+            delegation.starts_at.format(DATETIME_FMT).to_string(),
+            delegation.ends_at.format(DATETIME_FMT).to_string(),
+            delegation.created_at.format(DATETIME_FMT).to_string(),
+        )
+        .execute(&mut tx)
+        .await?;
+        for patient_code in delegation.patient_codes.iter().flatten() {
+            sqlx::query!(
+                "insert into delegation_patients(delegation_id,patient_code)
+                values(?,?)
+                ",
+                delegation.id,
+                patient_code,
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn fetch_delegation(&self, id: &String) -> Result<Option<Delegation>, MyError> {
+        let record = sqlx::query!(
+            "select id,delegator_id,delegate_id,all_patients,starts_at,ends_at,revoked_at,created_at
+            from delegations
+            where id=?
+            ",
+            id
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        let record = match record {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        let patient_codes = if record.all_patients != 0 {
+            None
+        } else {
+            Some(fetch_delegation_patient_codes(self.conn, &record.id).await?)
+        };
+        Ok(Some(Delegation::from(
+            record.id,
+            record.delegator_id,
+            record.delegate_id,
+            patient_codes,
+            Local
+                .datetime_from_str(&record.starts_at.to_string(), DATETIME_FMT)
+                .unwrap(),
+            Local
+                .datetime_from_str(&record.ends_at.to_string(), DATETIME_FMT)
+                .unwrap(),
+            record.revoked_at.map(|revoked_at| {
+                Local
+                    .datetime_from_str(&revoked_at.to_string(), DATETIME_FMT)
+                    .unwrap()
+            }),
+            Local
+                .datetime_from_str(&record.created_at.to_string(), DATETIME_FMT)
+                .unwrap(),
+        )))
+    }
+
+    async fn fetch_delegations(&self, user_id: &String) -> Result<Vec<Delegation>, MyError> {
+        let records = sqlx::query!(
+            "select id,delegator_id,delegate_id,all_patients,starts_at,ends_at,revoked_at,created_at
+            from delegations
+            where delegator_id=? or delegate_id=?
+            order by created_at desc
+            ",
+            user_id,
+            user_id
+        )
+        .fetch_all(self.conn)
+        .await?;
+        let mut delegations = vec![];
+        for record in records {
+            let patient_codes = if record.all_patients != 0 {
+                None
+            } else {
+                Some(fetch_delegation_patient_codes(self.conn, &record.id).await?)
+            };
+            delegations.push(Delegation::from(
+                record.id,
+                record.delegator_id,
+                record.delegate_id,
+                patient_codes,
+                Local
+                    .datetime_from_str(&record.starts_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                Local
+                    .datetime_from_str(&record.ends_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                record.revoked_at.map(|revoked_at| {
+                    Local
+                        .datetime_from_str(&revoked_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+                Local
+                    .datetime_from_str(&record.created_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+            ));
+        }
+        Ok(delegations)
+    }
+
+    async fn fetch_active_delegations(
+        &self,
+        delegate_id: &String,
+        now: DateTime<Local>,
+    ) -> Result<Vec<Delegation>, MyError> {
+        let now = now.format(DATETIME_FMT).to_string();
+        let records = sqlx::query!(
+            "select id,delegator_id,delegate_id,all_patients,starts_at,ends_at,revoked_at,created_at
+            from delegations
+            where delegate_id=? and starts_at<=? and ends_at>? and revoked_at is null
+            order by created_at desc
+            ",
+            delegate_id,
+            now,
+            now
+        )
+        .fetch_all(self.conn)
+        .await?;
+        let mut delegations = vec![];
+        for record in records {
+            let patient_codes = if record.all_patients != 0 {
+                None
+            } else {
+                Some(fetch_delegation_patient_codes(self.conn, &record.id).await?)
+            };
+            delegations.push(Delegation::from(
+                record.id,
+                record.delegator_id,
+                record.delegate_id,
+                patient_codes,
+                Local
+                    .datetime_from_str(&record.starts_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                Local
+                    .datetime_from_str(&record.ends_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+                record.revoked_at.map(|revoked_at| {
+                    Local
+                        .datetime_from_str(&revoked_at.to_string(), DATETIME_FMT)
+                        .unwrap()
+                }),
+                Local
+                    .datetime_from_str(&record.created_at.to_string(), DATETIME_FMT)
+                    .unwrap(),
+            ));
+        }
+        Ok(delegations)
+    }
+
+    async fn revoke_delegation(&self, id: &String, now: DateTime<Local>) -> Result<(), MyError> {
+        sqlx::query!(
+            "update delegations set revoked_at=? where id=? and revoked_at is null",
+            now.format(DATETIME_FMT).to_string(),
+            id
         )
         .execute(self.conn)
         .await?;
@@ -83,6 +696,163 @@ impl DoctorInChargeRepository for DoctorInChargeRepositoryImpl<'_> {
     }
 }
 
+/// patient codes of the delegation which does not delegate every patient.
+async fn fetch_delegation_patient_codes(
+    conn: &MySqlPool,
+    delegation_id: &String,
+) -> Result<Vec<String>, MyError> {
+    let patient_codes = sqlx::query!(
+        "select patient_code from delegation_patients where delegation_id=? order by patient_code",
+        delegation_id
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| record.patient_code)
+    .collect::<Vec<String>>();
+    Ok(patient_codes)
+}
+
+pub struct TwoFactorRepositoryImpl<'a> {
+    pub conn: &'a MySqlPool,
+}
+
+#[async_trait]
+impl TwoFactorRepository for TwoFactorRepositoryImpl<'_> {
+    async fn save(&self, two_factor: &TwoFactor) -> Result<(), MyError> {
+        sqlx::query!(
+            "insert into user_two_factors(user_id,secret,enabled,last_used_step)
+            values(?,?,?,?)
+            on duplicate key update secret=values(secret),enabled=values(enabled),last_used_step=values(last_used_step)
+            ",
+            two_factor.user_id,
+            two_factor.secret,
+            two_factor.enabled,
+            two_factor.last_used_step,
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_by_user_id(&self, user_id: &String) -> Result<Option<TwoFactor>, MyError> {
+        let record = sqlx::query!(
+            "select user_id,secret,enabled,last_used_step
+            from user_two_factors
+            where user_id=?
+            ",
+            user_id
+        )
+        .fetch_optional(self.conn)
+        .await?;
+        Ok(record.map(|record| {
+            TwoFactor::from(
+                record.user_id,
+                record.secret,
+                record.enabled != 0,
+                record.last_used_step,
+            )
+        }))
+    }
+
+    async fn update_last_used_step(&self, user_id: &String, step: i64) -> Result<bool, MyError> {
+        // conditional update, so that one code can not be used twice by two sign ins at once.
+        let result = sqlx::query!(
+            "update user_two_factors set last_used_step=?
+            where user_id=? and (last_used_step is null or last_used_step<?)
+            ",
+            step,
+            user_id,
+            step
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn save_recovery_codes(
+        &self,
+        user_id: &String,
+        recovery_codes: &[RecoveryCode],
+    ) -> Result<(), MyError> {
+        let mut tx = self.conn.begin().await?;
+        sqlx::query!("delete from user_recovery_codes where user_id=?", user_id)
+            .execute(&mut tx)
+            .await?;
+        for recovery_code in recovery_codes {
+            sqlx::query!(
+                "insert into user_recovery_codes(id,user_id,hashed_code)
+                values(?,?,?)
+                ",
+                recovery_code.id,
+                recovery_code.user_id,
+                recovery_code.hashed_code,
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn fetch_recovery_codes(&self, user_id: &String) -> Result<Vec<RecoveryCode>, MyError> {
+        let recovery_codes = sqlx::query!(
+            "select id,user_id,hashed_code
+            from user_recovery_codes
+            where user_id=? and used_at is null
+            ",
+            user_id
+        )
+        .fetch_all(self.conn)
+        .await?
+        .into_iter()
+        .map(|record| RecoveryCode::from(record.id, record.user_id, record.hashed_code))
+        .collect::<Vec<RecoveryCode>>();
+        Ok(recovery_codes)
+    }
+
+    async fn use_recovery_code(&self, id: &String) -> Result<bool, MyError> {
+        // conditional update, same as last_used_step.
+        let result = sqlx::query!(
+            "update user_recovery_codes set used_at=current_timestamp where id=? and used_at is null",
+            id
+        )
+        .execute(self.conn)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+/// lock the patient, so that assignments to the patient are checked and changed one at a time.
+/// then whether the user has another assignment to the patient overlapping care_assignment.
+async fn lock_and_find_overlapping(
+    tx: &mut Transaction<'_, MySql>,
+    care_assignment: &CareAssignment,
+) -> Result<bool, MyError> {
+    sqlx::query!(
+        "select code from patients where code=? for update",
+        care_assignment.patient_code
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let ends_at = care_assignment
+        .ends_at
+        .map(|ends_at| ends_at.format(DATETIME_FMT).to_string());
+    let record = sqlx::query!(
+        "select count(*) as count from doctor_in_charges
+        where user_id=? and patient_code=? and (? is null or starts_at<?) and (ends_at is null or ends_at>?)
+        ",
+        care_assignment.user_id,
+        care_assignment.patient_code,
+        ends_at,
+        ends_at,
+        care_assignment.starts_at.format(DATETIME_FMT).to_string(),
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    Ok(record.count > 0)
+}
+
 pub struct UserRepositoryMockImpl {}
 
 #[async_trait]
@@ -91,14 +861,29 @@ impl UserRepository for UserRepositoryMockImpl {
     async fn save(&self, user: &User) -> Result<(), MyError> {
         Ok(())
     }
-    /// return User::from("test_id", "test_code", "test_name", "test_password")
+
+    /// true if get_users() is empty. never, so someone has signed up first.
+    async fn save_first(&self, user: &User) -> Result<bool, MyError> {
+        Ok(get_users().is_empty())
+    }
+
+    /// true if one of get_invitations() which id matches is usable.
+    async fn save_with_invitation(
+        &self,
+        user: &User,
+        invitation_id: &String,
+    ) -> Result<bool, MyError> {
+        Ok(get_invitations().into_iter().any(|invitation| {
+            &invitation.id == invitation_id && invitation.is_usable(Local::now())
+        }))
+    }
+    /// return one of get_users() which id matches.
     /// id is not correct then return Error
     async fn fetch_one(&self, id: &String) -> Result<User, MyError> {
         // let yaml_file = std::fs::read("/repository/fixtures/user.yaml");
         // let yaml = serde_yaml::Deserializer::from_slice(yaml_file);
 
-        let user = get_data();
-        if id == &user.id {
+        if let Some(user) = get_users().into_iter().find(|user| &user.id == id) {
             return Ok(user);
         } else {
             return Err(MyError::BadRequest(json!({
@@ -107,11 +892,10 @@ impl UserRepository for UserRepositoryMockImpl {
         }
     }
 
-    /// return User::from("test_id", "test_code", "test_name", "test_password")
-    /// name is not correct then return Error
+    /// return one of get_users() which code matches.
+    /// code is not correct then return Error
     async fn find_by_code(&self, code: &String) -> Result<User, MyError> {
-        let user = get_data();
-        if code == &user.code {
+        if let Some(user) = get_users().into_iter().find(|user| &user.code == code) {
             return Ok(user);
         } else {
             return Err(MyError::BadRequest(json!({
@@ -119,23 +903,489 @@ impl UserRepository for UserRepositoryMockImpl {
             })));
         }
     }
+
+    /// nothing is done.
+    async fn update_role(&self, code: &String, role: &Role) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// nothing is done.
+    async fn update_password(&self, id: &String, hashed_password: &String) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// nothing is done.
+    async fn update_name(&self, id: &String, name: &String) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// nothing is done.
+    async fn update_active(&self, code: &String, active: bool) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// nothing is done.
+    async fn update_approved(&self, code: &String, approved: bool) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return get_users().
+    async fn fetch_all(&self) -> Result<Vec<User>, MyError> {
+        Ok(get_users())
+    }
+
+    /// return number of get_users().
+    async fn count(&self) -> Result<i64, MyError> {
+        Ok(get_users().len() as i64)
+    }
 }
 
-fn get_data() -> User {
-    User::from(
-        "test_id".to_string(),
-        "test_code".to_string(),
-        "test_name".to_string(),
-        "test_password".to_string(),
-    )
-    .unwrap()
+/// raw password of get_data().
+pub const TEST_PASSWORD: &str = "test_password";
+/// bcrypt hash of TEST_PASSWORD. hashed in advance to keep tests fast.
+const TEST_HASHED_PASSWORD: &str = "$2b$04$Ojqo0HBbUA670J/p.aixgu.ga6i19hgAuO0NnZNdXPexbu118TcOK";
+
+/// test data. password of every user is TEST_PASSWORD.
+/// "test_id" has not enabled two factor, "test_two_factor_id" has enabled it.
+/// "test_deactivated_id" has been deactivated. "test_pending_id" is waiting for approval.
+fn get_users() -> Vec<User> {
+    vec![
+        User::from(
+            "test_id".to_string(),
+            "test_code".to_string(),
+            "test_name".to_string(),
+            TEST_HASHED_PASSWORD.to_string(),
+            Role::Doctor,
+            true,
+            true,
+        )
+        .unwrap(),
+        User::from(
+            "test_two_factor_id".to_string(),
+            "test_two_factor_code".to_string(),
+            "test_two_factor_name".to_string(),
+            TEST_HASHED_PASSWORD.to_string(),
+            Role::Doctor,
+            true,
+            true,
+        )
+        .unwrap(),
+        User::from(
+            "test_deactivated_id".to_string(),
+            "test_deactivated_code".to_string(),
+            "test_deactivated_name".to_string(),
+            TEST_HASHED_PASSWORD.to_string(),
+            Role::Doctor,
+            false,
+            true,
+        )
+        .unwrap(),
+        User::from(
+            "test_pending_id".to_string(),
+            "test_pending_code".to_string(),
+            "test_pending_name".to_string(),
+            TEST_HASHED_PASSWORD.to_string(),
+            Role::Nurse,
+            true,
+            false,
+        )
+        .unwrap(),
+    ]
 }
 
 pub struct DoctorInChargeRepositoryMockImpl {}
 
 #[async_trait]
 impl DoctorInChargeRepository for DoctorInChargeRepositoryMockImpl {
-    async fn save(&self, user_id: &String, patient_id: &String) -> Result<(), MyError> {
+    /// Conflict if one of get_care_assignments() of the user and the patient overlaps.
+    async fn save(&self, care_assignment: &CareAssignment) -> Result<(), MyError> {
+        if find_overlapping(care_assignment) {
+            return Err(already_in_charge(&care_assignment.patient_code));
+        }
+        Ok(())
+    }
+
+    /// return true if one of get_care_assignments() of the user and the patient is active.
+    async fn exists(
+        &self,
+        user_id: &String,
+        patient_code: &String,
+        now: DateTime<Local>,
+    ) -> Result<bool, MyError> {
+        Ok(get_care_assignments().into_iter().any(|care_assignment| {
+            &care_assignment.user_id == user_id
+                && &care_assignment.patient_code == patient_code
+                && care_assignment.is_active(now)
+        }))
+    }
+
+    /// return get_care_assignments() of the patient, newest first.
+    async fn fetch_by_patient_code(
+        &self,
+        patient_code: &String,
+    ) -> Result<Vec<CareAssignment>, MyError> {
+        let mut care_assignments = get_care_assignments()
+            .into_iter()
+            .filter(|care_assignment| &care_assignment.patient_code == patient_code)
+            .collect::<Vec<CareAssignment>>();
+        care_assignments.sort_by(|a, b| b.starts_at.cmp(&a.starts_at));
+        Ok(care_assignments)
+    }
+
+    /// return true if one of get_care_assignments() of the user and the patient is active.
+    async fn end(
+        &self,
+        user_id: &String,
+        patient_code: &String,
+        now: DateTime<Local>,
+    ) -> Result<bool, MyError> {
+        self.exists(user_id, patient_code, now).await
+    }
+
+    /// return true if one of get_care_assignments() of from_user_id and the patient
+    /// is active at starts_at of care_assignment.
+    async fn transfer(
+        &self,
+        from_user_id: &String,
+        care_assignment: &CareAssignment,
+    ) -> Result<bool, MyError> {
+        if find_overlapping(care_assignment) {
+            return Err(already_in_charge(&care_assignment.patient_code));
+        }
+        self.exists(
+            from_user_id,
+            &care_assignment.patient_code,
+            care_assignment.starts_at,
+        )
+        .await
+    }
+
+    /// nothing is done.
+    async fn save_emergency_access(
+        &self,
+        emergency_access: &EmergencyAccess,
+    ) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return true if one of get_emergency_accesses() of the user and the patient is active.
+    async fn has_emergency_access(
+        &self,
+        user_id: &String,
+        patient_code: &String,
+        now: DateTime<Local>,
+    ) -> Result<bool, MyError> {
+        Ok(get_emergency_accesses()
+            .into_iter()
+            .any(|emergency_access| {
+                &emergency_access.user_id == user_id
+                    && &emergency_access.patient_code == patient_code
+                    && emergency_access.is_active(now)
+            }))
+    }
+
+    /// return one of get_emergency_accesses() which id matches.
+    async fn fetch_emergency_access(
+        &self,
+        id: &String,
+    ) -> Result<Option<EmergencyAccess>, MyError> {
+        Ok(get_emergency_accesses()
+            .into_iter()
+            .find(|emergency_access| &emergency_access.id == id))
+    }
+
+    /// return get_emergency_accesses().
+    async fn fetch_emergency_accesses(
+        &self,
+        unacknowledged_only: bool,
+    ) -> Result<Vec<EmergencyAccess>, MyError> {
+        Ok(get_emergency_accesses()
+            .into_iter()
+            .filter(|emergency_access| !unacknowledged_only || !emergency_access.is_acknowledged())
+            .collect())
+    }
+
+    /// nothing is done.
+    async fn acknowledge_emergency_access(
+        &self,
+        id: &String,
+        acknowledged_by: &String,
+        now: DateTime<Local>,
+    ) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// nothing is done.
+    async fn save_delegation(&self, delegation: &Delegation) -> Result<(), MyError> {
         Ok(())
     }
+
+    /// return one of get_delegations() which id matches.
+    async fn fetch_delegation(&self, id: &String) -> Result<Option<Delegation>, MyError> {
+        Ok(get_delegations()
+            .into_iter()
+            .find(|delegation| &delegation.id == id))
+    }
+
+    /// return get_delegations() the user gave or received.
+    async fn fetch_delegations(&self, user_id: &String) -> Result<Vec<Delegation>, MyError> {
+        Ok(get_delegations()
+            .into_iter()
+            .filter(|delegation| {
+                &delegation.delegator_id == user_id || &delegation.delegate_id == user_id
+            })
+            .collect())
+    }
+
+    /// return get_delegations() the user received which are active.
+    async fn fetch_active_delegations(
+        &self,
+        delegate_id: &String,
+        now: DateTime<Local>,
+    ) -> Result<Vec<Delegation>, MyError> {
+        Ok(get_delegations()
+            .into_iter()
+            .filter(|delegation| {
+                &delegation.delegate_id == delegate_id && delegation.is_active(now)
+            })
+            .collect())
+    }
+
+    /// nothing is done.
+    async fn revoke_delegation(&self, id: &String, now: DateTime<Local>) -> Result<(), MyError> {
+        Ok(())
+    }
+}
+
+/// test data. _1 is active access of "test_user_id_3" to "a".
+/// _2 is expired access of "test_user_id_2" to "a", and has been acknowledged by "test_id".
+pub fn get_emergency_accesses() -> Vec<EmergencyAccess> {
+    let now = Local::now();
+    vec![
+        EmergencyAccess::from(
+            "test_emergency_access_id_1".to_string(),
+            "test_user_id_3".to_string(),
+            "a".to_string(),
+            "cardiac arrest in ER".to_string(),
+            now - Duration::minutes(10),
+            now + Duration::minutes(50),
+            None,
+            None,
+        ),
+        EmergencyAccess::from(
+            "test_emergency_access_id_2".to_string(),
+            "test_user_id_2".to_string(),
+            "a".to_string(),
+            "unconscious patient".to_string(),
+            now - Duration::minutes(90),
+            now - Duration::minutes(30),
+            Some("test_id".to_string()),
+            Some(now - Duration::minutes(20)),
+        ),
+    ]
+}
+
+/// test data. _1 is active delegation of every patient of "test_user_id_1" to "test_id".
+/// _2 is active delegation of "b" of "test_user_id_2" to "test_user_id_3".
+/// _3 has been revoked, and _4 has expired.
+pub fn get_delegations() -> Vec<Delegation> {
+    let now = Local::now();
+    vec![
+        Delegation::from(
+            "test_delegation_id_1".to_string(),
+            "test_user_id_1".to_string(),
+            "test_id".to_string(),
+            None,
+            now - Duration::days(1),
+            now + Duration::days(6),
+            None,
+            now - Duration::days(1),
+        ),
+        Delegation::from(
+            "test_delegation_id_2".to_string(),
+            "test_user_id_2".to_string(),
+            "test_user_id_3".to_string(),
+            Some(vec!["b".to_string()]),
+            now - Duration::days(1),
+            now + Duration::days(6),
+            None,
+            now - Duration::days(2),
+        ),
+        Delegation::from(
+            "test_delegation_id_3".to_string(),
+            "test_user_id_2".to_string(),
+            "test_id".to_string(),
+            None,
+            now - Duration::days(3),
+            now + Duration::days(4),
+            Some(now - Duration::days(2)),
+            now - Duration::days(3),
+        ),
+        Delegation::from(
+            "test_delegation_id_4".to_string(),
+            "test_user_id_1".to_string(),
+            "test_user_id_2".to_string(),
+            None,
+            now - Duration::days(14),
+            now - Duration::days(7),
+            None,
+            now - Duration::days(14),
+        ),
+    ]
+}
+
+/// whether one of get_care_assignments() of the user and the patient overlaps care_assignment.
+fn find_overlapping(care_assignment: &CareAssignment) -> bool {
+    get_care_assignments().iter().any(|other| {
+        other.user_id == care_assignment.user_id
+            && other.patient_code == care_assignment.patient_code
+            && other.overlaps(care_assignment)
+    })
+}
+
+/// test data. each test user is in charge of one of get_patients().
+/// "test_id" of get_users() is consulting "b" for a week, and "test_two_factor_id" is nurse of "b".
+/// "test_user_id_2" was in charge of "a" and "test_deactivated_id" was in charge of "b" until a month ago.
+/// "test_user_id_3" is in charge of archived "c".
+pub fn get_care_assignments() -> Vec<CareAssignment> {
+    let now = Local::now();
+    vec![
+        CareAssignment::from(
+            "test_care_assignment_id_1".to_string(),
+            "test_user_id_1".to_string(),
+            "a".to_string(),
+            CareRole::Attending,
+            now - Duration::days(30),
+            None,
+            None,
+        ),
+        CareAssignment::from(
+            "test_care_assignment_id_2".to_string(),
+            "test_user_id_2".to_string(),
+            "b".to_string(),
+            CareRole::Attending,
+            now - Duration::days(30),
+            None,
+            None,
+        ),
+        CareAssignment::from(
+            "test_care_assignment_id_3".to_string(),
+            "test_id".to_string(),
+            "b".to_string(),
+            CareRole::Consulting,
+            now - Duration::days(7),
+            Some(now + Duration::days(7)),
+            Some("second opinion".to_string()),
+        ),
+        CareAssignment::from(
+            "test_care_assignment_id_4".to_string(),
+            "test_two_factor_id".to_string(),
+            "b".to_string(),
+            CareRole::Nurse,
+            now - Duration::days(20),
+            None,
+            None,
+        ),
+        CareAssignment::from(
+            "test_care_assignment_id_5".to_string(),
+            "test_user_id_2".to_string(),
+            "a".to_string(),
+            CareRole::Resident,
+            now - Duration::days(60),
+            Some(now - Duration::days(30)),
+            Some("rotation".to_string()),
+        ),
+        CareAssignment::from(
+            "test_care_assignment_id_6".to_string(),
+            "test_deactivated_id".to_string(),
+            "b".to_string(),
+            CareRole::Attending,
+            now - Duration::days(90),
+            Some(now - Duration::days(30)),
+            Some("retired".to_string()),
+        ),
+        CareAssignment::from(
+            "test_care_assignment_id_7".to_string(),
+            "test_user_id_3".to_string(),
+            "c".to_string(),
+            CareRole::Attending,
+            now - Duration::days(30),
+            None,
+            None,
+        ),
+    ]
+}
+
+pub struct TwoFactorRepositoryMockImpl {}
+
+#[async_trait]
+impl TwoFactorRepository for TwoFactorRepositoryMockImpl {
+    /// nothing is done.
+    async fn save(&self, two_factor: &TwoFactor) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return one of get_two_factors() which user_id matches.
+    async fn fetch_by_user_id(&self, user_id: &String) -> Result<Option<TwoFactor>, MyError> {
+        Ok(get_two_factors()
+            .into_iter()
+            .find(|two_factor| &two_factor.user_id == user_id))
+    }
+
+    /// true if step is newer than last_used_step of get_two_factors() which user_id matches.
+    async fn update_last_used_step(&self, user_id: &String, step: i64) -> Result<bool, MyError> {
+        Ok(get_two_factors().into_iter().any(|two_factor| {
+            &two_factor.user_id == user_id
+                && !matches!(two_factor.last_used_step, Some(last_used_step) if step <= last_used_step)
+        }))
+    }
+
+    /// nothing is done.
+    async fn save_recovery_codes(
+        &self,
+        user_id: &String,
+        recovery_codes: &[RecoveryCode],
+    ) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    /// return recovery code of TEST_RECOVERY_CODE for every user.
+    async fn fetch_recovery_codes(&self, user_id: &String) -> Result<Vec<RecoveryCode>, MyError> {
+        Ok(vec![RecoveryCode::from(
+            "test_recovery_code_id".to_string(),
+            user_id.clone(),
+            TEST_HASHED_RECOVERY_CODE.to_string(),
+        )])
+    }
+
+    /// true if id is the one of fetch_recovery_codes().
+    async fn use_recovery_code(&self, id: &String) -> Result<bool, MyError> {
+        Ok(id == "test_recovery_code_id")
+    }
+}
+
+/// base32 secret of get_two_factors().
+pub const TEST_TOTP_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+/// raw recovery code of every test user.
+pub const TEST_RECOVERY_CODE: &str = "abcd-efgh";
+/// bcrypt hash of TEST_RECOVERY_CODE.
+const TEST_HASHED_RECOVERY_CODE: &str =
+    "$2b$04$2xi581cVaT6.KHha9VlX4.UhZ.H61KIPvEo.vFES.NnH..Q8so0J2";
+
+/// test data. "test_id" has not confirmed enrolment, "test_two_factor_id" has enabled two factor.
+pub fn get_two_factors() -> Vec<TwoFactor> {
+    vec![
+        TwoFactor::from(
+            "test_id".to_string(),
+            TEST_TOTP_SECRET.to_string(),
+            false,
+            None,
+        ),
+        TwoFactor::from(
+            "test_two_factor_id".to_string(),
+            TEST_TOTP_SECRET.to_string(),
+            true,
+            None,
+        ),
+    ]
 }
@@ -1,39 +1,180 @@
+use crate::middleware::authentication::Authentication;
 use crate::presentation;
 use actix_web::web;
-use actix_web::web::{get, post};
+use actix_web::web::{get, patch, post};
 
 // api route definition
 pub fn api(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api")
+            // public routes. authentication is not required.
+            .service(web::resource("/user").route(post().to(presentation::registration::sign_up)))
+            .service(web::resource("/user/login").route(post().to(presentation::user::sign_in)))
             .service(
-                web::scope("/patient")
-                    .route("", get().to(presentation::patient::fetch_patients))
-                    .route("", post().to(presentation::patient::create_patient))
-                    .route(
-                        "with_me",
-                        post().to(presentation::patient::create_patient_with_medical_examination),
-                    ),
+                web::resource("/user/login/two_factor")
+                    .route(post().to(presentation::user::sign_in_two_factor)),
             )
+            .service(web::resource("/user/refresh").route(post().to(presentation::user::refresh)))
             .service(
-                web::scope("/user")
-                    .route("login", post().to(presentation::user::sign_in))
-                    .route("", post().to(presentation::user::sign_up))
-                    .route("assign", post().to(presentation::user::assign)),
+                web::resource("/user/password_reset")
+                    .route(post().to(presentation::password_reset::request_password_reset)),
             )
             .service(
-                web::scope("/medical_examination")
-                    .route(
-                        "",
-                        get().to(presentation::medical_examination::fetch_medical_examinations),
-                    )
-                    .route(
-                        "",
-                        post().to(presentation::medical_examination::create_medical_examination),
-                    ),
+                web::resource("/user/password_reset/confirm")
+                    .route(post().to(presentation::password_reset::confirm_password_reset)),
+            )
+            .service(
+                web::scope("/oidc")
+                    .route("login", get().to(presentation::oidc::login))
+                    .route("callback", get().to(presentation::oidc::callback)),
             )
             .service(
                 web::scope("/healthcheck").route("", get().to(presentation::healthcheck::index)),
+            )
+            // every route below requires authentication. bearer token is jwt or api key.
+            .service(
+                web::scope("")
+                    .wrap(Authentication)
+                    .service(
+                        web::scope("/patient")
+                            .route("", get().to(presentation::patient::fetch_patients))
+                            .route("", post().to(presentation::patient::create_patient))
+                            .route(
+                                "with_me",
+                                post().to(
+                                    presentation::patient::create_patient_with_medical_examination,
+                                ),
+                            )
+                            .route(
+                                "break_the_glass",
+                                post().to(presentation::patient::break_the_glass),
+                            )
+                            .route(
+                                "doctor",
+                                get().to(presentation::user::fetch_doctors_in_charge),
+                            )
+                            .route("search", get().to(presentation::patient::search_patients))
+                            // after fixed paths, so that "doctor" is not taken as code.
+                            .route("{code}", get().to(presentation::patient::fetch_patient))
+                            .route("{code}", patch().to(presentation::patient::update_patient))
+                            .route(
+                                "{code}/archive",
+                                post().to(presentation::patient::archive_patient),
+                            )
+                            .route(
+                                "{code}/restore",
+                                post().to(presentation::patient::restore_patient),
+                            ),
+                    )
+                    .service(
+                        web::scope("/user")
+                            .route("assign", post().to(presentation::user::assign))
+                            .route("unassign", post().to(presentation::user::unassign))
+                            .route("transfer", post().to(presentation::user::transfer))
+                            .route(
+                                "patient",
+                                get().to(presentation::user::fetch_assigned_patients),
+                            )
+                            .route(
+                                "delegation",
+                                get().to(presentation::user::fetch_delegations),
+                            )
+                            .route("delegation", post().to(presentation::user::delegate))
+                            .route(
+                                "delegation/revoke",
+                                post().to(presentation::user::revoke_delegation),
+                            )
+                            .route("role", post().to(presentation::user::change_role))
+                            .route("unlock", post().to(presentation::user::unlock))
+                            .route("me", get().to(presentation::user::fetch_me))
+                            .route("name", post().to(presentation::user::update_name))
+                            .route("list", get().to(presentation::user::fetch_users))
+                            .route("deactivate", post().to(presentation::user::deactivate))
+                            .route("reactivate", post().to(presentation::user::reactivate))
+                            .route(
+                                "pending",
+                                get().to(presentation::registration::fetch_pending_users),
+                            )
+                            .route("approve", post().to(presentation::registration::approve))
+                            .route("external_identity", post().to(presentation::oidc::link))
+                            .route(
+                                "emergency_access",
+                                get().to(presentation::user::fetch_emergency_accesses),
+                            )
+                            .route(
+                                "emergency_access/acknowledge",
+                                post().to(presentation::user::acknowledge_emergency_access),
+                            )
+                            .route("password", post().to(presentation::user::change_password))
+                            .route("session", get().to(presentation::session::fetch_sessions))
+                            .route(
+                                "session/revoke",
+                                post().to(presentation::session::revoke_session),
+                            )
+                            .route(
+                                "session/revoke_all",
+                                post().to(presentation::session::revoke_all_sessions),
+                            )
+                            .route(
+                                "two_factor",
+                                post().to(presentation::user::enroll_two_factor),
+                            )
+                            .route(
+                                "two_factor/confirm",
+                                post().to(presentation::user::confirm_two_factor),
+                            ),
+                    )
+                    .service(
+                        web::scope("/invitation")
+                            .route("", get().to(presentation::registration::fetch_invitations))
+                            .route("", post().to(presentation::registration::create_invitation))
+                            .route(
+                                "revoke",
+                                post().to(presentation::registration::revoke_invitation),
+                            ),
+                    )
+                    .service(
+                        web::scope("/service_account")
+                            .route(
+                                "",
+                                get().to(presentation::service_account::fetch_service_accounts),
+                            )
+                            .route(
+                                "",
+                                post().to(presentation::service_account::create_service_account),
+                            )
+                            .route(
+                                "api_key",
+                                get().to(presentation::service_account::fetch_api_keys),
+                            )
+                            .route(
+                                "api_key",
+                                post().to(presentation::service_account::issue_api_key),
+                            )
+                            .route(
+                                "api_key/revoke",
+                                post().to(presentation::service_account::revoke_api_key),
+                            ),
+                    )
+                    .service(
+                        web::scope("/medical_examination")
+                            .route(
+                                "",
+                                get().to(
+                                    presentation::medical_examination::fetch_medical_examinations,
+                                ),
+                            )
+                            .route(
+                                "",
+                                post().to(
+                                    presentation::medical_examination::create_medical_examination,
+                                ),
+                            ),
+                    ),
             ),
+    )
+    .service(
+        web::scope("/.well-known")
+            .route("jwks.json", get().to(presentation::well_known::fetch_jwks)),
     );
 }
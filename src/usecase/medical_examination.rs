@@ -4,23 +4,38 @@ use crate::{
     domain::{
         medical_examination::{MedicalExamination, MedicalExaminationRepository},
         patient::PatientRepository,
+        user::{ensure_in_charge, DoctorInChargeRepository},
     },
     utils::errors::MyError,
 };
 
-pub struct MedicalExaminationUsecase<M: MedicalExaminationRepository, P: PatientRepository> {
+pub struct MedicalExaminationUsecase<
+    M: MedicalExaminationRepository,
+    P: PatientRepository,
+    D: DoctorInChargeRepository,
+> {
     pub medical_examination_repository: M,
     pub patient_repository: P,
+    pub doctor_in_charge_repository: D,
 }
 
-impl<M: MedicalExaminationRepository, P: PatientRepository> MedicalExaminationUsecase<M, P> {
-    pub fn new(medical_examination_repository: M, patient_repository: P) -> Self {
+impl<M: MedicalExaminationRepository, P: PatientRepository, D: DoctorInChargeRepository + Sync>
+    MedicalExaminationUsecase<M, P, D>
+{
+    pub fn new(
+        medical_examination_repository: M,
+        patient_repository: P,
+        doctor_in_charge_repository: D,
+    ) -> Self {
         Self {
             medical_examination_repository,
             patient_repository,
+            doctor_in_charge_repository,
         }
     }
 
+    /// patient not exist,NotFound. user is not in charge of the patient,Forbidden.
+    /// patient is archived,BadRequest.
     pub async fn create_medical_examination(
         &self,
         interviewed_at: Option<DateTime<Local>>,
@@ -29,17 +44,25 @@ impl<M: MedicalExaminationRepository, P: PatientRepository> MedicalExaminationUs
         symptom: String,
     ) -> Result<(), MyError> {
         let medical_examination = MedicalExamination::new(symptom, interviewed_at);
-        self.patient_repository.fetch_by_code(&patient_code).await?;
+        self.patient_repository
+            .fetch_by_code(&patient_code)
+            .await?
+            .ensure_not_archived()?;
+        ensure_in_charge(&self.doctor_in_charge_repository, &user_id, &patient_code).await?;
         self.medical_examination_repository
             .save(&user_id, &patient_code, &medical_examination)
             .await?;
         Ok(())
     }
 
+    /// patient not exist,NotFound. user is not in charge of the patient,Forbidden.
     pub async fn fetch_by_patient_code(
         &self,
+        user_id: String,
         patient_code: String,
     ) -> Result<Vec<MedicalExamination>, MyError> {
+        self.patient_repository.fetch_by_code(&patient_code).await?;
+        ensure_in_charge(&self.doctor_in_charge_repository, &user_id, &patient_code).await?;
         let medical_examinations = self
             .medical_examination_repository
             .fetch_by_patient_code(&patient_code)
@@ -58,26 +81,30 @@ mod tests {
                 get_medical_examinations, MedicalExaminationRepositoryMockImpl,
             },
             patient_repository::PatientRepositoryMockImpl,
+            user_repository::DoctorInChargeRepositoryMockImpl,
         },
         utils::datetime::DATETIME_FMT,
     };
     use chrono::{Local, TimeZone};
+    use serde_json::json;
 
     use super::*;
 
     #[tokio::test]
     async fn test_create_medical_examination() {
-        let code = "test_code".to_string();
+        let code = "a".to_string();
         let interviewed_at = Local
             .datetime_from_str("2022-12-12 12:12:12", DATETIME_FMT)
             .unwrap();
-        let user_id = "1".to_string();
+        let user_id = "test_user_id_1".to_string();
         let symptom = "headache".to_string();
         let mock_patient_repository = PatientRepositoryMockImpl {};
         let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
         let medical_examination_usecase = MedicalExaminationUsecase {
             patient_repository: mock_patient_repository,
             medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
         };
         medical_examination_usecase
             .create_medical_examination(Some(interviewed_at), user_id, code, symptom)
@@ -87,17 +114,109 @@ mod tests {
 
     #[tokio::test]
     async fn test_fetch_by_patient_code() {
-        let code = "test_code".to_string();
+        let code = "a".to_string();
+        let user_id = "test_user_id_1".to_string();
         let mock_patient_repository = PatientRepositoryMockImpl {};
         let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
         let medical_examination_usecase = MedicalExaminationUsecase {
             patient_repository: mock_patient_repository,
             medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
         };
         let medical_examinations = medical_examination_usecase
-            .fetch_by_patient_code(code)
+            .fetch_by_patient_code(user_id, code)
             .await
             .unwrap();
         assert_eq!(medical_examinations, get_medical_examinations())
     }
+
+    #[tokio::test]
+    async fn test_fetch_by_patient_code_not_in_charge() {
+        // test_user_id_2 is in charge of "b", and the assignment to "a" has ended.
+        let code = "a".to_string();
+        let user_id = "test_user_id_2".to_string();
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let medical_examination_usecase = MedicalExaminationUsecase {
+            patient_repository: mock_patient_repository,
+            medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+        };
+        let err = medical_examination_usecase
+            .fetch_by_patient_code(user_id, code)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Forbidden(json!({"error":"not in charge of patient code=a."}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_by_patient_code_not_found() {
+        let code = "not_exist".to_string();
+        let user_id = "test_user_id_1".to_string();
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let medical_examination_usecase = MedicalExaminationUsecase {
+            patient_repository: mock_patient_repository,
+            medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+        };
+        let err = medical_examination_usecase
+            .fetch_by_patient_code(user_id, code)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::NotFound(json!({"error":"no record of code=not_exist."}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_medical_examination_not_in_charge() {
+        let code = "b".to_string();
+        let user_id = "test_user_id_1".to_string();
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let medical_examination_usecase = MedicalExaminationUsecase {
+            patient_repository: mock_patient_repository,
+            medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+        };
+        let err = medical_examination_usecase
+            .create_medical_examination(None, user_id, code, "headache".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Forbidden(json!({"error":"not in charge of patient code=b."}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_medical_examination_archived() {
+        let code = "c".to_string();
+        let user_id = "test_user_id_3".to_string();
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let medical_examination_usecase = MedicalExaminationUsecase {
+            patient_repository: mock_patient_repository,
+            medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+        };
+        let err = medical_examination_usecase
+            .create_medical_examination(None, user_id, code, "headache".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"patient code=c is archived."}))
+        );
+    }
 }
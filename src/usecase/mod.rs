@@ -1,3 +1,8 @@
 pub mod medical_examination;
+pub mod oidc;
+pub mod password_reset;
 pub mod patient;
+pub mod registration;
+pub mod service_account;
+pub mod session;
 pub mod user;
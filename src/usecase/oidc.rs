@@ -0,0 +1,376 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Local;
+use serde_json::json;
+
+use crate::{
+    domain::{
+        oidc::{
+            ExternalIdentity, ExternalIdentityRepository, IdTokenClaims, IdentityProvider,
+            OidcConfig, OidcLoginState,
+        },
+        refresh_token::RefreshTokenRepository,
+        session::{ClientInfo, SessionRepository},
+        user::{User, UserRepository, NAME_LIMIT},
+    },
+    usecase::session::issue_tokens,
+    utils::{errors::MyError, hash::hash_password, totp::random_bytes},
+};
+
+const PROVISION_PASSWORD_BYTES: usize = 32;
+
+pub struct OidcUsecase<
+    U: UserRepository,
+    E: ExternalIdentityRepository,
+    R: RefreshTokenRepository,
+    S: SessionRepository,
+    I: IdentityProvider,
+> {
+    pub user_repository: U,
+    pub external_identity_repository: E,
+    pub refresh_token_repository: R,
+    pub session_repository: S,
+    pub identity_provider: I,
+    pub config: OidcConfig,
+}
+
+impl<
+        U: UserRepository,
+        E: ExternalIdentityRepository,
+        R: RefreshTokenRepository + Sync,
+        S: SessionRepository + Sync,
+        I: IdentityProvider,
+    > OidcUsecase<U, E, R, S, I>
+{
+    pub fn new(
+        user_repository: U,
+        external_identity_repository: E,
+        refresh_token_repository: R,
+        session_repository: S,
+        identity_provider: I,
+        config: OidcConfig,
+    ) -> Self {
+        Self {
+            user_repository,
+            external_identity_repository,
+            refresh_token_repository,
+            session_repository,
+            identity_provider,
+            config,
+        }
+    }
+
+    /// start login. return id of login state to keep in cookie, and url to redirect browser to.
+    pub async fn start_login(&self) -> Result<(String, String), MyError> {
+        let login_state = OidcLoginState::new();
+        self.external_identity_repository
+            .save_login_state(&login_state)
+            .await?;
+        let url = self
+            .identity_provider
+            .authorization_url(&login_state)
+            .await?;
+        Ok((login_state.id, url))
+    }
+
+    /// finish login with authorization code. state must be the one kept in cookie by start_login.
+    /// return user, access token and refresh token. two factor of our own is not asked,
+    /// as the identity provider has authenticated the user.
+    pub async fn callback(
+        &self,
+        state: String,
+        cookie_state: Option<String>,
+        code: String,
+        client: ClientInfo,
+    ) -> Result<(User, String, String), MyError> {
+        if cookie_state.as_ref() != Some(&state) {
+            return Err(login_state_invalid());
+        }
+        let login_state = match self
+            .external_identity_repository
+            .take_login_state(&state)
+            .await?
+        {
+            Some(login_state) if !login_state.is_expired(Local::now()) => login_state,
+            _ => return Err(login_state_invalid()),
+        };
+        let claims = self
+            .identity_provider
+            .exchange_code(&code, &login_state)
+            .await?;
+        if claims.nonce.as_ref() != Some(&login_state.nonce) {
+            return Err(MyError::Unauthorized(
+                json!({"error":"nonce of id token is invalid"}),
+            ));
+        }
+        let user = match self
+            .external_identity_repository
+            .fetch_by_subject(&self.config.issuer, &claims.sub)
+            .await?
+        {
+            Some(external_identity) => {
+                self.user_repository
+                    .fetch_one(&external_identity.user_id)
+                    .await?
+            }
+            None => self.provision(&claims).await?,
+        };
+        user.ensure_active()?;
+
+        let (token, raw_refresh_token) = issue_tokens(
+            &self.session_repository,
+            &self.refresh_token_repository,
+            &user,
+            &client,
+        )
+        .await?;
+        Ok((user, token, raw_refresh_token))
+    }
+
+    /// link existing user to subject of the identity provider, so that the user can login with it.
+    pub async fn link(&self, code: String, subject: String) -> Result<(), MyError> {
+        let user = self.user_repository.find_by_code(&code).await?;
+        if self
+            .external_identity_repository
+            .fetch_by_subject(&self.config.issuer, &subject)
+            .await?
+            .is_some()
+        {
+            return Err(MyError::BadRequest(json!({
+                "error": format!("subject={} is already linked to a user.", subject)
+            })));
+        }
+        let external_identity = ExternalIdentity::new(user.id, self.config.issuer.clone(), subject);
+        self.external_identity_repository
+            .save(&external_identity)
+            .await?;
+        Ok(())
+    }
+
+    /// create user linked to the subject with role of config. if auto provision is disabled,Forbidden.
+    async fn provision(&self, claims: &IdTokenClaims) -> Result<User, MyError> {
+        if !self.config.auto_provision {
+            return Err(MyError::Forbidden(
+                json!({"error":"no user is linked to the external account"}),
+            ));
+        }
+        let name = claims
+            .name
+            .as_ref()
+            .or(claims.preferred_username.as_ref())
+            .unwrap_or(&claims.sub)
+            .chars()
+            .take(NAME_LIMIT as usize)
+            .collect::<String>();
+        // provisioned user logs in through the identity provider. nobody knows this password.
+        let hashed_password =
+            hash_password(&URL_SAFE_NO_PAD.encode(random_bytes(PROVISION_PASSWORD_BYTES)))?;
+        let mut user = User::new(name, None, hashed_password, self.config.provision_role)?;
+        user.approved = !self.config.provision_approval_required;
+        self.user_repository.save(&user).await?;
+        let external_identity = ExternalIdentity::new(
+            user.id.clone(),
+            self.config.issuer.clone(),
+            claims.sub.clone(),
+        );
+        self.external_identity_repository
+            .save(&external_identity)
+            .await?;
+        Ok(user)
+    }
+}
+
+fn login_state_invalid() -> MyError {
+    MyError::BadRequest(json!({"error":"login state is invalid or has expired"}))
+}
+
+#[cfg(test)]
+
+mod tests {
+    use crate::domain::user::Role;
+    use crate::middleware::keyring::{init_keyring, Keyring, SigningKey};
+    use crate::repository::{
+        external_identity_repository::{ExternalIdentityRepositoryMockImpl, TEST_ISSUER},
+        identity_provider::IdentityProviderMockImpl,
+        refresh_token_repository::RefreshTokenRepositoryMockImpl,
+        session_repository::SessionRepositoryMockImpl,
+        user_repository::UserRepositoryMockImpl,
+    };
+
+    use super::*;
+
+    fn set_up_keyring() {
+        init_keyring(Keyring::new(
+            SigningKey::hmac("test_kid".to_string(), "test_secret".to_string()),
+            vec![],
+        ));
+    }
+
+    fn oidc_config(auto_provision: bool) -> OidcConfig {
+        OidcConfig {
+            issuer: TEST_ISSUER.to_string(),
+            client_id: "test_client_id".to_string(),
+            client_secret: "test_client_secret".to_string(),
+            redirect_uri: "http://localhost:8000/api/oidc/callback".to_string(),
+            auto_provision,
+            provision_role: Role::Nurse,
+            provision_approval_required: false,
+        }
+    }
+
+    fn oidc_usecase(
+        config: OidcConfig,
+    ) -> OidcUsecase<
+        UserRepositoryMockImpl,
+        ExternalIdentityRepositoryMockImpl,
+        RefreshTokenRepositoryMockImpl,
+        SessionRepositoryMockImpl,
+        IdentityProviderMockImpl,
+    > {
+        OidcUsecase {
+            user_repository: UserRepositoryMockImpl {},
+            external_identity_repository: ExternalIdentityRepositoryMockImpl {},
+            refresh_token_repository: RefreshTokenRepositoryMockImpl {},
+            session_repository: SessionRepositoryMockImpl {},
+            identity_provider: IdentityProviderMockImpl {},
+            config,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_start_login() {
+        let oidc_usecase = oidc_usecase(oidc_config(false));
+        let (state, url) = oidc_usecase.start_login().await.unwrap();
+        assert_eq!(url, format!("{}/authorize?state={}", TEST_ISSUER, state));
+    }
+
+    #[tokio::test]
+    async fn test_callback() {
+        set_up_keyring();
+        let oidc_usecase = oidc_usecase(oidc_config(false));
+        let (user, token, refresh_token) = oidc_usecase
+            .callback(
+                "test_state".to_string(),
+                Some("test_state".to_string()),
+                "test_subject".to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(user.id, "test_id");
+        assert!(!token.is_empty());
+        assert!(!refresh_token.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_callback_provision() {
+        set_up_keyring();
+        let (user, _, _) = oidc_usecase(oidc_config(true))
+            .callback(
+                "test_state".to_string(),
+                Some("test_state".to_string()),
+                "test_new_subject".to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(user.name, "test_external_name");
+        assert_eq!(user.role, Role::Nurse);
+        assert!(user.approved);
+
+        // provisioned user waits for approval.
+        let mut config = oidc_config(true);
+        config.provision_approval_required = true;
+        let err = oidc_usecase(config)
+            .callback(
+                "test_state".to_string(),
+                Some("test_state".to_string()),
+                "test_new_subject".to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Forbidden(json!({"error":"account is waiting for approval"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_callback_failed() {
+        set_up_keyring();
+        let oidc_usecase = oidc_usecase(oidc_config(false));
+        // state is not of this browser, unknown, or expired.
+        for (state, cookie_state) in [
+            ("test_state", None),
+            ("test_state", Some("other_state")),
+            ("unknown_state", Some("unknown_state")),
+            ("test_expired_state", Some("test_expired_state")),
+        ] {
+            let err = oidc_usecase
+                .callback(
+                    state.to_string(),
+                    cookie_state.map(|cookie_state| cookie_state.to_string()),
+                    "test_subject".to_string(),
+                    ClientInfo::default(),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(err, login_state_invalid());
+        }
+        for (code, expected) in [
+            (
+                "test_invalid_code",
+                MyError::Unauthorized(json!({"error":"authorization code is invalid"})),
+            ),
+            (
+                "test_replayed_code",
+                MyError::Unauthorized(json!({"error":"nonce of id token is invalid"})),
+            ),
+            (
+                "test_new_subject",
+                MyError::Forbidden(json!({"error":"no user is linked to the external account"})),
+            ),
+            (
+                "test_deactivated_subject",
+                MyError::Forbidden(json!({"error":"account is deactivated"})),
+            ),
+        ] {
+            let err = oidc_usecase
+                .callback(
+                    "test_state".to_string(),
+                    Some("test_state".to_string()),
+                    code.to_string(),
+                    ClientInfo::default(),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(err, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_link() {
+        let oidc_usecase = oidc_usecase(oidc_config(false));
+        oidc_usecase
+            .link(
+                "test_two_factor_code".to_string(),
+                "test_new_subject".to_string(),
+            )
+            .await
+            .unwrap();
+        let err = oidc_usecase
+            .link(
+                "test_two_factor_code".to_string(),
+                "test_subject".to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(
+                json!({"error":"subject=test_subject is already linked to a user."})
+            )
+        );
+    }
+}
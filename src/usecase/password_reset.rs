@@ -0,0 +1,301 @@
+use chrono::Local;
+use serde_json::json;
+
+use crate::{
+    domain::{
+        notification::{Notification, Notifier},
+        password_policy::{
+            ensure_password_policy, PasswordContext, PasswordHistoryRepository, PasswordPolicy,
+        },
+        password_reset::{split_raw_token, PasswordResetToken, PasswordResetTokenRepository},
+        refresh_token::RefreshTokenRepository,
+        session::SessionRepository,
+        user::UserRepository,
+    },
+    usecase::session::revoke_sessions_of_user,
+    utils::{
+        errors::MyError,
+        hash::{hash_password, verify},
+    },
+};
+
+pub struct PasswordResetUsecase<
+    U: UserRepository,
+    T: PasswordResetTokenRepository,
+    R: RefreshTokenRepository,
+    N: Notifier,
+    H: PasswordHistoryRepository,
+    S: SessionRepository,
+> {
+    pub user_repository: U,
+    pub password_reset_token_repository: T,
+    pub refresh_token_repository: R,
+    pub notifier: N,
+    pub password_history_repository: H,
+    pub session_repository: S,
+    pub password_policy: PasswordPolicy,
+}
+
+impl<
+        U: UserRepository,
+        T: PasswordResetTokenRepository,
+        R: RefreshTokenRepository + Sync,
+        N: Notifier,
+        H: PasswordHistoryRepository + Sync,
+        S: SessionRepository + Sync,
+    > PasswordResetUsecase<U, T, R, N, H, S>
+{
+    pub fn new(
+        user_repository: U,
+        password_reset_token_repository: T,
+        refresh_token_repository: R,
+        notifier: N,
+        password_history_repository: H,
+        session_repository: S,
+        password_policy: PasswordPolicy,
+    ) -> Self {
+        Self {
+            user_repository,
+            password_reset_token_repository,
+            refresh_token_repository,
+            notifier,
+            password_history_repository,
+            session_repository,
+            password_policy,
+        }
+    }
+
+    /// issue reset token and send it to the user. older tokens of the user stop working.
+    /// unknown code also succeeds, so client can not find out which codes exist.
+    /// nothing is sent to deactivated or not yet approved user.
+    pub async fn request_password_reset(&self, code: String) -> Result<(), MyError> {
+        let user = match self.user_repository.find_by_code(&code).await {
+            Ok(user) if user.active && user.approved => user,
+            Ok(_) | Err(MyError::BadRequest(_)) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        self.password_reset_token_repository
+            .revoke_by_user_id(&user.id)
+            .await?;
+        let (password_reset_token, raw_token) = PasswordResetToken::new(user.id.clone())?;
+        self.password_reset_token_repository
+            .save(&password_reset_token)
+            .await?;
+
+        let notification = Notification::new(
+            user.id,
+            "password reset".to_string(),
+            format!(
+                "reset your password with this token until {}.\n{}",
+                password_reset_token.expires_at.to_rfc3339(),
+                raw_token
+            ),
+        );
+        self.notifier.notify(&notification).await?;
+        Ok(())
+    }
+
+    /// set new password with reset token. every session of the user is signed out.
+    /// access tokens already issued stop working too.
+    pub async fn confirm_password_reset(
+        &self,
+        raw_token: String,
+        raw_password: String,
+    ) -> Result<(), MyError> {
+        let (id, secret) = split_raw_token(&raw_token)?;
+        let password_reset_token = self.password_reset_token_repository.fetch_one(&id).await?;
+        if !verify(&secret, &password_reset_token.hashed_token)? {
+            return Err(MyError::BadRequest(
+                json!({"error":"password reset token is invalid"}),
+            ));
+        }
+        if !password_reset_token.is_usable(Local::now()) {
+            return Err(password_reset_token_unusable());
+        }
+        let user = self
+            .user_repository
+            .fetch_one(&password_reset_token.user_id)
+            .await?;
+        user.ensure_active()?;
+        let context = PasswordContext {
+            name: &user.name,
+            code: &user.code,
+        };
+        ensure_password_policy(
+            &self.password_policy,
+            &self.password_history_repository,
+            &raw_password,
+            &context,
+            Some(&user.id),
+        )
+        .await?;
+        // mark first, so that two resets at once can not share one token.
+        if !self
+            .password_reset_token_repository
+            .mark_used(&password_reset_token.id)
+            .await?
+        {
+            return Err(password_reset_token_unusable());
+        }
+
+        let hashed_password = hash_password(&raw_password)?;
+        self.user_repository
+            .update_password(&user.id, &hashed_password)
+            .await?;
+        self.password_history_repository
+            .save(&user.id, &hashed_password)
+            .await?;
+        revoke_sessions_of_user(
+            &self.session_repository,
+            &self.refresh_token_repository,
+            &user.id,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+fn password_reset_token_unusable() -> MyError {
+    MyError::BadRequest(json!({"error":"password reset token has expired or already been used"}))
+}
+
+#[cfg(test)]
+
+mod tests {
+    use crate::repository::{
+        notifier::NotifierMockImpl,
+        password_history_repository::{PasswordHistoryRepositoryMockImpl, TEST_OLD_PASSWORD},
+        password_reset_repository::{
+            PasswordResetTokenRepositoryMockImpl, TEST_PASSWORD_RESET_TOKEN_SECRET,
+        },
+        refresh_token_repository::RefreshTokenRepositoryMockImpl,
+        session_repository::SessionRepositoryMockImpl,
+        user_repository::UserRepositoryMockImpl,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_request_password_reset() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_password_reset_token_repository = PasswordResetTokenRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_notifier = NotifierMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let password_reset_usecase = PasswordResetUsecase {
+            user_repository: mock_user_repository,
+            password_reset_token_repository: mock_password_reset_token_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            notifier: mock_notifier,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            password_policy: PasswordPolicy::default(),
+        };
+        password_reset_usecase
+            .request_password_reset("test_code".to_string())
+            .await
+            .unwrap();
+        // unknown code is not told.
+        password_reset_usecase
+            .request_password_reset("unknown_code".to_string())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_confirm_password_reset() {
+        let raw_token = format!(
+            "test_password_reset_token_id_1.{}",
+            TEST_PASSWORD_RESET_TOKEN_SECRET
+        );
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_password_reset_token_repository = PasswordResetTokenRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_notifier = NotifierMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let password_reset_usecase = PasswordResetUsecase {
+            user_repository: mock_user_repository,
+            password_reset_token_repository: mock_password_reset_token_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            notifier: mock_notifier,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            password_policy: PasswordPolicy::default(),
+        };
+        password_reset_usecase
+            .confirm_password_reset(raw_token, "new-passw0rd".to_string())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_confirm_password_reset_failed() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_password_reset_token_repository = PasswordResetTokenRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_notifier = NotifierMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let password_reset_usecase = PasswordResetUsecase {
+            user_repository: mock_user_repository,
+            password_reset_token_repository: mock_password_reset_token_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            notifier: mock_notifier,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            password_policy: PasswordPolicy::default(),
+        };
+        // used, expired and used by another reset meanwhile.
+        for id in [
+            "test_password_reset_token_id_2",
+            "test_password_reset_token_id_3",
+            "test_password_reset_token_id_4",
+        ] {
+            let err = password_reset_usecase
+                .confirm_password_reset(
+                    format!("{}.{}", id, TEST_PASSWORD_RESET_TOKEN_SECRET),
+                    "new-passw0rd".to_string(),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err,
+                MyError::BadRequest(
+                    json!({"error":"password reset token has expired or already been used"})
+                )
+            );
+        }
+        // password in history.
+        let err = password_reset_usecase
+            .confirm_password_reset(
+                format!(
+                    "test_password_reset_token_id_1.{}",
+                    TEST_PASSWORD_RESET_TOKEN_SECRET
+                ),
+                TEST_OLD_PASSWORD.to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({
+                "error": "password does not satisfy the policy",
+                "violations": ["password must differ from the last 5 passwords"],
+            }))
+        );
+        // wrong secret.
+        let err = password_reset_usecase
+            .confirm_password_reset(
+                "test_password_reset_token_id_1.wrong_secret".to_string(),
+                "new-passw0rd".to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"password reset token is invalid"}))
+        );
+    }
+}
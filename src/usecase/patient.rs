@@ -1,24 +1,60 @@
 use chrono::{DateTime, Local};
+use log::warn;
+use serde_json::json;
 
 use crate::{
     domain::medical_examination::MedicalExaminationRepository,
     domain::{
+        care_assignment::{fetch_care_team, CareAssignment, CareRole},
+        delegation::{fetch_patients_in_charge, Delegation},
+        emergency_access::EmergencyAccess,
         medical_examination::{self, MedicalExamination},
-        patient::{Patient, PatientRepository},
+        patient::{
+            Demographics, NameMatch, Patient, PatientChart, PatientPatch, PatientQuery,
+            PatientRepository,
+        },
+        user::{ensure_patient_access, DoctorInChargeRepository, UserRepository},
     },
     utils::errors::MyError,
 };
 
-pub struct PatientUsecase<P: PatientRepository, M: MedicalExaminationRepository> {
+/// latest medical examinations in chart of patient.
+const LATEST_MEDICAL_EXAMINATION_LIMIT: usize = 5;
+/// patients in search result.
+const SEARCH_LIMIT: usize = 50;
+/// patients fetched from DB to rank for search result.
+const SEARCH_CANDIDATE_LIMIT: u32 = 200;
+
+pub struct PatientUsecase<
+    P: PatientRepository,
+    M: MedicalExaminationRepository,
+    D: DoctorInChargeRepository,
+    U: UserRepository,
+> {
     pub patient_repository: P,
     pub medical_examination_repository: M,
+    pub doctor_in_charge_repository: D,
+    pub user_repository: U,
 }
 
-impl<T: PatientRepository, M: MedicalExaminationRepository> PatientUsecase<T, M> {
-    pub fn new(patient_repository: T, medical_examination_repository: M) -> Self {
+impl<
+        T: PatientRepository + Sync,
+        M: MedicalExaminationRepository,
+        D: DoctorInChargeRepository + Sync,
+        U: UserRepository + Sync,
+    > PatientUsecase<T, M, D, U>
+{
+    pub fn new(
+        patient_repository: T,
+        medical_examination_repository: M,
+        doctor_in_charge_repository: D,
+        user_repository: U,
+    ) -> Self {
         Self {
             patient_repository,
             medical_examination_repository,
+            doctor_in_charge_repository,
+            user_repository,
         }
     }
 
@@ -27,40 +63,253 @@ impl<T: PatientRepository, M: MedicalExaminationRepository> PatientUsecase<T, M>
         &self,
         name: String,
         code: Option<String>,
+        demographics: Demographics,
     ) -> Result<Patient, MyError> {
-        let patient = Patient::new(name, code)?;
+        let patient = Patient::new(name, code, demographics)?;
         let _ = self.patient_repository.save(&patient).await?;
         Ok(patient)
     }
-    /// create new patient.
+    /// update patient. expected_version is the version the user has edited.
+    /// patient has been updated since,PreconditionFailed.
+    pub async fn update_patient(
+        &self,
+        user_id: &String,
+        access_all_patients: bool,
+        patient_code: String,
+        expected_version: u32,
+        patch: PatientPatch,
+    ) -> Result<Patient, MyError> {
+        let mut patient = self
+            .fetch_accessible(user_id, access_all_patients, &patient_code)
+            .await?;
+        if expected_version != patient.version {
+            return Err(patient_modified(&patient_code));
+        }
+        patient.apply(patch)?;
+        // someone else may have updated between fetch and update.
+        if !self.patient_repository.update(&patient).await? {
+            return Err(patient_modified(&patient_code));
+        }
+        Ok(patient)
+    }
+
+    /// archive patient instead of deleting it, so that medical examinations and care history remain.
+    pub async fn archive_patient(
+        &self,
+        user_id: String,
+        access_all_patients: bool,
+        patient_code: String,
+        reason: String,
+    ) -> Result<Patient, MyError> {
+        let mut patient = self
+            .fetch_accessible(&user_id, access_all_patients, &patient_code)
+            .await?;
+        patient.archive(user_id, reason, Local::now())?;
+        if !self.patient_repository.update(&patient).await? {
+            return Err(patient_modified(&patient_code));
+        }
+        Ok(patient)
+    }
+
+    /// restore archived patient.
+    pub async fn restore_patient(
+        &self,
+        user_id: &String,
+        access_all_patients: bool,
+        patient_code: String,
+    ) -> Result<Patient, MyError> {
+        let mut patient = self
+            .fetch_accessible(user_id, access_all_patients, &patient_code)
+            .await?;
+        patient.restore()?;
+        if !self.patient_repository.update(&patient).await? {
+            return Err(patient_modified(&patient_code));
+        }
+        Ok(patient)
+    }
+
+    /// create new patient. the user who examined is set attending of the patient.
     pub async fn create_patient_with_medical_examination(
         &self,
         name: String,
         code: Option<String>,
+        demographics: Demographics,
         interviewed_at: Option<DateTime<Local>>,
         user_id: String,
         symptom: String,
     ) -> Result<Patient, MyError> {
-        let patient = Patient::new(name, code.clone())?;
+        let patient = Patient::new(name, code, demographics)?;
         let medical_examination = MedicalExamination::new(symptom, interviewed_at);
-        self.patient_repository.save(&patient).await?;
-
-        self.medical_examination_repository
-            .save(&user_id, &patient.code, &medical_examination)
+        let care_assignment = CareAssignment::new(
+            user_id,
+            patient.code.clone(),
+            CareRole::Attending,
+            None,
+            None,
+            None,
+        )?;
+        // patient is never left without the doctor in charge or the first examination.
+        self.patient_repository
+            .save_with_medical_examination(&patient, &care_assignment, &medical_examination)
             .await?;
 
         Ok(patient)
     }
 
-    pub async fn fetch_one(&self, id: &String) -> Result<Patient, MyError> {
-        self.patient_repository.fetch_one(id).await
+    /// chart of patient the user can access. patient with the care team at the moment,
+    /// and latest medical examinations if include_medical_examinations.
+    pub async fn fetch_chart(
+        &self,
+        user_id: &String,
+        access_all_patients: bool,
+        patient_code: &String,
+        include_medical_examinations: bool,
+    ) -> Result<PatientChart, MyError> {
+        let patient = self
+            .fetch_accessible(user_id, access_all_patients, patient_code)
+            .await?;
+        let care_team = fetch_care_team(
+            &self.user_repository,
+            &self.doctor_in_charge_repository,
+            &patient.code,
+            false,
+            Local::now(),
+        )
+        .await?;
+        let (latest_medical_examinations, medical_examination_count) =
+            if include_medical_examinations {
+                let mut medical_examinations = self
+                    .medical_examination_repository
+                    .fetch_by_patient_code(&patient.code)
+                    .await?;
+                // examinations without interviewed_at come last.
+                medical_examinations.sort_by(|a, b| b.interviewed_at.cmp(&a.interviewed_at));
+                let medical_examination_count = medical_examinations.len();
+                medical_examinations.truncate(LATEST_MEDICAL_EXAMINATION_LIMIT);
+                (Some(medical_examinations), Some(medical_examination_count))
+            } else {
+                (None, None)
+            };
+        Ok(PatientChart {
+            patient,
+            care_team,
+            latest_medical_examinations,
+            medical_examination_count,
+        })
     }
 
-    pub async fn fetch_patients(&self) -> Result<Vec<Patient>, MyError> {
-        self.patient_repository.fetch_all().await
+    /// search patients by name or kana reading, and date of birth. for reception to find the patient
+    /// before registering, so patients not in charge are included. best match first, 50 at most.
+    /// names are matched from the beginning, and anywhere only if none matches so.
+    pub async fn search_patients(
+        &self,
+        name: Option<String>,
+        date_of_birth: Option<String>,
+        include_archived: bool,
+    ) -> Result<Vec<Patient>, MyError> {
+        let query = PatientQuery::new(name, date_of_birth, include_archived)?;
+        let mut patients = self
+            .patient_repository
+            .search(&query, NameMatch::Prefix, SEARCH_CANDIDATE_LIMIT)
+            .await?;
+        if patients.is_empty() && query.name.is_some() {
+            patients = self
+                .patient_repository
+                .search(&query, NameMatch::Partial, SEARCH_CANDIDATE_LIMIT)
+                .await?;
+        }
+        let mut patients = query.rank(patients);
+        patients.truncate(SEARCH_LIMIT);
+        Ok(patients)
+    }
+
+    /// fetch patients the user is in charge of, and patients delegated to the user with the delegation.
+    /// archived patients are left out unless include_archived.
+    pub async fn fetch_patients(
+        &self,
+        user_id: &String,
+        include_archived: bool,
+    ) -> Result<Vec<(Patient, Option<Delegation>)>, MyError> {
+        fetch_patients_in_charge(
+            &self.patient_repository,
+            &self.doctor_in_charge_repository,
+            user_id,
+            Local::now(),
+            include_archived,
+        )
+        .await
+    }
+
+    /// break the glass. grant the user time limited access to a patient not in charge of.
+    /// the access is recorded with the reason and left for administrator to review.
+    /// patient not exist,NotFound. user is already in charge of the patient,BadRequest.
+    pub async fn break_the_glass(
+        &self,
+        user_id: String,
+        patient_code: String,
+        reason: String,
+    ) -> Result<EmergencyAccess, MyError> {
+        self.patient_repository.fetch_by_code(&patient_code).await?;
+        if self
+            .doctor_in_charge_repository
+            .exists(&user_id, &patient_code, Local::now())
+            .await?
+        {
+            return Err(MyError::BadRequest(json!({
+                "error": format!("already in charge of patient code={}.", patient_code)
+            })));
+        }
+        let emergency_access = EmergencyAccess::new(user_id, patient_code, reason)?;
+        self.doctor_in_charge_repository
+            .save_emergency_access(&emergency_access)
+            .await?;
+        warn!(
+            "emergency access id={} user_id={} patient_code={} reason={:?}",
+            emergency_access.id,
+            emergency_access.user_id,
+            emergency_access.patient_code,
+            emergency_access.reason
+        );
+        Ok(emergency_access)
+    }
+
+    /// patient not exist,NotFound. the user can not access it,Forbidden.
+    async fn fetch_accessible(
+        &self,
+        user_id: &String,
+        access_all_patients: bool,
+        patient_code: &String,
+    ) -> Result<Patient, MyError> {
+        let patient = self.patient_repository.fetch_by_code(patient_code).await?;
+        ensure_patient_access(
+            &self.doctor_in_charge_repository,
+            user_id,
+            access_all_patients,
+            &patient.code,
+        )
+        .await?;
+        Ok(patient)
     }
 }
 
+fn patient_modified(patient_code: &String) -> MyError {
+    MyError::PreconditionFailed(json!({
+        "error": format!("patient code={} has been updated by someone else.", patient_code)
+    }))
+}
+
+/// fill search_name and search_kana of patients registered before search, by normalize_for_search.
+/// run once on start. return how many patients are filled.
+pub async fn fill_search_columns<P: PatientRepository + Sync>(
+    patient_repository: &P,
+) -> Result<usize, MyError> {
+    let patients = patient_repository.fetch_without_search_name().await?;
+    for patient in &patients {
+        patient_repository.update_search_columns(patient).await?;
+    }
+    Ok(patients.len())
+}
+
 #[cfg(test)]
 
 mod tests {
@@ -69,10 +318,12 @@ mod tests {
         repository::{
             medical_examination_repository::MedicalExaminationRepositoryMockImpl,
             patient_repository::{get_patients, PatientRepositoryMockImpl},
+            user_repository::{DoctorInChargeRepositoryMockImpl, UserRepositoryMockImpl},
         },
         utils::datetime::DATETIME_FMT,
     };
     use chrono::{Local, TimeZone};
+    use serde_json::json;
 
     use super::*;
 
@@ -82,14 +333,19 @@ mod tests {
         let code = "test_code".to_string();
         let mock_patient_repository = PatientRepositoryMockImpl {};
         let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_user_repository = UserRepositoryMockImpl {};
         let patient_usecase = PatientUsecase {
             patient_repository: mock_patient_repository,
             medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            user_repository: mock_user_repository,
         };
-        patient_usecase
-            .create_patient(name, Some(code))
+        let patient = patient_usecase
+            .create_patient(name, Some(code), Demographics::default())
             .await
             .unwrap();
+        assert_eq!(patient.demographics, Demographics::default());
     }
 
     #[tokio::test]
@@ -103,14 +359,19 @@ mod tests {
         let symptom = "headache".to_string();
         let mock_patient_repository = PatientRepositoryMockImpl {};
         let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_user_repository = UserRepositoryMockImpl {};
         let patient_usecase = PatientUsecase {
             patient_repository: mock_patient_repository,
             medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            user_repository: mock_user_repository,
         };
         patient_usecase
             .create_patient_with_medical_examination(
                 name,
                 Some(code),
+                Demographics::default(),
                 Some(interviewed_at),
                 user_id,
                 symptom,
@@ -119,15 +380,417 @@ mod tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn test_update_patient() {
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let patient_usecase = PatientUsecase {
+            patient_repository: mock_patient_repository,
+            medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            user_repository: mock_user_repository,
+        };
+        let test_user_id = "test_id".to_string();
+        let patient = patient_usecase
+            .update_patient(
+                &test_user_id,
+                false,
+                "b".to_string(),
+                3,
+                PatientPatch {
+                    name: Some("test_patient_name_fixed".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(patient.name, "test_patient_name_fixed");
+        assert_eq!(patient.version, 4);
+
+        let err = patient_usecase
+            .update_patient(
+                &test_user_id,
+                false,
+                "b".to_string(),
+                2,
+                PatientPatch::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::PreconditionFailed(
+                json!({"error":"patient code=b has been updated by someone else."})
+            )
+        );
+        let err = patient_usecase
+            .update_patient(
+                &test_user_id,
+                false,
+                "b".to_string(),
+                3,
+                PatientPatch {
+                    date_of_birth: Some("2000-13-01".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(
+                json!({"error":"date_of_birth must be a valid date of YYYY-MM-DD"})
+            )
+        );
+
+        // test_user_id_2 is no longer in charge of "a", unless the user is at reception.
+        let err = patient_usecase
+            .update_patient(
+                &"test_user_id_2".to_string(),
+                false,
+                "a".to_string(),
+                1,
+                PatientPatch::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Forbidden(json!({"error":"not in charge of patient code=a."}))
+        );
+        let patient = patient_usecase
+            .update_patient(
+                &"test_user_id_2".to_string(),
+                true,
+                "a".to_string(),
+                1,
+                PatientPatch::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(patient.version, 2);
+    }
+
+    #[tokio::test]
+    async fn test_archive_patient() {
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let patient_usecase = PatientUsecase {
+            patient_repository: mock_patient_repository,
+            medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            user_repository: mock_user_repository,
+        };
+        let patient = patient_usecase
+            .archive_patient(
+                "test_id".to_string(),
+                false,
+                "a".to_string(),
+                "duplicated".to_string(),
+            )
+            .await
+            .unwrap();
+        let archive = patient.archive.unwrap();
+        assert_eq!(archive.archived_by, "test_id");
+        assert_eq!(archive.reason, "duplicated");
+        assert_eq!(patient.version, 2);
+        let err = patient_usecase
+            .archive_patient(
+                "test_user_id_3".to_string(),
+                false,
+                "c".to_string(),
+                "duplicated".to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"patient code=c is archived."}))
+        );
+        // archived patient can not be updated until restored.
+        let err = patient_usecase
+            .update_patient(
+                &"test_user_id_3".to_string(),
+                false,
+                "c".to_string(),
+                2,
+                PatientPatch::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"patient code=c is archived."}))
+        );
+
+        // only users who can access the patient restore it.
+        let err = patient_usecase
+            .restore_patient(&"test_id".to_string(), false, "c".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Forbidden(json!({"error":"not in charge of patient code=c."}))
+        );
+        let patient = patient_usecase
+            .restore_patient(&"test_user_id_3".to_string(), false, "c".to_string())
+            .await
+            .unwrap();
+        assert!(!patient.is_archived());
+        assert_eq!(patient.version, 3);
+        let err = patient_usecase
+            .restore_patient(&"test_id".to_string(), true, "a".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"patient code=a is not archived."}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_patients() {
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let patient_usecase = PatientUsecase {
+            patient_repository: mock_patient_repository,
+            medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            user_repository: mock_user_repository,
+        };
+        for (name, date_of_birth, include_archived, expected) in [
+            (Some("てすと かんじゃ"), None, false, vec!["a", "b"]),
+            (Some("ﾃｽﾄｶﾝｼﾞｬﾆ"), None, false, vec!["b"]),
+            (Some("TEST_PATIENT_NAME_2"), None, false, vec!["b"]),
+            (Some("name"), None, false, vec!["a", "b"]),
+            // no prefix match, so matched anywhere.
+            (Some("かんじゃ に"), None, false, vec!["b"]),
+            // archived "c" has no kana reading, so comes first of prefix matches.
+            (Some("test"), None, true, vec!["c", "a", "b"]),
+            (Some("test"), Some("1980-01-15"), false, vec!["a"]),
+            (None, Some("1980-01-15"), false, vec!["a"]),
+            (Some("やまだ"), None, false, vec![]),
+        ] {
+            let patients = patient_usecase
+                .search_patients(
+                    name.map(|name| name.to_string()),
+                    date_of_birth.map(|date_of_birth| date_of_birth.to_string()),
+                    include_archived,
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                patients
+                    .iter()
+                    .map(|patient| patient.code.as_str())
+                    .collect::<Vec<&str>>(),
+                expected
+            );
+        }
+        let err = patient_usecase
+            .search_patients(None, None, false)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"name or date_of_birth is required"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fill_search_columns() {
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let filled = fill_search_columns(&mock_patient_repository).await.unwrap();
+        assert_eq!(filled, 3);
+    }
+
     #[tokio::test]
     async fn test_fetch_patients() {
         let mock_patient_repository = PatientRepositoryMockImpl {};
         let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_user_repository = UserRepositoryMockImpl {};
         let patient_usecase = PatientUsecase {
             patient_repository: mock_patient_repository,
             medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            user_repository: mock_user_repository,
         };
-        let patients = patient_usecase.fetch_patients().await.unwrap();
-        assert_eq!(patients, get_patients())
+        let patients = patient_usecase
+            .fetch_patients(&"test_user_id_1".to_string(), false)
+            .await
+            .unwrap();
+        assert_eq!(patients, vec![(get_patients()[0].clone(), None)]);
+
+        let patients = patient_usecase
+            .fetch_patients(&"test_user_id_2".to_string(), false)
+            .await
+            .unwrap();
+        assert_eq!(patients, vec![(get_patients()[1].clone(), None)]);
+
+        // test_id sees "a" through delegation from test_user_id_1 besides "b" of the own.
+        let patients = patient_usecase
+            .fetch_patients(&"test_id".to_string(), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            patients
+                .into_iter()
+                .map(|(patient, delegation)| (
+                    patient.code,
+                    delegation.map(|delegation| delegation.id)
+                ))
+                .collect::<Vec<(String, Option<String>)>>(),
+            vec![
+                ("b".to_string(), None),
+                ("a".to_string(), Some("test_delegation_id_1".to_string())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_chart() {
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let patient_usecase = PatientUsecase {
+            patient_repository: mock_patient_repository,
+            medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            user_repository: mock_user_repository,
+        };
+        let patient_chart = patient_usecase
+            .fetch_chart(&"test_id".to_string(), false, &"b".to_string(), true)
+            .await
+            .unwrap();
+        assert_eq!(patient_chart.patient, get_patients()[1]);
+        // ended assignment of test_deactivated_id is not in the care team.
+        assert_eq!(
+            patient_chart
+                .care_team
+                .iter()
+                .map(|(user, care_assignment)| (user.id.as_str(), care_assignment.care_role))
+                .collect::<Vec<(&str, CareRole)>>(),
+            vec![
+                ("test_id", CareRole::Consulting),
+                ("test_two_factor_id", CareRole::Nurse)
+            ]
+        );
+        assert_eq!(patient_chart.medical_examination_count, Some(2));
+        assert_eq!(patient_chart.latest_medical_examinations.unwrap().len(), 2);
+
+        let patient_chart = patient_usecase
+            .fetch_chart(&"test_id".to_string(), false, &"b".to_string(), false)
+            .await
+            .unwrap();
+        assert_eq!(patient_chart.latest_medical_examinations, None);
+        assert_eq!(patient_chart.medical_examination_count, None);
+
+        let err = patient_usecase
+            .fetch_chart(&"test_id".to_string(), false, &"z".to_string(), true)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::NotFound(json!({"error":"no record of code=z."}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_chart_not_in_charge() {
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let patient_usecase = PatientUsecase {
+            patient_repository: mock_patient_repository,
+            medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            user_repository: mock_user_repository,
+        };
+        // test_user_id_2 is no longer in charge of "a".
+        let err = patient_usecase
+            .fetch_chart(&"test_user_id_2".to_string(), false, &"a".to_string(), true)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Forbidden(json!({"error":"not in charge of patient code=a."}))
+        );
+        // test_user_id_3 has broken the glass for "a".
+        let patient_chart = patient_usecase
+            .fetch_chart(&"test_user_id_3".to_string(), false, &"a".to_string(), true)
+            .await
+            .unwrap();
+        assert_eq!(patient_chart.patient.code, "a");
+        // test_user_id_1 has delegated "a" to test_id.
+        let patient_chart = patient_usecase
+            .fetch_chart(&"test_id".to_string(), false, &"a".to_string(), true)
+            .await
+            .unwrap();
+        assert_eq!(patient_chart.patient.code, "a");
+        // reception sees registration of the patient, but not medical examinations.
+        let patient_chart = patient_usecase
+            .fetch_chart(&"test_user_id_2".to_string(), true, &"a".to_string(), false)
+            .await
+            .unwrap();
+        assert_eq!(patient_chart.patient.code, "a");
+        assert_eq!(patient_chart.medical_examination_count, None);
+    }
+
+    #[tokio::test]
+    async fn test_break_the_glass() {
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_medical_examination_repository = MedicalExaminationRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let patient_usecase = PatientUsecase {
+            patient_repository: mock_patient_repository,
+            medical_examination_repository: mock_medical_examination_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            user_repository: mock_user_repository,
+        };
+        let emergency_access = patient_usecase
+            .break_the_glass(
+                "test_user_id_2".to_string(),
+                "a".to_string(),
+                "cardiac arrest in ER".to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(emergency_access.patient_code, "a");
+        assert!(emergency_access.is_active(Local::now()));
+
+        let err = patient_usecase
+            .break_the_glass(
+                "test_user_id_1".to_string(),
+                "a".to_string(),
+                "cardiac arrest in ER".to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"already in charge of patient code=a."}))
+        );
+        let err = patient_usecase
+            .break_the_glass(
+                "test_user_id_2".to_string(),
+                "not_exist".to_string(),
+                "cardiac arrest in ER".to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::NotFound(json!({"error":"no record of code=not_exist."}))
+        );
     }
 }
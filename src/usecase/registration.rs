@@ -0,0 +1,449 @@
+use chrono::Local;
+use serde_json::json;
+
+use crate::{
+    domain::{
+        invitation::{split_raw_code, Invitation, InvitationRepository},
+        password_policy::{
+            ensure_password_policy, PasswordContext, PasswordHistoryRepository, PasswordPolicy,
+        },
+        refresh_token::RefreshTokenRepository,
+        session::{ClientInfo, SessionRepository},
+        user::{Role, User, UserRepository},
+    },
+    usecase::session::issue_tokens,
+    utils::{errors::MyError, hash::hash_password},
+};
+
+pub struct RegistrationUsecase<
+    U: UserRepository,
+    I: InvitationRepository,
+    H: PasswordHistoryRepository,
+    S: SessionRepository,
+    R: RefreshTokenRepository,
+> {
+    pub user_repository: U,
+    pub invitation_repository: I,
+    pub password_history_repository: H,
+    pub session_repository: S,
+    pub refresh_token_repository: R,
+    pub password_policy: PasswordPolicy,
+}
+
+impl<
+        U: UserRepository,
+        I: InvitationRepository,
+        H: PasswordHistoryRepository + Sync,
+        S: SessionRepository + Sync,
+        R: RefreshTokenRepository + Sync,
+    > RegistrationUsecase<U, I, H, S, R>
+{
+    pub fn new(
+        user_repository: U,
+        invitation_repository: I,
+        password_history_repository: H,
+        session_repository: S,
+        refresh_token_repository: R,
+        password_policy: PasswordPolicy,
+    ) -> Self {
+        Self {
+            user_repository,
+            invitation_repository,
+            password_history_repository,
+            session_repository,
+            refresh_token_repository,
+            password_policy,
+        }
+    }
+
+    /// sign up with invitation. new user gets the role of the invitation.
+    /// return user, access token and refresh token. tokens are None if the invitation requires approval.
+    /// only the first user can sign up without invitation, and becomes administrator.
+    pub async fn sign_up(
+        &self,
+        name: String,
+        code: Option<String>,
+        raw_password: String,
+        raw_invitation_code: Option<String>,
+        client: ClientInfo,
+    ) -> Result<(User, Option<(String, String)>), MyError> {
+        let invitation = match raw_invitation_code {
+            Some(raw_invitation_code) => Some(self.find_invitation(&raw_invitation_code).await?),
+            None if self.user_repository.count().await? == 0 => None,
+            None => return Err(invitation_required()),
+        };
+        let context = PasswordContext {
+            name: &name,
+            code: code.as_deref().unwrap_or_default(),
+        };
+        ensure_password_policy(
+            &self.password_policy,
+            &self.password_history_repository,
+            &raw_password,
+            &context,
+            None,
+        )
+        .await?;
+        let hashed_password = hash_password(&raw_password)?;
+        let role = invitation
+            .as_ref()
+            .map_or(Role::Administrator, |invitation| invitation.role);
+        let mut user = User::new(name, code, hashed_password, role)?;
+        match &invitation {
+            Some(invitation) => {
+                user.approved = !invitation.approval_required;
+                // claimed with the save, so that one invitation can not sign up two users.
+                if !self
+                    .user_repository
+                    .save_with_invitation(&user, &invitation.id)
+                    .await?
+                {
+                    return Err(invitation_unusable());
+                }
+            }
+            None => {
+                if !self.user_repository.save_first(&user).await? {
+                    return Err(invitation_required());
+                }
+            }
+        }
+        self.password_history_repository
+            .save(&user.id, &user.hashed_password)
+            .await?;
+        if !user.approved {
+            return Ok((user, None));
+        }
+        let tokens = issue_tokens(
+            &self.session_repository,
+            &self.refresh_token_repository,
+            &user,
+            &client,
+        )
+        .await?;
+
+        Ok((user, Some(tokens)))
+    }
+
+    /// return new invitation and raw code. raw code can not be shown again.
+    pub async fn create_invitation(
+        &self,
+        role: Role,
+        expires_in_days: Option<i64>,
+        approval_required: bool,
+        created_by: String,
+    ) -> Result<(Invitation, String), MyError> {
+        let (invitation, raw_code) =
+            Invitation::new(role, expires_in_days, approval_required, created_by)?;
+        self.invitation_repository.save(&invitation).await?;
+        Ok((invitation, raw_code))
+    }
+
+    pub async fn fetch_invitations(&self) -> Result<Vec<Invitation>, MyError> {
+        self.invitation_repository.fetch_all().await
+    }
+
+    pub async fn revoke_invitation(&self, id: String) -> Result<(), MyError> {
+        if self.invitation_repository.fetch_one(&id).await?.is_none() {
+            return Err(MyError::NotFound(json!({
+                "error": format!("no invitation of id={}.", id)
+            })));
+        }
+        self.invitation_repository.revoke(&id).await?;
+        Ok(())
+    }
+
+    /// users waiting for approval. deactivated ones are excluded as they have been rejected.
+    pub async fn fetch_pending_users(&self) -> Result<Vec<User>, MyError> {
+        Ok(self
+            .user_repository
+            .fetch_all()
+            .await?
+            .into_iter()
+            .filter(|user| user.active && !user.approved)
+            .collect())
+    }
+
+    /// approved user can sign in.
+    pub async fn approve(&self, code: String) -> Result<(), MyError> {
+        let user = self.user_repository.find_by_code(&code).await?;
+        if user.approved {
+            return Err(MyError::BadRequest(
+                json!({"error":"user is already approved"}),
+            ));
+        }
+        self.user_repository.update_approved(&code, true).await?;
+        Ok(())
+    }
+
+    /// unknown, wrong, used, revoked or expired code is BadRequest.
+    async fn find_invitation(&self, raw_code: &str) -> Result<Invitation, MyError> {
+        let (id, secret) = split_raw_code(raw_code)?;
+        let invitation = match self.invitation_repository.fetch_one(&id).await? {
+            Some(invitation) if invitation.matches(&secret) => invitation,
+            _ => {
+                return Err(MyError::BadRequest(
+                    json!({"error":"invitation code is invalid"}),
+                ))
+            }
+        };
+        if !invitation.is_usable(Local::now()) {
+            return Err(invitation_unusable());
+        }
+        Ok(invitation)
+    }
+}
+
+fn invitation_required() -> MyError {
+    MyError::BadRequest(json!({"error":"invitation code is required"}))
+}
+
+fn invitation_unusable() -> MyError {
+    MyError::BadRequest(json!({
+        "error": "invitation code has expired or already been used"
+    }))
+}
+
+#[cfg(test)]
+
+mod tests {
+    use crate::middleware::keyring::{init_keyring, Keyring, SigningKey};
+    use crate::repository::{
+        invitation_repository::{InvitationRepositoryMockImpl, TEST_INVITATION_SECRET},
+        password_history_repository::PasswordHistoryRepositoryMockImpl,
+        refresh_token_repository::RefreshTokenRepositoryMockImpl,
+        session_repository::SessionRepositoryMockImpl,
+        user_repository::UserRepositoryMockImpl,
+    };
+
+    use super::*;
+
+    fn set_up_keyring() {
+        init_keyring(Keyring::new(
+            SigningKey::hmac("test_kid".to_string(), "test_secret".to_string()),
+            vec![],
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sign_up() {
+        set_up_keyring();
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_invitation_repository = InvitationRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let registration_usecase = RegistrationUsecase {
+            user_repository: mock_user_repository,
+            invitation_repository: mock_invitation_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            password_policy: PasswordPolicy::default(),
+        };
+        let (user, tokens) = registration_usecase
+            .sign_up(
+                "test_name".to_string(),
+                Some("test_new_code".to_string()),
+                "correct-horse-42".to_string(),
+                Some(format!("test_invitation_id_1.{}", TEST_INVITATION_SECRET)),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(user.role, Role::Nurse);
+        assert!(user.approved);
+        assert!(tokens.is_some());
+
+        // no token until approved.
+        let (user, tokens) = registration_usecase
+            .sign_up(
+                "test_name".to_string(),
+                Some("test_new_code".to_string()),
+                "correct-horse-42".to_string(),
+                Some(format!("test_invitation_id_2.{}", TEST_INVITATION_SECRET)),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(user.role, Role::Doctor);
+        assert!(!user.approved);
+        assert!(tokens.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sign_up_failed() {
+        set_up_keyring();
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_invitation_repository = InvitationRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let registration_usecase = RegistrationUsecase {
+            user_repository: mock_user_repository,
+            invitation_repository: mock_invitation_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            password_policy: PasswordPolicy::default(),
+        };
+        // users exist, so invitation is required.
+        let err = registration_usecase
+            .sign_up(
+                "test_name".to_string(),
+                None,
+                "correct-horse-42".to_string(),
+                None,
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"invitation code is required"}))
+        );
+        for raw_invitation_code in [
+            "test_invitation_id_1.wrong_secret".to_string(),
+            format!("unknown_id.{}", TEST_INVITATION_SECRET),
+            "test_invitation_id_1".to_string(),
+        ] {
+            let err = registration_usecase
+                .sign_up(
+                    "test_name".to_string(),
+                    None,
+                    "correct-horse-42".to_string(),
+                    Some(raw_invitation_code),
+                    ClientInfo::default(),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err,
+                MyError::BadRequest(json!({"error":"invitation code is invalid"}))
+            );
+        }
+        // used, revoked and expired invitation.
+        for id in [
+            "test_invitation_id_3",
+            "test_invitation_id_4",
+            "test_invitation_id_5",
+        ] {
+            let err = registration_usecase
+                .sign_up(
+                    "test_name".to_string(),
+                    None,
+                    "correct-horse-42".to_string(),
+                    Some(format!("{}.{}", id, TEST_INVITATION_SECRET)),
+                    ClientInfo::default(),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err,
+                MyError::BadRequest(
+                    json!({"error":"invitation code has expired or already been used"})
+                )
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign_up_weak_password_failed() {
+        set_up_keyring();
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_invitation_repository = InvitationRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let registration_usecase = RegistrationUsecase {
+            user_repository: mock_user_repository,
+            invitation_repository: mock_invitation_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            password_policy: PasswordPolicy::default(),
+        };
+        let err = registration_usecase
+            .sign_up(
+                "test_name".to_string(),
+                Some("test_code".to_string()),
+                "test_name".to_string(),
+                Some(format!("test_invitation_id_1.{}", TEST_INVITATION_SECRET)),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({
+                "error": "password does not satisfy the policy",
+                "violations": ["password must not contain user name or code"],
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_approve() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_invitation_repository = InvitationRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let registration_usecase = RegistrationUsecase {
+            user_repository: mock_user_repository,
+            invitation_repository: mock_invitation_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            password_policy: PasswordPolicy::default(),
+        };
+        let pending_users = registration_usecase.fetch_pending_users().await.unwrap();
+        assert_eq!(
+            pending_users
+                .iter()
+                .map(|user| user.code.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["test_pending_code"]
+        );
+        registration_usecase
+            .approve("test_pending_code".to_string())
+            .await
+            .unwrap();
+        let err = registration_usecase
+            .approve("test_code".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"user is already approved"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_revoke_invitation() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_invitation_repository = InvitationRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let registration_usecase = RegistrationUsecase {
+            user_repository: mock_user_repository,
+            invitation_repository: mock_invitation_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            password_policy: PasswordPolicy::default(),
+        };
+        registration_usecase
+            .revoke_invitation("test_invitation_id_1".to_string())
+            .await
+            .unwrap();
+        let err = registration_usecase
+            .revoke_invitation("unknown_id".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::NotFound(json!({"error":"no invitation of id=unknown_id."}))
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use chrono::{Duration, Local};
+use serde_json::json;
+
+use crate::{
+    domain::{
+        service_account::{
+            split_raw_key, ApiKey, ApiKeyRepository, ServiceAccount, ServiceAccountRepository,
+        },
+        user::{Permission, Role},
+    },
+    utils::errors::MyError,
+};
+
+pub struct ServiceAccountUsecase<S: ServiceAccountRepository, K: ApiKeyRepository> {
+    pub service_account_repository: S,
+    pub api_key_repository: K,
+}
+
+impl<S: ServiceAccountRepository, K: ApiKeyRepository> ServiceAccountUsecase<S, K> {
+    pub fn new(service_account_repository: S, api_key_repository: K) -> Self {
+        Self {
+            service_account_repository,
+            api_key_repository,
+        }
+    }
+
+    pub async fn create_service_account(
+        &self,
+        name: String,
+        role: Role,
+        created_by: String,
+    ) -> Result<ServiceAccount, MyError> {
+        let service_account = ServiceAccount::new(name, role, created_by)?;
+        self.service_account_repository
+            .save(&service_account)
+            .await?;
+        Ok(service_account)
+    }
+
+    pub async fn fetch_service_accounts(&self) -> Result<Vec<ServiceAccount>, MyError> {
+        self.service_account_repository.fetch_all().await
+    }
+
+    /// return new key and raw key. raw key can not be shown again.
+    pub async fn issue_api_key(
+        &self,
+        service_account_id: String,
+        scopes: Vec<Permission>,
+        expires_in_days: Option<i64>,
+    ) -> Result<(ApiKey, String), MyError> {
+        let service_account = self
+            .service_account_repository
+            .fetch_one(&service_account_id)
+            .await?;
+        let expires_at = match expires_in_days {
+            Some(days) if days <= 0 => {
+                return Err(MyError::BadRequest(
+                    json!({"error":"expires_in_days must be positive"}),
+                ))
+            }
+            Some(days) => Some(Local::now() + Duration::days(days)),
+            None => None,
+        };
+        let (api_key, raw_key) = ApiKey::new(&service_account, scopes, expires_at)?;
+        self.api_key_repository.save(&api_key).await?;
+        Ok((api_key, raw_key))
+    }
+
+    pub async fn fetch_api_keys(&self, service_account_id: String) -> Result<Vec<ApiKey>, MyError> {
+        // service_account_id check
+        self.service_account_repository
+            .fetch_one(&service_account_id)
+            .await?;
+        self.api_key_repository
+            .fetch_by_service_account_id(&service_account_id)
+            .await
+    }
+
+    pub async fn revoke_api_key(&self, id: String) -> Result<(), MyError> {
+        if self.api_key_repository.fetch_one(&id).await?.is_none() {
+            return Err(MyError::NotFound(json!({
+                "error": format!("no api key of id={}.", id)
+            })));
+        }
+        self.api_key_repository.revoke(&id).await?;
+        Ok(())
+    }
+
+    /// find service account of raw key and the key. last used time of the key is updated.
+    /// unknown, wrong, revoked or expired key is Unauthorized.
+    pub async fn authenticate(&self, raw_key: &str) -> Result<(ServiceAccount, ApiKey), MyError> {
+        let now = Local::now();
+        let (id, secret) = split_raw_key(raw_key)?;
+        let api_key = match self.api_key_repository.fetch_one(&id).await? {
+            Some(api_key) if api_key.matches(&secret) => api_key,
+            _ => return Err(MyError::Unauthorized(json!({"error":"api key is invalid"}))),
+        };
+        if !api_key.is_usable(now) {
+            return Err(MyError::Unauthorized(
+                json!({"error":"api key has expired or been revoked"}),
+            ));
+        }
+        self.api_key_repository
+            .update_last_used_at(&api_key.id, now)
+            .await?;
+        let service_account = self
+            .service_account_repository
+            .fetch_one(&api_key.service_account_id)
+            .await?;
+        Ok((service_account, api_key))
+    }
+}
+
+#[cfg(test)]
+
+mod tests {
+    use crate::repository::service_account_repository::{
+        ApiKeyRepositoryMockImpl, ServiceAccountRepositoryMockImpl, TEST_API_KEY_SECRET,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_issue_api_key() {
+        let mock_service_account_repository = ServiceAccountRepositoryMockImpl {};
+        let mock_api_key_repository = ApiKeyRepositoryMockImpl {};
+        let service_account_usecase = ServiceAccountUsecase {
+            service_account_repository: mock_service_account_repository,
+            api_key_repository: mock_api_key_repository,
+        };
+        let (api_key, raw_key) = service_account_usecase
+            .issue_api_key(
+                "test_service_account_id".to_string(),
+                vec![Permission::ReadPatient],
+                Some(30),
+            )
+            .await
+            .unwrap();
+        assert!(raw_key.starts_with(&api_key.prefix()));
+        assert_eq!(api_key.scopes, vec![Permission::ReadPatient]);
+        assert!(api_key.expires_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_issue_api_key_failed() {
+        let mock_service_account_repository = ServiceAccountRepositoryMockImpl {};
+        let mock_api_key_repository = ApiKeyRepositoryMockImpl {};
+        let service_account_usecase = ServiceAccountUsecase {
+            service_account_repository: mock_service_account_repository,
+            api_key_repository: mock_api_key_repository,
+        };
+        // scope beyond the role.
+        let err = service_account_usecase
+            .issue_api_key(
+                "test_service_account_id".to_string(),
+                vec![Permission::ManageUser],
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"nurse is not permitted to manage_user"}))
+        );
+        let err = service_account_usecase
+            .issue_api_key(
+                "unknown_id".to_string(),
+                vec![Permission::ReadPatient],
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::NotFound(json!({"error":"no service account of id=unknown_id."}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let mock_service_account_repository = ServiceAccountRepositoryMockImpl {};
+        let mock_api_key_repository = ApiKeyRepositoryMockImpl {};
+        let service_account_usecase = ServiceAccountUsecase {
+            service_account_repository: mock_service_account_repository,
+            api_key_repository: mock_api_key_repository,
+        };
+        let (service_account, api_key) = service_account_usecase
+            .authenticate(&format!("pmk_test_api_key_id_1.{}", TEST_API_KEY_SECRET))
+            .await
+            .unwrap();
+        assert_eq!(service_account.id, "test_service_account_id");
+        assert_eq!(service_account.role, Role::Nurse);
+        assert_eq!(
+            api_key.scopes,
+            vec![Permission::ReadPatient, Permission::ReadMedicalExamination]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_failed() {
+        let mock_service_account_repository = ServiceAccountRepositoryMockImpl {};
+        let mock_api_key_repository = ApiKeyRepositoryMockImpl {};
+        let service_account_usecase = ServiceAccountUsecase {
+            service_account_repository: mock_service_account_repository,
+            api_key_repository: mock_api_key_repository,
+        };
+        for raw_key in [
+            "pmk_test_api_key_id_1.wrong_secret".to_string(),
+            format!("pmk_unknown_id.{}", TEST_API_KEY_SECRET),
+        ] {
+            let err = service_account_usecase
+                .authenticate(&raw_key)
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err,
+                MyError::Unauthorized(json!({"error":"api key is invalid"}))
+            );
+        }
+        // revoked and expired key.
+        for id in ["test_api_key_id_2", "test_api_key_id_3"] {
+            let err = service_account_usecase
+                .authenticate(&format!("pmk_{}.{}", id, TEST_API_KEY_SECRET))
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err,
+                MyError::Unauthorized(json!({"error":"api key has expired or been revoked"}))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_revoke_api_key() {
+        let mock_service_account_repository = ServiceAccountRepositoryMockImpl {};
+        let mock_api_key_repository = ApiKeyRepositoryMockImpl {};
+        let service_account_usecase = ServiceAccountUsecase {
+            service_account_repository: mock_service_account_repository,
+            api_key_repository: mock_api_key_repository,
+        };
+        service_account_usecase
+            .revoke_api_key("test_api_key_id_1".to_string())
+            .await
+            .unwrap();
+        let err = service_account_usecase
+            .revoke_api_key("unknown_id".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::NotFound(json!({"error":"no api key of id=unknown_id."}))
+        );
+    }
+}
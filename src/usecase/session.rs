@@ -0,0 +1,209 @@
+use chrono::Local;
+use serde_json::json;
+
+use crate::{
+    domain::{
+        refresh_token::{RefreshToken, RefreshTokenRepository},
+        session::{ClientInfo, Session, SessionRepository},
+        user::User,
+    },
+    middleware::authn::make_jwt,
+    utils::errors::MyError,
+};
+
+pub struct SessionUsecase<S: SessionRepository, R: RefreshTokenRepository> {
+    pub session_repository: S,
+    pub refresh_token_repository: R,
+}
+
+impl<S: SessionRepository + Sync, R: RefreshTokenRepository + Sync> SessionUsecase<S, R> {
+    pub fn new(session_repository: S, refresh_token_repository: R) -> Self {
+        Self {
+            session_repository,
+            refresh_token_repository,
+        }
+    }
+
+    /// active sessions of the user. newest first.
+    pub async fn fetch_sessions(&self, user_id: String) -> Result<Vec<Session>, MyError> {
+        let now = Local::now();
+        let sessions = self
+            .session_repository
+            .fetch_not_revoked_by_user_id(&user_id)
+            .await?
+            .into_iter()
+            .filter(|session| session.is_active(now))
+            .collect::<Vec<Session>>();
+        Ok(sessions)
+    }
+
+    /// sign out one session of the user. session of other user is NotFound.
+    pub async fn revoke_session(&self, user_id: String, session_id: String) -> Result<(), MyError> {
+        match self.session_repository.fetch_one(&session_id).await? {
+            Some(session) if session.user_id == user_id => {}
+            _ => {
+                return Err(MyError::NotFound(json!({
+                    "error": format!("no session of id={}.", session_id)
+                })))
+            }
+        }
+        revoke_session(
+            &self.session_repository,
+            &self.refresh_token_repository,
+            &session_id,
+        )
+        .await
+    }
+
+    /// access token of revoked or unknown session is Unauthorized. checked on every request.
+    pub async fn ensure_not_revoked(&self, session_id: &String) -> Result<(), MyError> {
+        match self.session_repository.fetch_one(session_id).await? {
+            Some(session) if session.revoked_at.is_none() => Ok(()),
+            _ => Err(MyError::Unauthorized(
+                json!({"error":"session has been revoked please sign in again"}),
+            )),
+        }
+    }
+
+    /// sign out every session of the user including the current one.
+    pub async fn revoke_all_sessions(&self, user_id: String) -> Result<(), MyError> {
+        revoke_sessions_of_user(
+            &self.session_repository,
+            &self.refresh_token_repository,
+            &user_id,
+        )
+        .await
+    }
+}
+
+/// start new session of the user. return access token and refresh token of it.
+/// shared by every way of sign in.
+pub async fn issue_tokens<S: SessionRepository + Sync, R: RefreshTokenRepository + Sync>(
+    session_repository: &S,
+    refresh_token_repository: &R,
+    user: &User,
+    client: &ClientInfo,
+) -> Result<(String, String), MyError> {
+    let session = Session::new(user.id.clone(), client);
+    session_repository.save(&session).await?;
+    let token = make_jwt(&user.id, &user.role, &session.id)?;
+    let (refresh_token, raw_refresh_token) = RefreshToken::new(user.id.clone(), Some(session.id))?;
+    refresh_token_repository.save(&refresh_token).await?;
+    Ok((token, raw_refresh_token))
+}
+
+/// revoke the session. its refresh tokens and access tokens stop working.
+pub async fn revoke_session<S: SessionRepository + Sync, R: RefreshTokenRepository + Sync>(
+    session_repository: &S,
+    refresh_token_repository: &R,
+    session_id: &String,
+) -> Result<(), MyError> {
+    let now = Local::now();
+    session_repository.revoke(session_id, now).await?;
+    refresh_token_repository.revoke_family(session_id).await?;
+    Ok(())
+}
+
+/// revoke every session of the user. used on sign out everywhere and password change.
+pub async fn revoke_sessions_of_user<
+    S: SessionRepository + Sync,
+    R: RefreshTokenRepository + Sync,
+>(
+    session_repository: &S,
+    refresh_token_repository: &R,
+    user_id: &String,
+) -> Result<(), MyError> {
+    for session in session_repository
+        .fetch_not_revoked_by_user_id(user_id)
+        .await?
+    {
+        revoke_session(session_repository, refresh_token_repository, &session.id).await?;
+    }
+    refresh_token_repository.revoke_by_user_id(user_id).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+
+mod tests {
+    use crate::repository::{
+        refresh_token_repository::RefreshTokenRepositoryMockImpl,
+        session_repository::SessionRepositoryMockImpl,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fetch_sessions() {
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let session_usecase = SessionUsecase {
+            session_repository: mock_session_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+        };
+        // idle and revoked sessions are not listed.
+        let sessions = session_usecase
+            .fetch_sessions("test_id".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            sessions
+                .iter()
+                .map(|session| session.id.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["test_family_id"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_revoke_session() {
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let session_usecase = SessionUsecase {
+            session_repository: mock_session_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+        };
+        session_usecase
+            .revoke_session("test_id".to_string(), "test_idle_session_id".to_string())
+            .await
+            .unwrap();
+
+        // session of other user.
+        let err = session_usecase
+            .revoke_session(
+                "test_two_factor_id".to_string(),
+                "test_family_id".to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::NotFound(json!({"error":"no session of id=test_family_id."}))
+        );
+    }
+    #[tokio::test]
+    async fn test_ensure_not_revoked() {
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let session_usecase = SessionUsecase {
+            session_repository: mock_session_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+        };
+        session_usecase
+            .ensure_not_revoked(&"test_family_id".to_string())
+            .await
+            .unwrap();
+        for session_id in ["test_revoked_session_id", "unknown_session_id"] {
+            let err = session_usecase
+                .ensure_not_revoked(&session_id.to_string())
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err,
+                MyError::Unauthorized(
+                    json!({"error":"session has been revoked please sign in again"})
+                )
+            );
+        }
+    }
+}
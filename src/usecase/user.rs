@@ -1,120 +1,2171 @@
-use crate::domain::patient::PatientRepository;
-use crate::domain::user::DoctorInChargeRepository;
-use crate::middleware::authn::make_jwt;
-use crate::utils::hash::{hash_password, verify};
-use crate::utils::password::verify_user_password;
+use crate::domain::care_assignment::{fetch_care_team, CareAssignment, CareRole};
+use crate::domain::delegation::{fetch_patients_in_charge, Delegation};
+use crate::domain::emergency_access::EmergencyAccess;
+use crate::domain::login_attempt::{
+    AttemptTarget, LockoutPolicy, LoginAttempt, LoginAttemptRepository,
+};
+use crate::domain::password_policy::{
+    ensure_password_policy, PasswordContext, PasswordHistoryRepository, PasswordPolicy,
+};
+use crate::domain::patient::{Patient, PatientRepository};
+use crate::domain::refresh_token::{split_raw_token, RefreshToken, RefreshTokenRepository};
+use crate::domain::session::{ClientInfo, SessionRepository};
+use crate::domain::user::{
+    DoctorInChargeRepository, RecoveryCode, Role, TwoFactor, TwoFactorRepository,
+};
+use crate::middleware::authn::{decode_two_factor_token, make_jwt, make_two_factor_token};
+use crate::usecase::session::{issue_tokens, revoke_sessions_of_user};
+use crate::utils::hash::{hash_password, needs_rehash, verify, verify_dummy};
+use chrono::{DateTime, Duration, Local};
+use serde_json::json;
 
 use crate::{
     domain::user::{User, UserRepository},
     utils::errors::MyError,
 };
 
-pub struct UserUsecase<U: UserRepository, P: PatientRepository, D: DoctorInChargeRepository> {
+pub struct UserUsecase<
+    U: UserRepository,
+    P: PatientRepository,
+    D: DoctorInChargeRepository,
+    R: RefreshTokenRepository,
+    L: LoginAttemptRepository,
+    T: TwoFactorRepository,
+    H: PasswordHistoryRepository,
+    S: SessionRepository,
+> {
     pub user_repository: U,
     pub patient_repository: P,
     pub doctor_in_charge_repository: D,
+    pub refresh_token_repository: R,
+    pub login_attempt_repository: L,
+    pub two_factor_repository: T,
+    pub password_history_repository: H,
+    pub session_repository: S,
+    pub lockout_policy: LockoutPolicy,
+    pub password_policy: PasswordPolicy,
+}
+
+/// result of password step of sign in.
+#[derive(Debug, PartialEq)]
+pub enum SignInResult {
+    /// access token and refresh token.
+    Authenticated(String, String),
+    /// two factor is enabled. sign_in_two_factor with this token and the code.
+    TwoFactorRequired(String),
 }
 
-impl<U: UserRepository, P: PatientRepository, D: DoctorInChargeRepository> UserUsecase<U, P, D> {
-    pub fn new(user_repository: U, patient_repository: P, doctor_in_charge_repository: D) -> Self {
+impl<
+        U: UserRepository + Sync,
+        P: PatientRepository + Sync,
+        D: DoctorInChargeRepository + Sync,
+        R: RefreshTokenRepository + Sync,
+        L: LoginAttemptRepository,
+        T: TwoFactorRepository,
+        H: PasswordHistoryRepository + Sync,
+        S: SessionRepository + Sync,
+    > UserUsecase<U, P, D, R, L, T, H, S>
+{
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        user_repository: U,
+        patient_repository: P,
+        doctor_in_charge_repository: D,
+        refresh_token_repository: R,
+        login_attempt_repository: L,
+        two_factor_repository: T,
+        password_history_repository: H,
+        session_repository: S,
+        lockout_policy: LockoutPolicy,
+        password_policy: PasswordPolicy,
+    ) -> Self {
         Self {
             user_repository,
             patient_repository,
             doctor_in_charge_repository,
+            refresh_token_repository,
+            login_attempt_repository,
+            two_factor_repository,
+            password_history_repository,
+            session_repository,
+            lockout_policy,
+            password_policy,
         }
     }
 
-    pub async fn sign_up(
+    /// return access token and refresh token, or token for second step if two factor is enabled.
+    /// failures are counted per account and per ip. each failure delays next attempt,
+    /// and too many failures lock the account or ip for a while.
+    pub async fn sign_in(
         &self,
-        name: String,
-        code: Option<String>,
+        code: String,
         raw_password: String,
-    ) -> Result<(User, String), MyError> {
-        verify_user_password(&raw_password);
-        let hashed_password = hash_password(&raw_password)?;
-        let user = User::new(name, code, hashed_password)?;
-        self.user_repository.save(&user).await?;
-        let token = make_jwt(&user.id)?;
+        client: ClientInfo,
+    ) -> Result<SignInResult, MyError> {
+        let now = Local::now();
+        let ip_attempt = match &client.ip {
+            Some(ip) => Some(
+                self.login_attempt_repository
+                    .fetch(&AttemptTarget::Ip, ip)
+                    .await?,
+            ),
+            None => None,
+        };
+        if let Some(retry_after) = ip_attempt
+            .as_ref()
+            .and_then(|ip_attempt| ip_attempt.retry_after(now, &self.lockout_policy))
+        {
+            return Err(too_many_requests(retry_after));
+        }
+        let account_attempt = self
+            .login_attempt_repository
+            .fetch(&AttemptTarget::Account, &code)
+            .await?;
+        if let Some(locked_until) = account_attempt
+            .locked_until
+            .filter(|_| account_attempt.is_locked(now))
+        {
+            return Err(account_locked(locked_until));
+        }
+        if let Some(retry_after) = account_attempt.retry_after(now, &self.lockout_policy) {
+            return Err(too_many_requests(retry_after));
+        }
+
+        // unknown code is told as same as wrong password, and takes as long.
+        let user = match self.user_repository.find_by_code(&code).await {
+            Ok(user) => Some(user),
+            Err(MyError::BadRequest(_)) | Err(MyError::NotFound(_)) => None,
+            Err(err) => return Err(err),
+        };
+        let verified = match &user {
+            Some(user) => verify(&raw_password, &user.hashed_password)?,
+            None => {
+                verify_dummy(&raw_password)?;
+                false
+            }
+        };
+        let user = match user {
+            Some(user) if verified => user,
+            _ => {
+                self.record_ip_failure(ip_attempt, now).await?;
+                let account_attempt = self
+                    .login_attempt_repository
+                    .record_failure(
+                        &AttemptTarget::Account,
+                        &account_attempt.target,
+                        now,
+                        &self.lockout_policy,
+                    )
+                    .await?;
+                if let Some(locked_until) = account_attempt.locked_until {
+                    return Err(account_locked(locked_until));
+                }
+                return Err(MyError::Unauthorized(
+                    json!({"error":"code or password is incorrect"}),
+                ));
+            }
+        };
+        // told only to who knows the password.
+        user.ensure_active()?;
+        // hashes of older algorithm or cost are replaced while raw password is at hand.
+        if needs_rehash(&user.hashed_password) {
+            self.user_repository
+                .update_password(&user.id, &hash_password(&raw_password)?)
+                .await?;
+        }
+        // failures are kept until two factor code is also correct.
+        if let Some(two_factor) = self
+            .two_factor_repository
+            .fetch_by_user_id(&user.id)
+            .await?
+        {
+            if two_factor.enabled {
+                return Ok(SignInResult::TwoFactorRequired(make_two_factor_token(
+                    &user.id,
+                )?));
+            }
+        }
+        self.login_attempt_repository
+            .delete(&AttemptTarget::Account, &code)
+            .await?;
+
+        let (token, raw_refresh_token) = issue_tokens(
+            &self.session_repository,
+            &self.refresh_token_repository,
+            &user,
+            &client,
+        )
+        .await?;
+        Ok(SignInResult::Authenticated(token, raw_refresh_token))
+    }
+
+    /// second step of sign in. code is TOTP code or one of recovery codes.
+    /// failures are counted to the account like password.
+    pub async fn sign_in_two_factor(
+        &self,
+        two_factor_token: String,
+        code: String,
+        client: ClientInfo,
+    ) -> Result<(String, String), MyError> {
+        let now = Local::now();
+        let user_id = decode_two_factor_token(&two_factor_token)?;
+        let user = self.user_repository.fetch_one(&user_id).await?;
+        user.ensure_active()?;
+        let account_attempt = self
+            .login_attempt_repository
+            .fetch(&AttemptTarget::Account, &user.code)
+            .await?;
+        if let Some(locked_until) = account_attempt
+            .locked_until
+            .filter(|_| account_attempt.is_locked(now))
+        {
+            return Err(account_locked(locked_until));
+        }
+        if let Some(retry_after) = account_attempt.retry_after(now, &self.lockout_policy) {
+            return Err(too_many_requests(retry_after));
+        }
+        let two_factor = match self
+            .two_factor_repository
+            .fetch_by_user_id(&user.id)
+            .await?
+        {
+            Some(two_factor) if two_factor.enabled => two_factor,
+            _ => {
+                return Err(MyError::Unauthorized(
+                    json!({"error":"two factor is not enabled"}),
+                ))
+            }
+        };
+
+        // claim the code, so that another sign in at once can not reuse it.
+        let accepted = if let Some(step) = two_factor.verify(&code, now) {
+            self.two_factor_repository
+                .update_last_used_step(&user.id, step)
+                .await?
+        } else if let Some(recovery_code) = self
+            .two_factor_repository
+            .fetch_recovery_codes(&user.id)
+            .await?
+            .into_iter()
+            .find(|recovery_code| recovery_code.matches(&code))
+        {
+            self.two_factor_repository
+                .use_recovery_code(&recovery_code.id)
+                .await?
+        } else {
+            false
+        };
+        if !accepted {
+            let account_attempt = self
+                .login_attempt_repository
+                .record_failure(
+                    &AttemptTarget::Account,
+                    &account_attempt.target,
+                    now,
+                    &self.lockout_policy,
+                )
+                .await?;
+            if let Some(locked_until) = account_attempt.locked_until {
+                return Err(account_locked(locked_until));
+            }
+            return Err(MyError::Unauthorized(
+                json!({"error":"two factor code is incorrect"}),
+            ));
+        }
+        self.login_attempt_repository
+            .delete(&AttemptTarget::Account, &user.code)
+            .await?;
+
+        issue_tokens(
+            &self.session_repository,
+            &self.refresh_token_repository,
+            &user,
+            &client,
+        )
+        .await
+    }
 
-        Ok((user, token))
+    /// start TOTP enrolment. return secret and otpauth uri for authenticator app.
+    /// enrolment not confirmed yet is replaced with new secret.
+    pub async fn enroll_two_factor(&self, user_id: String) -> Result<(String, String), MyError> {
+        let user = self.user_repository.fetch_one(&user_id).await?;
+        if let Some(two_factor) = self
+            .two_factor_repository
+            .fetch_by_user_id(&user.id)
+            .await?
+        {
+            if two_factor.enabled {
+                return Err(MyError::BadRequest(
+                    json!({"error":"two factor is already enabled"}),
+                ));
+            }
+        }
+        let two_factor = TwoFactor::new(user.id.clone());
+        self.two_factor_repository.save(&two_factor).await?;
+        Ok((
+            two_factor.secret.clone(),
+            two_factor.otpauth_uri(&user.code),
+        ))
+    }
+
+    /// enable two factor with the first code from authenticator app.
+    /// return recovery codes. they are shown only once.
+    pub async fn confirm_two_factor(
+        &self,
+        user_id: String,
+        code: String,
+    ) -> Result<Vec<String>, MyError> {
+        let two_factor = match self
+            .two_factor_repository
+            .fetch_by_user_id(&user_id)
+            .await?
+        {
+            Some(two_factor) if !two_factor.enabled => two_factor,
+            Some(_) => {
+                return Err(MyError::BadRequest(
+                    json!({"error":"two factor is already enabled"}),
+                ))
+            }
+            None => {
+                return Err(MyError::BadRequest(
+                    json!({"error":"two factor is not enrolled"}),
+                ))
+            }
+        };
+        let step = match two_factor.verify(&code, Local::now()) {
+            Some(step) => step,
+            None => {
+                return Err(MyError::BadRequest(
+                    json!({"error":"two factor code is incorrect"}),
+                ))
+            }
+        };
+        let (recovery_codes, raw_recovery_codes) = RecoveryCode::generate(&user_id)?;
+        self.two_factor_repository
+            .save_recovery_codes(&user_id, &recovery_codes)
+            .await?;
+        self.two_factor_repository
+            .save(&TwoFactor::from(
+                two_factor.user_id,
+                two_factor.secret,
+                true,
+                Some(step),
+            ))
+            .await?;
+        Ok(raw_recovery_codes)
+    }
+
+    /// rotate refresh token. return new access token and refresh token.
+    /// reuse of rotated refresh token revokes the whole family.
+    /// client and time are recorded to the session.
+    pub async fn refresh(
+        &self,
+        raw_refresh_token: String,
+        client: ClientInfo,
+    ) -> Result<(String, String), MyError> {
+        let (id, secret) = split_raw_token(&raw_refresh_token)?;
+        let refresh_token = self.refresh_token_repository.fetch_one(&id).await?;
+        if !verify(&secret, &refresh_token.hashed_token)? {
+            return Err(MyError::Unauthorized(
+                json!({"error":"refresh token is invalid"}),
+            ));
+        }
+        if refresh_token.revoked {
+            self.refresh_token_repository
+                .revoke_family(&refresh_token.family_id)
+                .await?;
+            return Err(refresh_token_reused());
+        }
+        let now = Local::now();
+        if refresh_token.is_expired(now) {
+            return Err(MyError::Unauthorized(
+                json!({"error":"refresh token has expired please sign in again"}),
+            ));
+        }
+        // family of refresh token is the session.
+        match self
+            .session_repository
+            .fetch_one(&refresh_token.family_id)
+            .await?
+        {
+            Some(session) if session.revoked_at.is_none() => {}
+            _ => {
+                return Err(MyError::Unauthorized(
+                    json!({"error":"session has been revoked please sign in again"}),
+                ))
+            }
+        }
+
+        // another refresh may have rotated the same token meanwhile.
+        if !self
+            .refresh_token_repository
+            .revoke(&refresh_token.id)
+            .await?
+        {
+            self.refresh_token_repository
+                .revoke_family(&refresh_token.family_id)
+                .await?;
+            return Err(refresh_token_reused());
+        }
+        // role may be changed after sign in.
+        let user = self
+            .user_repository
+            .fetch_one(&refresh_token.user_id)
+            .await?;
+        user.ensure_active()?;
+        let token = make_jwt(&user.id, &user.role, &refresh_token.family_id)?;
+        self.session_repository
+            .touch(&refresh_token.family_id, &client, now)
+            .await?;
+        let (new_refresh_token, raw_refresh_token) =
+            RefreshToken::new(refresh_token.user_id, Some(refresh_token.family_id))?;
+        self.refresh_token_repository
+            .save(&new_refresh_token)
+            .await?;
+        Ok((token, raw_refresh_token))
+    }
+
+    /// change password of signed in user. current password is required.
+    /// wrong current password is counted as failed sign in of the account.
+    /// every session of the user is signed out and new tokens are returned.
+    pub async fn change_password(
+        &self,
+        user_id: String,
+        current_password: String,
+        new_password: String,
+        client: ClientInfo,
+    ) -> Result<(String, String), MyError> {
+        let now = Local::now();
+        let user = self.user_repository.fetch_one(&user_id).await?;
+        let account_attempt = self
+            .login_attempt_repository
+            .fetch(&AttemptTarget::Account, &user.code)
+            .await?;
+        if let Some(locked_until) = account_attempt
+            .locked_until
+            .filter(|_| account_attempt.is_locked(now))
+        {
+            return Err(account_locked(locked_until));
+        }
+        if let Some(retry_after) = account_attempt.retry_after(now, &self.lockout_policy) {
+            return Err(too_many_requests(retry_after));
+        }
+        if !verify(&current_password, &user.hashed_password)? {
+            let account_attempt = self
+                .login_attempt_repository
+                .record_failure(
+                    &AttemptTarget::Account,
+                    &account_attempt.target,
+                    now,
+                    &self.lockout_policy,
+                )
+                .await?;
+            if let Some(locked_until) = account_attempt.locked_until {
+                return Err(account_locked(locked_until));
+            }
+            return Err(MyError::Unauthorized(
+                json!({"error":"current password is incorrect"}),
+            ));
+        }
+        let context = PasswordContext {
+            name: &user.name,
+            code: &user.code,
+        };
+        ensure_password_policy(
+            &self.password_policy,
+            &self.password_history_repository,
+            &new_password,
+            &context,
+            Some(&user.id),
+        )
+        .await?;
+
+        let hashed_password = hash_password(&new_password)?;
+        self.user_repository
+            .update_password(&user.id, &hashed_password)
+            .await?;
+        self.password_history_repository
+            .save(&user.id, &hashed_password)
+            .await?;
+        revoke_sessions_of_user(
+            &self.session_repository,
+            &self.refresh_token_repository,
+            &user.id,
+        )
+        .await?;
+        issue_tokens(
+            &self.session_repository,
+            &self.refresh_token_repository,
+            &user,
+            &client,
+        )
+        .await
     }
 
-    pub async fn sign_in(&self, code: String, raw_password: String) -> Result<String, MyError> {
+    /// role is in access token, so that every session of the user is signed out
+    /// and the user signs in again with the new role.
+    pub async fn change_role(&self, code: String, role: Role) -> Result<(), MyError> {
         let user = self.user_repository.find_by_code(&code).await?;
-        let _ = verify(&raw_password, &user.hashed_password);
-        let token = make_jwt(&user.id)?;
-        Ok(token)
+        self.user_repository.update_role(&code, &role).await?;
+        revoke_sessions_of_user(
+            &self.session_repository,
+            &self.refresh_token_repository,
+            &user.id,
+        )
+        .await?;
+        Ok(())
     }
 
-    pub async fn assign(&self, user_id: String, patient_code: String) -> Result<(), MyError> {
+    pub async fn fetch_me(&self, user_id: String) -> Result<User, MyError> {
+        self.user_repository.fetch_one(&user_id).await
+    }
+
+    pub async fn update_name(&self, user_id: String, name: String) -> Result<User, MyError> {
+        let mut user = self.user_repository.fetch_one(&user_id).await?;
+        user.change_name(name)?;
+        self.user_repository
+            .update_name(&user.id, &user.name)
+            .await?;
+        Ok(user)
+    }
+
+    pub async fn fetch_users(&self) -> Result<Vec<User>, MyError> {
+        self.user_repository.fetch_all().await
+    }
+
+    /// deactivated user can not sign in. every session of the user is signed out.
+    /// user can not deactivate oneself, so that the last administrator is not locked out by mistake.
+    pub async fn deactivate(&self, operator_id: String, code: String) -> Result<(), MyError> {
+        let user = self.user_repository.find_by_code(&code).await?;
+        if user.id == operator_id {
+            return Err(MyError::BadRequest(
+                json!({"error":"can not deactivate yourself"}),
+            ));
+        }
+        self.user_repository.update_active(&code, false).await?;
+        revoke_sessions_of_user(
+            &self.session_repository,
+            &self.refresh_token_repository,
+            &user.id,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// user signs in again after reactivated. sessions signed out on deactivation are not restored.
+    pub async fn reactivate(&self, code: String) -> Result<(), MyError> {
+        // user code check
+        self.user_repository.find_by_code(&code).await?;
+        self.user_repository.update_active(&code, true).await?;
+        Ok(())
+    }
+
+    /// forget failed sign in attempts of the user and unlock the account.
+    pub async fn unlock(&self, code: String) -> Result<(), MyError> {
+        // user code check
+        self.user_repository.find_by_code(&code).await?;
+        self.login_attempt_repository
+            .delete(&AttemptTarget::Account, &code)
+            .await?;
+        Ok(())
+    }
+
+    /// put the user of code in charge of the patient as care_role. the caller if code is None.
+    /// starts now if starts_at is None, and lasts until unassigned if ends_at is None.
+    /// if the user is already in charge during the period,Conflict. patient is archived,BadRequest.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn assign(
+        &self,
+        user_id: String,
+        code: Option<String>,
+        patient_code: String,
+        care_role: CareRole,
+        starts_at: Option<DateTime<Local>>,
+        ends_at: Option<DateTime<Local>>,
+        reason: Option<String>,
+    ) -> Result<CareAssignment, MyError> {
+        // patient_code check
+        self.patient_repository
+            .fetch_by_code(&patient_code)
+            .await?
+            .ensure_not_archived()?;
+        let user_id = match code {
+            Some(code) => self.find_assignee(&code).await?,
+            None => user_id,
+        };
+        let care_assignment =
+            CareAssignment::new(user_id, patient_code, care_role, starts_at, ends_at, reason)?;
+        self.doctor_in_charge_repository
+            .save(&care_assignment)
+            .await?;
+        Ok(care_assignment)
+    }
+
+    /// end the assignment of the user of code to the patient now. the caller if code is None.
+    /// the assignment is kept as care history.
+    pub async fn unassign(
+        &self,
+        user_id: String,
+        code: Option<String>,
+        patient_code: String,
+    ) -> Result<(), MyError> {
         // patient_code check
         self.patient_repository.fetch_by_code(&patient_code).await?;
+        let user_id = match code {
+            Some(code) => self.user_repository.find_by_code(&code).await?.id,
+            None => user_id,
+        };
+        if !self
+            .doctor_in_charge_repository
+            .end(&user_id, &patient_code, Local::now())
+            .await?
+        {
+            return Err(not_in_charge(&patient_code));
+        }
+        Ok(())
+    }
+
+    /// hand the patient over from one user to another at once.
+    /// the new assignment takes over care_role and ends_at of the current one.
+    pub async fn transfer(
+        &self,
+        from_code: String,
+        to_code: String,
+        patient_code: String,
+        reason: Option<String>,
+    ) -> Result<CareAssignment, MyError> {
+        if from_code == to_code {
+            return Err(MyError::BadRequest(
+                json!({"error":"can not transfer to the same user"}),
+            ));
+        }
+        // patient_code check
+        self.patient_repository.fetch_by_code(&patient_code).await?;
+        let from_user = self.user_repository.find_by_code(&from_code).await?;
+        let to_user_id = self.find_assignee(&to_code).await?;
+        let now = Local::now();
+        let from_assignment = self
+            .doctor_in_charge_repository
+            .fetch_by_patient_code(&patient_code)
+            .await?
+            .into_iter()
+            .find(|care_assignment| {
+                care_assignment.user_id == from_user.id && care_assignment.is_active(now)
+            })
+            .ok_or_else(|| not_in_charge(&patient_code))?;
+        let care_assignment = CareAssignment::new(
+            to_user_id,
+            patient_code.clone(),
+            from_assignment.care_role,
+            Some(now),
+            from_assignment.ends_at,
+            reason,
+        )?;
+        if !self
+            .doctor_in_charge_repository
+            .transfer(&from_user.id, &care_assignment)
+            .await?
+        {
+            return Err(not_in_charge(&patient_code));
+        }
+        Ok(care_assignment)
+    }
+
+    /// care team of the patient with their assignments, newest first.
+    /// ended assignments are included only if include_history.
+    pub async fn fetch_care_team(
+        &self,
+        patient_code: String,
+        include_history: bool,
+    ) -> Result<Vec<(User, CareAssignment)>, MyError> {
+        // patient_code check
+        self.patient_repository.fetch_by_code(&patient_code).await?;
+        fetch_care_team(
+            &self.user_repository,
+            &self.doctor_in_charge_repository,
+            &patient_code,
+            include_history,
+            Local::now(),
+        )
+        .await
+    }
+
+    /// patients the user of code is in charge of at the moment. the caller's if code is None.
+    /// patients delegated to the user follow with the delegation.
+    /// archived patients are left out unless include_archived.
+    pub async fn fetch_assigned_patients(
+        &self,
+        user_id: String,
+        code: Option<String>,
+        include_archived: bool,
+    ) -> Result<Vec<(Patient, Option<Delegation>)>, MyError> {
+        let user_id = match code {
+            Some(code) => self.user_repository.find_by_code(&code).await?.id,
+            None => user_id,
+        };
+        fetch_patients_in_charge(
+            &self.patient_repository,
+            &self.doctor_in_charge_repository,
+            &user_id,
+            Local::now(),
+            include_archived,
+        )
+        .await
+    }
+
+    /// lend the user's access to the patients to the user of to_code until ends_at.
+    /// every patient the user is in charge of while the delegation lasts if patient_codes is None.
+    pub async fn delegate(
+        &self,
+        user_id: String,
+        to_code: String,
+        patient_codes: Option<Vec<String>>,
+        starts_at: Option<DateTime<Local>>,
+        ends_at: DateTime<Local>,
+    ) -> Result<Delegation, MyError> {
+        let delegate_id = self.find_assignee(&to_code).await?;
+        let delegation = Delegation::new(user_id, delegate_id, patient_codes, starts_at, ends_at)?;
+        let now = Local::now();
+        for patient_code in delegation.patient_codes.iter().flatten() {
+            // patient_code check
+            self.patient_repository.fetch_by_code(patient_code).await?;
+            if !self
+                .doctor_in_charge_repository
+                .exists(&delegation.delegator_id, patient_code, now)
+                .await?
+            {
+                return Err(not_in_charge(patient_code));
+            }
+        }
+        self.doctor_in_charge_repository
+            .save_delegation(&delegation)
+            .await?;
+        Ok(delegation)
+    }
+
+    /// delegations the user gave or received, newest first.
+    pub async fn fetch_delegations(&self, user_id: String) -> Result<Vec<Delegation>, MyError> {
+        self.doctor_in_charge_repository
+            .fetch_delegations(&user_id)
+            .await
+    }
+
+    /// either the delegator or the delegate can revoke the delegation.
+    pub async fn revoke_delegation(&self, user_id: String, id: String) -> Result<(), MyError> {
+        let delegation = match self
+            .doctor_in_charge_repository
+            .fetch_delegation(&id)
+            .await?
+        {
+            Some(delegation)
+                if delegation.delegator_id == user_id || delegation.delegate_id == user_id =>
+            {
+                delegation
+            }
+            _ => {
+                return Err(MyError::NotFound(json!({
+                    "error": format!("no delegation of id={}.", id)
+                })))
+            }
+        };
+        if delegation.is_revoked() {
+            return Err(MyError::BadRequest(
+                json!({"error":"delegation is already revoked"}),
+            ));
+        }
         self.doctor_in_charge_repository
-            .save(&user_id, &patient_code)
+            .revoke_delegation(&id, Local::now())
             .await?;
         Ok(())
     }
+
+    /// emergency accesses for administrator to review, newest first.
+    pub async fn fetch_emergency_accesses(
+        &self,
+        unacknowledged_only: bool,
+    ) -> Result<Vec<EmergencyAccess>, MyError> {
+        self.doctor_in_charge_repository
+            .fetch_emergency_accesses(unacknowledged_only)
+            .await
+    }
+
+    /// mark emergency access as reviewed by the administrator.
+    pub async fn acknowledge_emergency_access(
+        &self,
+        operator_id: String,
+        id: String,
+    ) -> Result<(), MyError> {
+        match self
+            .doctor_in_charge_repository
+            .fetch_emergency_access(&id)
+            .await?
+        {
+            Some(emergency_access) if emergency_access.is_acknowledged() => {
+                return Err(MyError::BadRequest(
+                    json!({"error":"emergency access is already acknowledged"}),
+                ))
+            }
+            Some(_) => {}
+            None => {
+                return Err(MyError::NotFound(json!({
+                    "error": format!("no emergency access of id={}.", id)
+                })))
+            }
+        }
+        self.doctor_in_charge_repository
+            .acknowledge_emergency_access(&id, &operator_id, Local::now())
+            .await?;
+        Ok(())
+    }
+
+    /// id of the user of code. deactivated user can not take charge of patients.
+    async fn find_assignee(&self, code: &String) -> Result<String, MyError> {
+        let user = self.user_repository.find_by_code(code).await?;
+        if !user.active {
+            return Err(MyError::BadRequest(json!({
+                "error": format!("user code={} is deactivated.", code)
+            })));
+        }
+        Ok(user.id)
+    }
+
+    /// count up failure of the ip if it is known.
+    async fn record_ip_failure(
+        &self,
+        login_attempt: Option<LoginAttempt>,
+        now: DateTime<Local>,
+    ) -> Result<(), MyError> {
+        if let Some(login_attempt) = login_attempt {
+            self.login_attempt_repository
+                .record_failure(
+                    &login_attempt.target_type,
+                    &login_attempt.target,
+                    now,
+                    &self.lockout_policy,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn account_locked(locked_until: DateTime<Local>) -> MyError {
+    MyError::AccountLocked(json!({
+        "error": "account is locked due to too many failed sign in attempts",
+        "locked_until": locked_until.to_rfc3339(),
+    }))
+}
+
+fn not_in_charge(patient_code: &String) -> MyError {
+    MyError::NotFound(json!({
+        "error": format!("not in charge of patient code={}.", patient_code)
+    }))
+}
+
+fn too_many_requests(retry_after: Duration) -> MyError {
+    MyError::TooManyRequests(json!({
+        "error": "too many sign in attempts please retry later",
+        "retry_after": retry_after.num_seconds().max(1),
+    }))
+}
+
+fn refresh_token_reused() -> MyError {
+    MyError::Unauthorized(json!({"error":"refresh token has been revoked please sign in again"}))
 }
 
 #[cfg(test)]
 
 mod tests {
+    use crate::middleware::keyring::{init_keyring, Keyring, SigningKey};
     use crate::repository::{
-        patient_repository::PatientRepositoryMockImpl,
-        user_repository::{DoctorInChargeRepositoryMockImpl, UserRepositoryMockImpl},
+        login_attempt_repository::{
+            LoginAttemptRepositoryMockImpl, TEST_LOCKED_CODE, TEST_LOCKED_IP,
+        },
+        password_history_repository::{PasswordHistoryRepositoryMockImpl, TEST_OLD_PASSWORD},
+        patient_repository::{get_patients, PatientRepositoryMockImpl},
+        refresh_token_repository::{RefreshTokenRepositoryMockImpl, TEST_REFRESH_TOKEN_SECRET},
+        session_repository::SessionRepositoryMockImpl,
+        user_repository::{
+            DoctorInChargeRepositoryMockImpl, TwoFactorRepositoryMockImpl, UserRepositoryMockImpl,
+            TEST_PASSWORD, TEST_RECOVERY_CODE, TEST_TOTP_SECRET,
+        },
     };
 
+    use crate::utils::totp::{time_step, totp_code};
+
     use super::*;
 
+    fn set_up_keyring() {
+        init_keyring(Keyring::new(
+            SigningKey::hmac("test_kid".to_string(), "test_secret".to_string()),
+            vec![],
+        ));
+    }
+
     #[tokio::test]
-    async fn test_sign_up() {
-        let name = "test_name".to_string();
+    async fn test_sign_in() {
+        set_up_keyring();
         let code = "test_code".to_string();
-        let raw_password = "test_password".to_string();
+        let raw_password = TEST_PASSWORD.to_string();
         let mock_user_repository = UserRepositoryMockImpl {};
         let mock_patient_repository = PatientRepositoryMockImpl {};
         let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
         let user_usecase = UserUsecase {
             user_repository: mock_user_repository,
             patient_repository: mock_patient_repository,
             doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
         };
-        let (user, token) = user_usecase
-            .sign_up(name, Some(code), raw_password)
+        let result = user_usecase
+            .sign_in(
+                code,
+                raw_password,
+                ClientInfo {
+                    ip: Some("192.0.2.2".to_string()),
+                    user_agent: Some("test_user_agent".to_string()),
+                },
+            )
             .await
             .unwrap();
+        assert!(matches!(result, SignInResult::Authenticated(_, _)));
     }
 
     #[tokio::test]
-    async fn test_sign_in() {
-        let code = "test_code".to_string();
-        let raw_password = "test_password".to_string();
+    async fn test_sign_in_two_factor() {
+        set_up_keyring();
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let result = user_usecase
+            .sign_in(
+                "test_two_factor_code".to_string(),
+                TEST_PASSWORD.to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+        let two_factor_token = match result {
+            SignInResult::TwoFactorRequired(two_factor_token) => two_factor_token,
+            SignInResult::Authenticated(_, _) => panic!("two factor is skipped"),
+        };
+        // two factor token is not access token.
+        assert!(crate::middleware::authn::decode(&two_factor_token).is_err());
+
+        let code = totp_code(TEST_TOTP_SECRET, time_step(Local::now().timestamp())).unwrap();
+        user_usecase
+            .sign_in_two_factor(two_factor_token.clone(), code, ClientInfo::default())
+            .await
+            .unwrap();
+        user_usecase
+            .sign_in_two_factor(
+                two_factor_token.clone(),
+                TEST_RECOVERY_CODE.to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+        let err = user_usecase
+            .sign_in_two_factor(
+                two_factor_token,
+                "wrong_code".to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Unauthorized(json!({"error":"two factor code is incorrect"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_enroll_two_factor() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let (secret, otpauth_uri) = user_usecase
+            .enroll_two_factor("test_id".to_string())
+            .await
+            .unwrap();
+        assert!(otpauth_uri.contains(&secret));
+
+        let err = user_usecase
+            .enroll_two_factor("test_two_factor_id".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"two factor is already enabled"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_confirm_two_factor() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let code = totp_code(TEST_TOTP_SECRET, time_step(Local::now().timestamp())).unwrap();
+        let recovery_codes = user_usecase
+            .confirm_two_factor("test_id".to_string(), code)
+            .await
+            .unwrap();
+        assert_eq!(recovery_codes.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_sign_in_wrong_password_failed() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let err = user_usecase
+            .sign_in(
+                "test_code".to_string(),
+                "wrong_password".to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Unauthorized(json!({"error":"code or password is incorrect"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sign_in_unknown_code_failed() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        // same as wrong password.
+        let err = user_usecase
+            .sign_in(
+                "not_exist".to_string(),
+                TEST_PASSWORD.to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Unauthorized(json!({"error":"code or password is incorrect"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sign_in_locked_account_failed() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let err = user_usecase
+            .sign_in(
+                TEST_LOCKED_CODE.to_string(),
+                TEST_PASSWORD.to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, MyError::AccountLocked(_)));
+    }
+
+    #[tokio::test]
+    async fn test_sign_in_locked_ip_failed() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let err = user_usecase
+            .sign_in(
+                "test_code".to_string(),
+                TEST_PASSWORD.to_string(),
+                ClientInfo {
+                    ip: Some(TEST_LOCKED_IP.to_string()),
+                    user_agent: None,
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, MyError::TooManyRequests(_)));
+    }
+
+    #[tokio::test]
+    async fn test_unlock() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        user_usecase.unlock("test_code".to_string()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_refresh() {
+        set_up_keyring();
+        let raw_refresh_token = format!("test_refresh_token_id_1.{}", TEST_REFRESH_TOKEN_SECRET);
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let (token, refresh_token) = user_usecase
+            .refresh(raw_refresh_token, ClientInfo::default())
+            .await
+            .unwrap();
+        assert_ne!(
+            refresh_token,
+            format!("test_refresh_token_id_1.{}", TEST_REFRESH_TOKEN_SECRET)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_reused_token_failed() {
+        let raw_refresh_token = format!("test_refresh_token_id_2.{}", TEST_REFRESH_TOKEN_SECRET);
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let err = user_usecase
+            .refresh(raw_refresh_token, ClientInfo::default())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Unauthorized(
+                json!({"error":"refresh token has been revoked please sign in again"})
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_concurrently_reused_token_failed() {
+        let raw_refresh_token = format!("test_refresh_token_id_4.{}", TEST_REFRESH_TOKEN_SECRET);
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        // another refresh has rotated the token after it was fetched.
+        let err = user_usecase
+            .refresh(raw_refresh_token, ClientInfo::default())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Unauthorized(
+                json!({"error":"refresh token has been revoked please sign in again"})
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_revoked_session_failed() {
+        let raw_refresh_token = format!("test_refresh_token_id_3.{}", TEST_REFRESH_TOKEN_SECRET);
         let mock_user_repository = UserRepositoryMockImpl {};
         let mock_patient_repository = PatientRepositoryMockImpl {};
         let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
         let user_usecase = UserUsecase {
             user_repository: mock_user_repository,
             patient_repository: mock_patient_repository,
             doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
         };
-        let token = user_usecase.sign_in(code, raw_password).await.unwrap();
+        let err = user_usecase
+            .refresh(raw_refresh_token, ClientInfo::default())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Unauthorized(json!({"error":"session has been revoked please sign in again"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_change_password() {
+        set_up_keyring();
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        user_usecase
+            .change_password(
+                "test_id".to_string(),
+                TEST_PASSWORD.to_string(),
+                "correct-horse-42".to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_change_password_failed() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let err = user_usecase
+            .change_password(
+                "test_id".to_string(),
+                "wrong_password".to_string(),
+                "correct-horse-42".to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Unauthorized(json!({"error":"current password is incorrect"}))
+        );
+        // password in history.
+        let err = user_usecase
+            .change_password(
+                "test_id".to_string(),
+                TEST_PASSWORD.to_string(),
+                TEST_OLD_PASSWORD.to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({
+                "error": "password does not satisfy the policy",
+                "violations": ["password must differ from the last 5 passwords"],
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sign_in_deactivated_failed() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let err = user_usecase
+            .sign_in(
+                "test_deactivated_code".to_string(),
+                TEST_PASSWORD.to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Forbidden(json!({"error":"account is deactivated"}))
+        );
+        // wrong password is not told whether the account is deactivated.
+        let err = user_usecase
+            .sign_in(
+                "test_deactivated_code".to_string(),
+                "wrong_password".to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Unauthorized(json!({"error":"code or password is incorrect"}))
+        );
+        let err = user_usecase
+            .sign_in(
+                "test_pending_code".to_string(),
+                TEST_PASSWORD.to_string(),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::Forbidden(json!({"error":"account is waiting for approval"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_name() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let user = user_usecase
+            .update_name("test_id".to_string(), "new_name".to_string())
+            .await
+            .unwrap();
+        assert_eq!(user.name, "new_name");
+        assert_eq!(user.code, "test_code");
+    }
+
+    #[tokio::test]
+    async fn test_deactivate() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        user_usecase
+            .deactivate("test_two_factor_id".to_string(), "test_code".to_string())
+            .await
+            .unwrap();
+        let err = user_usecase
+            .deactivate("test_id".to_string(), "test_code".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"can not deactivate yourself"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_change_role() {
+        let test_code = "test_code".to_string();
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        user_usecase
+            .change_role(test_code, Role::Nurse)
+            .await
+            .unwrap();
     }
 
     #[tokio::test]
     async fn test_assign() {
         let test_user_id = "test_user_id".to_string();
-        let test_patient_code = "test_patient_code".to_string();
+        let test_patient_code = "a".to_string();
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let care_assignment = user_usecase
+            .assign(
+                test_user_id.clone(),
+                None,
+                test_patient_code.clone(),
+                CareRole::Attending,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(care_assignment.is_active(Local::now()));
+        let care_assignment = user_usecase
+            .assign(
+                test_user_id.clone(),
+                Some("test_two_factor_code".to_string()),
+                test_patient_code.clone(),
+                CareRole::Nurse,
+                None,
+                Some(Local::now() + Duration::days(3)),
+                Some("night shift".to_string()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(care_assignment.user_id, "test_two_factor_id");
+        assert_eq!(care_assignment.care_role, CareRole::Nurse);
+        // test_user_id_2 can be assigned to "a" again after the previous assignment ended.
+        user_usecase
+            .assign(
+                "test_user_id_2".to_string(),
+                None,
+                "a".to_string(),
+                CareRole::Resident,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        // test_id takes over "b" after the consultation ends.
+        user_usecase
+            .assign(
+                "test_id".to_string(),
+                None,
+                "b".to_string(),
+                CareRole::Attending,
+                Some(Local::now() + Duration::days(7)),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_assign_failed() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let now = Local::now();
+        for (user_id, code, patient_code, starts_at, ends_at, expected) in [
+            // test_user_id_1 is in charge of "a" already.
+            (
+                "test_user_id_1",
+                None,
+                "a",
+                None,
+                None,
+                MyError::Conflict(json!({"error":"already in charge of patient code=a."})),
+            ),
+            // test_id is consulting "b" until 7 days later.
+            (
+                "test_id",
+                None,
+                "b",
+                Some(now + Duration::days(3)),
+                None,
+                MyError::Conflict(json!({"error":"already in charge of patient code=b."})),
+            ),
+            (
+                "test_id",
+                Some("test_deactivated_code"),
+                "a",
+                None,
+                None,
+                MyError::BadRequest(
+                    json!({"error":"user code=test_deactivated_code is deactivated."}),
+                ),
+            ),
+            (
+                "test_id",
+                None,
+                "a",
+                Some(now),
+                Some(now - Duration::days(1)),
+                MyError::BadRequest(json!({"error":"ends_at must be after starts_at"})),
+            ),
+        ] {
+            let err = user_usecase
+                .assign(
+                    user_id.to_string(),
+                    code.map(|code| code.to_string()),
+                    patient_code.to_string(),
+                    CareRole::Attending,
+                    starts_at,
+                    ends_at,
+                    None,
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(err, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unassign() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        user_usecase
+            .unassign(
+                "test_user_id".to_string(),
+                Some("test_code".to_string()),
+                "b".to_string(),
+            )
+            .await
+            .unwrap();
+        let err = user_usecase
+            .unassign("test_id".to_string(), None, "a".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::NotFound(json!({"error":"not in charge of patient code=a."}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfer() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        // the new assignment takes over the consultation of test_id.
+        let care_assignment = user_usecase
+            .transfer(
+                "test_code".to_string(),
+                "test_pending_code".to_string(),
+                "b".to_string(),
+                Some("leave".to_string()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(care_assignment.user_id, "test_pending_id");
+        assert_eq!(care_assignment.care_role, CareRole::Consulting);
+        assert!(care_assignment.ends_at.is_some());
+        for (from_code, to_code, expected) in [
+            (
+                "test_two_factor_code",
+                "test_code",
+                MyError::Conflict(json!({"error":"already in charge of patient code=b."})),
+            ),
+            // test_deactivated_id was in charge of "b" until a month ago.
+            (
+                "test_deactivated_code",
+                "test_pending_code",
+                MyError::NotFound(json!({"error":"not in charge of patient code=b."})),
+            ),
+            (
+                "test_code",
+                "test_deactivated_code",
+                MyError::BadRequest(
+                    json!({"error":"user code=test_deactivated_code is deactivated."}),
+                ),
+            ),
+            (
+                "test_code",
+                "test_code",
+                MyError::BadRequest(json!({"error":"can not transfer to the same user"})),
+            ),
+        ] {
+            let err = user_usecase
+                .transfer(
+                    from_code.to_string(),
+                    to_code.to_string(),
+                    "b".to_string(),
+                    None,
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(err, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_care_team() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let care_team = user_usecase
+            .fetch_care_team("b".to_string(), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            care_team
+                .into_iter()
+                .map(|(user, care_assignment)| (user.id, care_assignment.care_role))
+                .collect::<Vec<(String, CareRole)>>(),
+            vec![
+                ("test_id".to_string(), CareRole::Consulting),
+                ("test_two_factor_id".to_string(), CareRole::Nurse),
+            ]
+        );
+        let care_team = user_usecase
+            .fetch_care_team("b".to_string(), true)
+            .await
+            .unwrap();
+        assert_eq!(care_team.len(), 3);
+        assert_eq!(care_team[2].0.id, "test_deactivated_id");
+        assert_eq!(care_team[2].1.reason, Some("retired".to_string()));
+        let patients = user_usecase
+            .fetch_assigned_patients(
+                "test_user_id".to_string(),
+                Some("test_code".to_string()),
+                false,
+            )
+            .await
+            .unwrap();
+        // "a" is delegated by test_user_id_1.
+        assert_eq!(
+            patients
+                .into_iter()
+                .map(|(patient, delegation)| (patient.code, delegation.is_some()))
+                .collect::<Vec<(String, bool)>>(),
+            vec![("b".to_string(), false), ("a".to_string(), true)]
+        );
+        let patients = user_usecase
+            .fetch_assigned_patients("test_user_id_1".to_string(), None, false)
+            .await
+            .unwrap();
+        assert_eq!(patients, vec![(get_patients()[0].clone(), None)]);
+        // archived "c" of test_user_id_3 is shown only if include_archived.
+        for (include_archived, expected) in [(false, vec!["b"]), (true, vec!["c", "b"])] {
+            let patients = user_usecase
+                .fetch_assigned_patients("test_user_id_3".to_string(), None, include_archived)
+                .await
+                .unwrap();
+            assert_eq!(
+                patients
+                    .iter()
+                    .map(|(patient, _)| patient.code.as_str())
+                    .collect::<Vec<&str>>(),
+                expected
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delegate() {
         let mock_user_repository = UserRepositoryMockImpl {};
         let mock_patient_repository = PatientRepositoryMockImpl {};
         let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
         let user_usecase = UserUsecase {
             user_repository: mock_user_repository,
             patient_repository: mock_patient_repository,
             doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
         };
+        let ends_at = Local::now() + Duration::days(14);
+        let delegation = user_usecase
+            .delegate(
+                "test_id".to_string(),
+                "test_two_factor_code".to_string(),
+                Some(vec!["b".to_string()]),
+                None,
+                ends_at,
+            )
+            .await
+            .unwrap();
+        assert_eq!(delegation.delegate_id, "test_two_factor_id");
+        assert!(delegation.is_active(Local::now()));
+        for (to_code, patient_codes, expected) in [
+            // test_id is not in charge of "a" but sees it through delegation.
+            (
+                "test_two_factor_code",
+                Some(vec!["a".to_string()]),
+                MyError::NotFound(json!({"error":"not in charge of patient code=a."})),
+            ),
+            (
+                "test_two_factor_code",
+                Some(vec!["not_exist".to_string()]),
+                MyError::NotFound(json!({"error":"no record of code=not_exist."})),
+            ),
+            (
+                "test_deactivated_code",
+                None,
+                MyError::BadRequest(
+                    json!({"error":"user code=test_deactivated_code is deactivated."}),
+                ),
+            ),
+            (
+                "test_code",
+                None,
+                MyError::BadRequest(json!({"error":"can not delegate to yourself"})),
+            ),
+        ] {
+            let err = user_usecase
+                .delegate(
+                    "test_id".to_string(),
+                    to_code.to_string(),
+                    patient_codes,
+                    None,
+                    ends_at,
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(err, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_revoke_delegation() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let delegations = user_usecase
+            .fetch_delegations("test_id".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            delegations
+                .into_iter()
+                .map(|delegation| delegation.id)
+                .collect::<Vec<String>>(),
+            vec![
+                "test_delegation_id_1".to_string(),
+                "test_delegation_id_3".to_string()
+            ]
+        );
+
+        // both the delegator and the delegate can revoke.
+        for user_id in ["test_user_id_1", "test_id"] {
+            user_usecase
+                .revoke_delegation(user_id.to_string(), "test_delegation_id_1".to_string())
+                .await
+                .unwrap();
+        }
+        for (user_id, id, expected) in [
+            (
+                "test_id",
+                "test_delegation_id_3",
+                MyError::BadRequest(json!({"error":"delegation is already revoked"})),
+            ),
+            (
+                "test_id",
+                "test_delegation_id_2",
+                MyError::NotFound(json!({"error":"no delegation of id=test_delegation_id_2."})),
+            ),
+            (
+                "test_id",
+                "unknown_id",
+                MyError::NotFound(json!({"error":"no delegation of id=unknown_id."})),
+            ),
+        ] {
+            let err = user_usecase
+                .revoke_delegation(user_id.to_string(), id.to_string())
+                .await
+                .unwrap_err();
+            assert_eq!(err, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_acknowledge_emergency_access() {
+        let mock_user_repository = UserRepositoryMockImpl {};
+        let mock_patient_repository = PatientRepositoryMockImpl {};
+        let mock_doctor_in_charge_repository = DoctorInChargeRepositoryMockImpl {};
+        let mock_refresh_token_repository = RefreshTokenRepositoryMockImpl {};
+        let mock_login_attempt_repository = LoginAttemptRepositoryMockImpl {};
+        let mock_two_factor_repository = TwoFactorRepositoryMockImpl {};
+        let mock_password_history_repository = PasswordHistoryRepositoryMockImpl {};
+        let mock_session_repository = SessionRepositoryMockImpl {};
+        let user_usecase = UserUsecase {
+            user_repository: mock_user_repository,
+            patient_repository: mock_patient_repository,
+            doctor_in_charge_repository: mock_doctor_in_charge_repository,
+            refresh_token_repository: mock_refresh_token_repository,
+            login_attempt_repository: mock_login_attempt_repository,
+            two_factor_repository: mock_two_factor_repository,
+            password_history_repository: mock_password_history_repository,
+            session_repository: mock_session_repository,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+        };
+        let emergency_accesses = user_usecase.fetch_emergency_accesses(true).await.unwrap();
+        assert_eq!(emergency_accesses.len(), 1);
+        assert_eq!(emergency_accesses[0].id, "test_emergency_access_id_1");
+
         user_usecase
-            .assign(test_user_id, test_patient_code)
+            .acknowledge_emergency_access(
+                "test_id".to_string(),
+                "test_emergency_access_id_1".to_string(),
+            )
             .await
             .unwrap();
+        let err = user_usecase
+            .acknowledge_emergency_access(
+                "test_id".to_string(),
+                "test_emergency_access_id_2".to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::BadRequest(json!({"error":"emergency access is already acknowledged"}))
+        );
+        let err = user_usecase
+            .acknowledge_emergency_access("test_id".to_string(), "unknown_id".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MyError::NotFound(json!({"error":"no emergency access of id=unknown_id."}))
+        );
     }
 }
@@ -1 +1,2 @@
 pub const DATETIME_FMT: &str = "%Y-%m-%d %H:%M:%S%.f";
+pub const DATE_FMT: &str = "%Y-%m-%d";
@@ -20,6 +20,19 @@ pub enum MyError {
     UnprocessableEntity(JsonValue),
     #[error("Unauthorized")]
     Unauthorized(JsonValue),
+    #[error("Forbidden")]
+    Forbidden(JsonValue),
+    #[error("Conflict")]
+    Conflict(JsonValue),
+    #[error("Locked")]
+    AccountLocked(JsonValue),
+    #[error("Too Many Requests")]
+    TooManyRequests(JsonValue),
+    /// If-Match does not match current version. someone else has updated the record.
+    #[error("Precondition Failed")]
+    PreconditionFailed(JsonValue),
+    #[error("Precondition Required")]
+    PreconditionRequired(JsonValue),
 }
 
 impl ResponseError for MyError {
@@ -32,6 +45,14 @@ impl ResponseError for MyError {
             MyError::NotFound(ref msg) => HttpResponse::NotFound().json(msg),
             MyError::BadRequest(ref msg) => HttpResponse::BadRequest().json(msg),
             MyError::Unauthorized(ref msg) => HttpResponse::Unauthorized().json(msg),
+            MyError::Forbidden(ref msg) => HttpResponse::Forbidden().json(msg),
+            MyError::Conflict(ref msg) => HttpResponse::Conflict().json(msg),
+            MyError::AccountLocked(ref msg) => HttpResponse::build(StatusCode::LOCKED).json(msg),
+            MyError::TooManyRequests(ref msg) => HttpResponse::TooManyRequests().json(msg),
+            MyError::PreconditionFailed(ref msg) => HttpResponse::PreconditionFailed().json(msg),
+            MyError::PreconditionRequired(ref msg) => {
+                HttpResponse::PreconditionRequired().json(msg)
+            }
         }
     }
 
@@ -42,6 +63,12 @@ impl ResponseError for MyError {
             MyError::BadRequest(_) => StatusCode::BAD_REQUEST,
             MyError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
             MyError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            MyError::Forbidden(_) => StatusCode::FORBIDDEN,
+            MyError::Conflict(_) => StatusCode::CONFLICT,
+            MyError::AccountLocked(_) => StatusCode::LOCKED,
+            MyError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            MyError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            MyError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
         }
     }
 }
@@ -1,6 +1,96 @@
-pub use bcrypt::verify;
-use bcrypt::{hash, DEFAULT_COST};
+use crate::utils::errors::MyError;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use data_encoding::HEXLOWER;
+use log::error;
+use ring::constant_time::verify_slices_are_equal;
+use ring::digest::{digest, SHA256};
+use std::sync::OnceLock;
 
-pub fn hash_password(raw_password: &str) -> Result<String, bcrypt::BcryptError> {
-    hash(raw_password, DEFAULT_COST)
+const BCRYPT_PREFIX: &str = "$2";
+
+/// hash verified in place of the password of unknown user.
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+/// current algorithm and params. hashes made with others are upgraded on sign in.
+fn argon2() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::default())
+}
+
+fn hash(raw: &str) -> Result<String, MyError> {
+    let salt = SaltString::generate(&mut OsRng);
+    argon2()
+        .hash_password(raw.as_bytes(), &salt)
+        .map(|hashed| hashed.to_string())
+        .map_err(|err| {
+            error!("failed to hash: {}", err);
+            MyError::InternalServerError
+        })
+}
+
+/// hash with Argon2id. result is PHC string which keeps algorithm and params.
+pub fn hash_password(raw_password: &str) -> Result<String, MyError> {
+    hash(raw_password)
+}
+
+/// hash secret part of tokens handed to client. e.g. refresh token.
+pub fn hash_token(raw_token: &str) -> Result<String, MyError> {
+    hash(raw_token)
+}
+
+/// verify Argon2 hash, or bcrypt hash made before Argon2 was introduced.
+pub fn verify<P: AsRef<[u8]>>(raw: P, hashed: &str) -> Result<bool, MyError> {
+    if hashed.starts_with(BCRYPT_PREFIX) {
+        return Ok(bcrypt::verify(raw, hashed)?);
+    }
+    let hashed = PasswordHash::new(hashed).map_err(|err| {
+        error!("failed to parse hash: {}", err);
+        MyError::InternalServerError
+    })?;
+    Ok(argon2().verify_password(raw.as_ref(), &hashed).is_ok())
+}
+
+/// verify against dummy hash as long as verify of real password takes. result is thrown away,
+/// so that response time does not tell whether the user exists.
+pub fn verify_dummy<P: AsRef<[u8]>>(raw: P) -> Result<(), MyError> {
+    let hashed = match DUMMY_HASH.get() {
+        Some(hashed) => hashed,
+        None => {
+            let hashed = hash("dummy_password")?;
+            DUMMY_HASH.get_or_init(|| hashed)
+        }
+    };
+    verify(raw, hashed)?;
+    Ok(())
+}
+
+/// whether hash is made with outdated algorithm or params. e.g. bcrypt or weaker Argon2.
+pub fn needs_rehash(hashed: &str) -> bool {
+    let hashed = match PasswordHash::new(hashed) {
+        Ok(hashed) => hashed,
+        Err(_) => return true,
+    };
+    let current = Params::default();
+    match Params::try_from(&hashed) {
+        Ok(params) => {
+            hashed.algorithm != Algorithm::Argon2id.ident()
+                || hashed.version != Some(Version::V0x13.into())
+                || params.m_cost() < current.m_cost()
+                || params.t_cost() < current.t_cost()
+                || params.p_cost() < current.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+/// SHA-256 of secret verified on every request. e.g. api key.
+/// the secret is long random value, so slow hash like Argon2 is not needed.
+pub fn digest_token(raw_token: &str) -> String {
+    HEXLOWER.encode(digest(&SHA256, raw_token.as_bytes()).as_ref())
+}
+
+/// compare digest_token of raw_token in constant time.
+pub fn verify_digest(raw_token: &str, digested: &str) -> bool {
+    verify_slices_are_equal(digest_token(raw_token).as_bytes(), digested.as_bytes()).is_ok()
 }
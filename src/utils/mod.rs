@@ -2,5 +2,5 @@ pub mod datetime;
 pub mod db;
 pub mod errors;
 pub mod hash;
-pub mod password;
 pub mod state;
+pub mod totp;
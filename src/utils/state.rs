@@ -1,3 +1,7 @@
+use crate::domain::login_attempt::LockoutPolicy;
+use crate::domain::oidc::OidcConfig;
+use crate::domain::password_policy::PasswordPolicy;
+use crate::repository::notifier::NotifierConfig;
 use crate::utils;
 use crate::utils::errors::MyError;
 use sqlx::MySqlPool;
@@ -6,6 +10,11 @@ use sqlx::MySqlPool;
 #[derive(Clone)]
 pub struct AppState {
     pub sqlx_db: sqlx::Pool<sqlx::MySql>,
+    pub lockout_policy: LockoutPolicy,
+    pub notifier_config: NotifierConfig,
+    pub password_policy: PasswordPolicy,
+    /// None if oidc login is not configured.
+    pub oidc_config: Option<OidcConfig>,
 }
 
 impl AppState {
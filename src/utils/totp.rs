@@ -0,0 +1,57 @@
+use data_encoding::BASE32_NOPAD;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// RFC 6238 parameters. authenticator apps assume these.
+pub const TOTP_DIGITS: u32 = 6;
+pub const TOTP_PERIOD_SECONDS: i64 = 30;
+const SECRET_BYTES: usize = 20;
+/// accepted clock skew between server and authenticator app in steps.
+const ALLOWED_SKEW_STEPS: i64 = 1;
+
+/// random bytes from os.
+pub fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("os random is not available");
+    bytes
+}
+
+/// new secret shared with authenticator app. base32 without padding.
+pub fn generate_secret() -> String {
+    BASE32_NOPAD.encode(&random_bytes(SECRET_BYTES))
+}
+
+/// time step of unix time.
+pub fn time_step(unix_time: i64) -> i64 {
+    unix_time.div_euclid(TOTP_PERIOD_SECONDS)
+}
+
+/// code of the step. None if secret is not base32.
+pub fn totp_code(secret: &str, step: i64) -> Option<String> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &key);
+    let digest = hmac::sign(&key, &step.to_be_bytes());
+    let digest = digest.as_ref();
+    // dynamic truncation of RFC 4226
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    Some(format!(
+        "{:0width$}",
+        binary % 10_u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    ))
+}
+
+/// step the code matches within allowed skew. None if no step matches.
+pub fn find_step(secret: &str, code: &str, unix_time: i64) -> Option<i64> {
+    let current = time_step(unix_time);
+    (current - ALLOWED_SKEW_STEPS..=current + ALLOWED_SKEW_STEPS)
+        .find(|step| totp_code(secret, *step).as_deref() == Some(code))
+}